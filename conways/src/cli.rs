@@ -0,0 +1,111 @@
+use crate::rule::Automaton;
+
+pub const USAGE: &str = "usage: conways [--rule RULE]
+       conways diff A B [--generations N] [--headless] [--rule RULE]
+       conways predecessor TARGET [--save NAME] [--rule RULE]
+
+  --rule RULE      B3/S23 style rule, add V, H or T for von Neumann, hexagonal
+                   or triangular neighbours, or Larger than Life like
+                   R5,C0,M1,S34..58,B34..45,NM, WireWorld or the path
+                   to a Golly .rule file
+
+  diff A B         run the saved templates (or pulsar) A and B side by side,
+                   highlighting the cells that differ
+  --generations N  generations to compare with --headless, 100 by default
+  --headless       print the comparison instead of opening the TUI
+
+  predecessor T    search for a pattern that turns into the template (or
+                   pulsar) T in one generation, Life-like Moore rules only
+  --save NAME      save the parent that was found as a template";
+
+// Generations compared when none are given
+const GENERATIONS: u64 = 100;
+
+pub enum Command {
+    Play,
+    Diff {
+        left: String,
+        right: String,
+        generations: u64,
+        headless: bool,
+    },
+    Predecessor {
+        target: String,
+        // Template name to store a found parent under
+        save: Option<String>,
+    },
+}
+
+pub struct Args {
+    pub command: Command,
+    pub rule: Option<Automaton>,
+    pub help: bool,
+}
+
+pub fn parse(args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        command: Command::Play,
+        rule: None,
+        help: false,
+    };
+    let mut names = vec![];
+    let mut generations = None;
+    let mut headless = false;
+    let mut save = None;
+
+    let mut args = args.skip(1).peekable();
+    let subcommand = args
+        .next_if(|arg| arg == "diff" || arg == "predecessor")
+        .unwrap_or_default();
+    let (diffing, searching) = (subcommand == "diff", subcommand == "predecessor");
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rule" | "-r" => {
+                let rule = args.next().ok_or("--rule needs a rule")?;
+                parsed.rule = Some(Automaton::load(&rule)?);
+            }
+            "--help" | "-h" => parsed.help = true,
+            "--generations" | "-g" if diffing => {
+                let n = args.next().ok_or("--generations needs a number")?;
+                generations = Some(
+                    n.parse()
+                        .map_err(|_| format!("{} is not a number of generations", n))?,
+                );
+            }
+            "--headless" if diffing => headless = true,
+            "--save" | "-s" if searching => {
+                save = Some(args.next().ok_or("--save needs a template name")?);
+            }
+            name if !subcommand.is_empty() && !name.starts_with('-') => {
+                names.push(name.to_string())
+            }
+            other => return Err(format!("unknown argument {}", other)),
+        }
+    }
+    if parsed.help {
+        return Ok(parsed);
+    }
+
+    parsed.command = match subcommand.as_str() {
+        "diff" => {
+            let [left, right]: [String; 2] = names
+                .try_into()
+                .map_err(|_| "diff needs two templates to compare")?;
+            Command::Diff {
+                left,
+                right,
+                generations: generations.unwrap_or(GENERATIONS),
+                headless,
+            }
+        }
+        "predecessor" => {
+            let [target]: [String; 1] = names
+                .try_into()
+                .map_err(|_| "predecessor needs one template to search a parent for")?;
+            Command::Predecessor { target, save }
+        }
+        _ => Command::Play,
+    };
+    Ok(parsed)
+}
@@ -0,0 +1,151 @@
+use crate::keymap::{parse_key, Action, Keymap};
+use crate::rule::Automaton;
+use crate::theme::Theme;
+use crossterm::event::KeyCode;
+use std::fs;
+use std::time::Duration;
+
+// Settings read from conways.conf, e.g.
+//
+//   theme = ocean
+//   speed = 250
+//   rule = B2/S34H
+//
+//   [keys]
+//   clear = x, delete
+//   toggle_play = p
+pub struct Config {
+    pub theme: Option<String>,
+    // Milliseconds between two generations
+    pub speed: Option<Duration>,
+    pub rule: Option<Automaton>,
+    pub keymap: Keymap,
+    // Lines that could not be understood, shown in the status bar instead of failing
+    pub warnings: Vec<String>,
+}
+
+impl Config {
+    pub fn default() -> Self {
+        Self {
+            theme: None,
+            speed: None,
+            rule: None,
+            keymap: Keymap::default(),
+            warnings: vec![],
+        }
+    }
+
+    // CONWAYS_CONFIG points somewhere else, a missing file just means defaults
+    pub fn load() -> Self {
+        let path = std::env::var("CONWAYS_CONFIG").unwrap_or_else(|_| "conways.conf".to_string());
+        match fs::read_to_string(&path) {
+            Ok(content) => Self::parse(&content),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn parse(content: &str) -> Self {
+        let mut config = Self::default();
+        let mut in_keys = false;
+
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') {
+                in_keys = line == "[keys]";
+                if !in_keys {
+                    config.warn(number, "unknown section");
+                }
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                config.warn(number, "expected `name = value`");
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim());
+
+            if in_keys {
+                let codes: Option<Vec<KeyCode>> =
+                    value.split(',').map(|key| parse_key(key.trim())).collect();
+                match (Action::from_name(key), codes) {
+                    (Some(action), Some(codes)) => config.keymap.bind(&codes, action),
+                    (None, _) => config.warn(number, "unknown action"),
+                    (_, None) => config.warn(number, "unknown key"),
+                }
+                continue;
+            }
+
+            match key {
+                "theme" => match Theme::named(value) {
+                    Some(_) => config.theme = Some(value.to_string()),
+                    None => config.warn(number, "unknown theme"),
+                },
+                "speed" => match value.parse() {
+                    // No pause between generations would keep a core busy
+                    Ok(0) => config.warn(number, "speed must be at least 1 millisecond"),
+                    Ok(millis) => config.speed = Some(Duration::from_millis(millis)),
+                    Err(_) => config.warn(number, "speed must be milliseconds"),
+                },
+                "rule" => match Automaton::load(value) {
+                    Ok(rule) => config.rule = Some(rule),
+                    Err(err) => config.warn(number, &err),
+                },
+                _ => config.warn(number, "unknown setting"),
+            }
+        }
+        config
+    }
+
+    fn warn(&mut self, number: usize, message: &str) {
+        self.warnings
+            .push(format!("conways.conf line {}: {}", number + 1, message));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_settings_and_keys() {
+        let config = Config::parse(
+            "# comment\n\ntheme = ocean\nspeed = 250\nrule = B36/S23\n\n[keys]\nclear = x, delete\nquit = esc\n",
+        );
+        assert!(config.warnings.is_empty(), "{:?}", config.warnings);
+        assert_eq!(config.theme.as_deref(), Some("ocean"));
+        assert_eq!(config.speed, Some(Duration::from_millis(250)));
+        assert!(config.rule.is_some());
+        assert!(config.keymap.action(KeyCode::Char('x')) == Some(Action::Clear));
+        assert!(config.keymap.action(KeyCode::Delete) == Some(Action::Clear));
+        assert!(config.keymap.action(KeyCode::Esc) == Some(Action::Quit));
+        assert!(config.keymap.action(KeyCode::Char('q')).is_none());
+    }
+
+    #[test]
+    fn warns_about_what_it_doesnt_understand() {
+        let config = Config::parse(
+            "speed = 0\nspeed = fast\ncolour = red\nno equals\n[mouse]\n[keys]\nfly = f\nquit = ctrl-q, q\n",
+        );
+        assert_eq!(config.speed, None);
+        assert_eq!(
+            config.warnings,
+            vec![
+                "conways.conf line 1: speed must be at least 1 millisecond",
+                "conways.conf line 2: speed must be milliseconds",
+                "conways.conf line 3: unknown setting",
+                "conways.conf line 4: expected `name = value`",
+                "conways.conf line 5: unknown section",
+                "conways.conf line 7: unknown action",
+                "conways.conf line 8: unknown key",
+            ]
+        );
+        let config = Config::parse("theme = ocean\ntheme = neon\n");
+        assert_eq!(config.theme.as_deref(), Some("ocean"));
+        assert_eq!(config.warnings, vec!["conways.conf line 2: unknown theme"]);
+        // Nothing of a line with a mistake is used
+        assert!(config.keymap.action(KeyCode::Char('q')) == Some(Action::Quit));
+    }
+}
@@ -1,4 +1,6 @@
 use super::World;
+use crate::rule::{Automaton, Rule};
+use std::collections::HashMap;
 
 impl World {
     pub fn default() -> Self {
@@ -6,13 +8,16 @@ impl World {
             width: 0,
             height: 0,
             alive: vec![],
+            states: HashMap::new(),
+            ages: HashMap::new(),
+            trail: HashMap::new(),
+            generation: 0,
+            rule: Automaton::Life(Rule::conway()),
         }
     }
 
     pub fn pulsar() -> Self {
         Self {
-            width: 0,
-            height: 0,
             alive: vec![
                 (9, 18),
                 (9, 17),
@@ -63,6 +68,7 @@ impl World {
                 (13, 26),
                 (14, 26),
             ],
+            ..Self::default()
         }
     }
 }
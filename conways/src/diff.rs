@@ -0,0 +1,300 @@
+use crate::config::Config;
+use crate::events::{Event, Events};
+use crate::keymap::Action;
+use crate::rule::Automaton;
+use crate::terminal::TerminalGuard;
+use crate::theme::Theme;
+use crate::ui;
+use crate::world::{Cells, World};
+use crate::{Mode, MAX_TICK_RATE, MIN_TICK_RATE};
+use rusqlite::Connection;
+use std::collections::{HashSet, VecDeque};
+use std::io;
+use std::time::Duration;
+use tui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Direction, Layout},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+    Terminal,
+};
+
+// Generations a comparison can step back, older ones are forgotten
+const HISTORY_LENGTH: usize = 1000;
+
+// Two worlds stepped in lockstep, remembering the last generations so it can step back
+pub struct Comparison {
+    pub left: World,
+    pub right: World,
+    history: VecDeque<(Cells, Cells)>,
+    // First generation where the populations were not the same
+    pub diverged_at: Option<u64>,
+}
+
+impl Comparison {
+    pub fn new(left: World, right: World) -> Self {
+        let mut comparison = Self {
+            left,
+            right,
+            history: VecDeque::new(),
+            diverged_at: None,
+        };
+        comparison.check_divergence();
+        comparison
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.left.generation
+    }
+
+    pub fn step(&mut self) {
+        if self.history.len() == HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history
+            .push_back((self.left.cells(), self.right.cells()));
+        self.left.next_day();
+        self.right.next_day();
+        self.check_divergence();
+    }
+
+    // Returns false when already back at the start
+    pub fn step_back(&mut self) -> bool {
+        let Some((left, right)) = self.history.pop_back() else {
+            return false;
+        };
+        for (world, cells) in [(&mut self.left, left), (&mut self.right, right)] {
+            world.set_cells(cells);
+            world.ages.clear();
+            world.trail.clear();
+            world.generation -= 1;
+        }
+        true
+    }
+
+    pub fn differences(&self) -> HashSet<(u16, u16)> {
+        self.left.differences(&self.right)
+    }
+
+    fn check_divergence(&mut self) {
+        if self.diverged_at.is_none() && self.left.alive.len() != self.right.alive.len() {
+            self.diverged_at = Some(self.generation());
+        }
+    }
+}
+
+// Both seeds get the size of the larger one, so they run on the same board
+pub fn load(
+    conn: &Connection,
+    left: &str,
+    right: &str,
+    rule: Option<Automaton>,
+) -> Result<Comparison, Box<dyn std::error::Error>> {
+    let (mut left, mut right) = (
+        World::load_named(conn, left)?,
+        World::load_named(conn, right)?,
+    );
+    let (width, height) = (left.width.max(right.width), left.height.max(right.height));
+    for world in [&mut left, &mut right] {
+        world.width = width;
+        world.height = height;
+        if let Some(rule) = &rule {
+            world.rule = rule.clone();
+        }
+    }
+    Ok(Comparison::new(left, right))
+}
+
+pub fn run_headless(mut comparison: Comparison, generations: u64) {
+    println!("{:>6} {:>8} {:>8} {:>8}", "gen", "left", "right", "differ");
+    loop {
+        println!(
+            "{:>6} {:>8} {:>8} {:>8}",
+            comparison.generation(),
+            comparison.left.alive.len(),
+            comparison.right.alive.len(),
+            comparison.differences().len()
+        );
+        if comparison.generation() >= generations {
+            break;
+        }
+        comparison.step();
+    }
+
+    match comparison.diverged_at {
+        Some(generation) => println!("populations diverge at generation {}", generation),
+        None => println!("populations stay the same for {} generations", generations),
+    }
+}
+
+pub fn run_tui(
+    mut comparison: Comparison,
+    names: (&str, &str),
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Dropped in reverse order, so the input thread is joined before the terminal is restored
+    let _guard = TerminalGuard::enter()?;
+    let config = Config::load();
+    let events = Events::new(config.speed.unwrap_or(Duration::from_millis(500)));
+
+    let backend = CrosstermBackend::new(io::stdout());
+    let mut terminal = Terminal::new(backend)?;
+    terminal.clear()?;
+
+    let (theme, theme_warning) = Theme::pick(config.theme.as_deref());
+    let mut should_play = false;
+    let mut show_help = false;
+    // Last error, shown in the status bar until the next one replaces it
+    let mut status: Option<String> = config.warnings.first().cloned().or(theme_warning);
+
+    loop {
+        terminal.draw(|f| {
+            let rows = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(3), Constraint::Length(1)])
+                .split(f.size());
+            let panes = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .split(rows[0]);
+
+            let differences = comparison.differences();
+            for (world, name, area) in [
+                (&comparison.left, names.0, panes[0]),
+                (&comparison.right, names.1, panes[1]),
+            ] {
+                let grid = world.get_diff_grid(
+                    &Mode::Play,
+                    &theme,
+                    false,
+                    &differences,
+                    area.height.saturating_sub(2),
+                    area.width.saturating_sub(2),
+                );
+                let pane = Paragraph::new(grid)
+                    .block(Block::default().title(name).borders(Borders::ALL))
+                    .wrap(Wrap { trim: true });
+                f.render_widget(pane, area);
+            }
+
+            f.render_widget(
+                ui::diff_status_bar(
+                    &comparison,
+                    differences.len(),
+                    events.tick_rate(),
+                    status.as_deref(),
+                ),
+                rows[1],
+            );
+
+            if show_help {
+                let help = config.keymap.help();
+                let area = ui::centered(50, help.len() as u16 + 2, rows[0]);
+                f.render_widget(Clear, area);
+                f.render_widget(ui::help(&config.keymap), area);
+            }
+        })?;
+
+        let event = match events.next() {
+            Ok(event) => event,
+            Err(_) => break,
+        };
+
+        match event {
+            Event::KeyInput(_) if show_help => show_help = false,
+            Event::KeyInput(key) => match config.keymap.action(key.code) {
+                Some(Action::Quit) => break,
+                Some(Action::TogglePlay) | Some(Action::Play) => should_play = !should_play,
+                Some(Action::Faster) => {
+                    events.set_tick_rate((events.tick_rate() / 2).max(MIN_TICK_RATE))
+                }
+                Some(Action::Slower) => {
+                    events.set_tick_rate((events.tick_rate() * 2).min(MAX_TICK_RATE))
+                }
+                // Stepping by hand pauses, so the generation stays put while looking at it
+                Some(Action::Step) => {
+                    should_play = false;
+                    comparison.step();
+                }
+                Some(Action::StepBack) => {
+                    should_play = false;
+                    comparison.step_back();
+                }
+                Some(Action::Help) => show_help = true,
+                _ => {}
+            },
+            Event::Tick => {
+                if should_play {
+                    comparison.step();
+                }
+            }
+            Event::LeftClick(_) => {}
+            Event::Failed(err) => status = Some(format!("Lost terminal input: {}", err)),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world(alive: Vec<(u16, u16)>) -> World {
+        World {
+            alive,
+            ..World::default().width(10).height(10)
+        }
+    }
+
+    #[test]
+    fn reports_first_divergence_and_steps_back() {
+        // A blinker has three cells and a block four, so they differ from the start
+        let blinker = world(vec![(4, 3), (4, 4), (4, 5)]);
+        let block = world(vec![(4, 4), (4, 5), (5, 4), (5, 5)]);
+        let mut comparison = Comparison::new(blinker, block);
+        assert_eq!(comparison.diverged_at, Some(0));
+
+        let start = comparison.left.cells();
+        comparison.step();
+        assert_eq!(comparison.generation(), 1);
+        assert!(comparison.step_back());
+        assert_eq!(comparison.generation(), 0);
+        assert_eq!(comparison.left.cells(), start);
+        assert!(!comparison.step_back());
+    }
+
+    #[test]
+    fn forgets_the_oldest_generations() {
+        let seed = vec![(4, 3), (4, 4), (4, 5)];
+        let mut comparison = Comparison::new(world(seed.clone()), world(seed));
+        for _ in 0..HISTORY_LENGTH + 5 {
+            comparison.step();
+        }
+        assert_eq!(comparison.history.len(), HISTORY_LENGTH);
+        while comparison.step_back() {}
+        assert_eq!(comparison.generation(), 5);
+    }
+
+    #[test]
+    fn identical_seeds_never_diverge() {
+        let seed = vec![(4, 3), (4, 4), (4, 5)];
+        let mut comparison = Comparison::new(world(seed.clone()), world(seed));
+        for _ in 0..10 {
+            comparison.step();
+            assert!(comparison.differences().is_empty());
+        }
+        assert_eq!(comparison.diverged_at, None);
+    }
+
+    #[test]
+    fn differences_show_where_seeds_split() {
+        let mut comparison = Comparison::new(
+            world(vec![(4, 3), (4, 4), (4, 5)]),
+            world(vec![(4, 3), (4, 4), (4, 5), (0, 0)]),
+        );
+        assert_eq!(comparison.differences(), HashSet::from([(0, 0)]));
+        assert_eq!(comparison.diverged_at, Some(0));
+        comparison.step();
+        // The lone cell dies, after which both blinkers are the same
+        assert!(comparison.differences().is_empty());
+    }
+}
@@ -0,0 +1,106 @@
+use crossterm::event::{self, Event as CEvent, KeyEvent, MouseButton, MouseEvent, MouseEventKind};
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// Longest the input thread waits before it looks at the stop flag again
+const POLL: Duration = Duration::from_millis(50);
+
+pub enum Event<Key, Pos> {
+    KeyInput(Key),
+    LeftClick(Pos),
+    Tick,
+    // The input thread hit an error and stopped
+    Failed(io::Error),
+}
+
+// Reads terminal input on its own thread and sends it together with ticks to the main loop
+pub struct Events {
+    rx: Receiver<Event<KeyEvent, (u16, u16)>>,
+    stop: Arc<AtomicBool>,
+    // Milliseconds between two ticks, can be changed while running
+    tick_rate: Arc<AtomicU64>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Events {
+    pub fn new(tick_rate: Duration) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let should_stop = Arc::clone(&stop);
+        let tick_rate = Arc::new(AtomicU64::new(tick_rate.as_millis() as u64));
+        let current_rate = Arc::clone(&tick_rate);
+
+        let handle = thread::spawn(move || {
+            let mut last_tick = Instant::now();
+            while !should_stop.load(Ordering::Relaxed) {
+                let tick_rate = Duration::from_millis(current_rate.load(Ordering::Relaxed));
+                let timeout = tick_rate
+                    .checked_sub(last_tick.elapsed())
+                    .unwrap_or_else(|| Duration::from_secs(0))
+                    .min(POLL);
+
+                let event = match event::poll(timeout) {
+                    Ok(true) => match event::read() {
+                        Ok(CEvent::Key(key)) => Some(Event::KeyInput(key)),
+                        Ok(CEvent::Mouse(MouseEvent {
+                            kind: MouseEventKind::Down(MouseButton::Left),
+                            column,
+                            row,
+                            ..
+                        })) => Some(Event::LeftClick((row, column))),
+                        Ok(_) => None,
+                        Err(err) => Some(Event::Failed(err)),
+                    },
+                    Ok(false) => None,
+                    Err(err) => Some(Event::Failed(err)),
+                };
+
+                if let Some(event) = event {
+                    let failed = matches!(event, Event::Failed(_));
+                    // The receiver is gone, so nobody is listening anymore
+                    if tx.send(event).is_err() || failed {
+                        break;
+                    }
+                }
+
+                if last_tick.elapsed() >= tick_rate && tx.send(Event::Tick).is_ok() {
+                    last_tick = Instant::now();
+                }
+            }
+        });
+
+        Self {
+            rx,
+            stop,
+            tick_rate,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn tick_rate(&self) -> Duration {
+        Duration::from_millis(self.tick_rate.load(Ordering::Relaxed))
+    }
+
+    pub fn set_tick_rate(&self, tick_rate: Duration) {
+        self.tick_rate
+            .store(tick_rate.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub fn next(&self) -> Result<Event<KeyEvent, (u16, u16)>, RecvError> {
+        self.rx.recv()
+    }
+}
+
+impl Drop for Events {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // The thread wakes up at the latest after POLL and sees the flag
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
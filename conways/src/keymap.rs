@@ -0,0 +1,208 @@
+use crossterm::event::KeyCode;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+    Quit,
+    TogglePlay,
+    Insert,
+    Save,
+    Load,
+    Play,
+    Clear,
+    Pulsar,
+    CycleTheme,
+    ToggleHeatmap,
+    Faster,
+    Slower,
+    Step,
+    StepBack,
+    Help,
+}
+
+// Every action with its config name, default key and help text.
+// Both the default keymap and the help overlay are generated from this table.
+const ACTIONS: [(Action, &str, &str, &str); 15] = [
+    (Action::Quit, "quit", "q", "Quit"),
+    (
+        Action::TogglePlay,
+        "toggle_play",
+        "space",
+        "Pause or resume",
+    ),
+    (Action::Insert, "insert", "i", "Stop and edit the world"),
+    (Action::Save, "save", "s", "Save the world as a template"),
+    (Action::Load, "load", "l", "List saved templates"),
+    (Action::Play, "play", "enter", "Start playing"),
+    (Action::Clear, "clear", "delete", "Remove all cells"),
+    (Action::Pulsar, "pulsar", "1", "Play the pulsar example"),
+    (
+        Action::CycleTheme,
+        "cycle_theme",
+        "t",
+        "Switch to the next theme",
+    ),
+    (
+        Action::ToggleHeatmap,
+        "heatmap",
+        "a",
+        "Colour cells by their age",
+    ),
+    (Action::Faster, "faster", "+", "Speed up"),
+    (Action::Slower, "slower", "-", "Slow down"),
+    (
+        Action::Step,
+        "step",
+        "right",
+        "Pause and show the next generation",
+    ),
+    (
+        Action::StepBack,
+        "step_back",
+        "left",
+        "Pause and go back a generation (comparing)",
+    ),
+    (Action::Help, "help", "?", "Show or hide this help"),
+];
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Self> {
+        ACTIONS
+            .iter()
+            .find(|(_, n, _, _)| *n == name)
+            .map(|(action, _, _, _)| *action)
+    }
+}
+
+pub struct Keymap {
+    bindings: Vec<(KeyCode, Action)>,
+}
+
+impl Keymap {
+    pub fn default() -> Self {
+        Self {
+            bindings: ACTIONS
+                .iter()
+                .filter_map(|(action, _, key, _)| Some((parse_key(key)?, *action)))
+                .collect(),
+        }
+    }
+
+    pub fn action(&self, code: KeyCode) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(key, _)| *key == code)
+            .map(|(_, action)| *action)
+    }
+
+    // Moves the action onto the given keys, whatever was bound to them before loses them
+    pub fn bind(&mut self, codes: &[KeyCode], action: Action) {
+        self.bindings
+            .retain(|(key, a)| !codes.contains(key) && *a != action);
+        self.bindings
+            .extend(codes.iter().map(|code| (*code, action)));
+    }
+
+    // Key and description of every action in table order, unbound actions show up empty
+    pub fn help(&self) -> Vec<(String, &'static str)> {
+        ACTIONS
+            .iter()
+            .map(|(action, _, _, description)| {
+                let keys = self
+                    .bindings
+                    .iter()
+                    .filter(|(_, a)| a == action)
+                    .map(|(key, _)| key_name(*key))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                (keys, *description)
+            })
+            .collect()
+    }
+}
+
+pub fn parse_key(name: &str) -> Option<KeyCode> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(KeyCode::Char(c));
+    }
+
+    let code = match name.to_lowercase().as_str() {
+        "space" => KeyCode::Char(' '),
+        "enter" => KeyCode::Enter,
+        "delete" | "del" => KeyCode::Delete,
+        "backspace" => KeyCode::Backspace,
+        "esc" => KeyCode::Esc,
+        "tab" => KeyCode::Tab,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        f if f.starts_with('f') => KeyCode::F(f[1..].parse().ok()?),
+        _ => return None,
+    };
+    Some(code)
+}
+
+pub fn key_name(code: KeyCode) -> String {
+    match code {
+        KeyCode::Char(' ') => "space".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::F(n) => format!("f{}", n),
+        KeyCode::Enter => "enter".to_string(),
+        KeyCode::Delete => "delete".to_string(),
+        KeyCode::Backspace => "backspace".to_string(),
+        KeyCode::Esc => "esc".to_string(),
+        KeyCode::Tab => "tab".to_string(),
+        KeyCode::Up => "up".to_string(),
+        KeyCode::Down => "down".to_string(),
+        KeyCode::Left => "left".to_string(),
+        KeyCode::Right => "right".to_string(),
+        KeyCode::Home => "home".to_string(),
+        KeyCode::End => "end".to_string(),
+        KeyCode::PageUp => "pageup".to_string(),
+        KeyCode::PageDown => "pagedown".to_string(),
+        other => format!("{:?}", other).to_lowercase(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_action_has_a_name_and_a_default_key() {
+        let keymap = Keymap::default();
+        for (action, name, key, _) in ACTIONS {
+            assert!(Action::from_name(name) == Some(action), "{}", name);
+            let code = parse_key(key).unwrap();
+            assert_eq!(key_name(code), key);
+            assert!(keymap.action(code) == Some(action), "{}", name);
+        }
+        assert_eq!(keymap.help().len(), ACTIONS.len());
+    }
+
+    #[test]
+    fn binding_moves_keys_between_actions() {
+        let mut keymap = Keymap::default();
+        keymap.bind(&[KeyCode::Char('q'), KeyCode::Esc], Action::Clear);
+        assert!(keymap.action(KeyCode::Char('q')) == Some(Action::Clear));
+        assert!(keymap.action(KeyCode::Delete).is_none());
+
+        let help = keymap.help();
+        assert_eq!(help[0], (String::new(), "Quit"));
+        assert_eq!(help[6], ("q, esc".to_string(), "Remove all cells"));
+    }
+
+    #[test]
+    fn names_keys_both_ways() {
+        assert!(parse_key("F5") == Some(KeyCode::F(5)));
+        assert!(parse_key("PageUp") == Some(KeyCode::PageUp));
+        assert!(parse_key("ctrl-q").is_none());
+        assert!(parse_key("fx").is_none());
+        assert_eq!(key_name(KeyCode::Char(' ')), "space");
+    }
+}
@@ -1,34 +1,42 @@
-use crossterm::{
-    event::{
-        self, DisableMouseCapture, EnableMouseCapture, Event as CEvent, KeyCode, MouseButton,
-        MouseEvent, MouseEventKind,
-    },
-    execute,
-    terminal::{disable_raw_mode, enable_raw_mode},
-};
+use crossterm::event::{Event as CEvent, KeyCode};
 use rusqlite::Connection;
 use std::io;
-use std::sync::mpsc;
-use std::thread;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use tui::{
     backend::CrosstermBackend,
-    widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
+    layout::{Constraint, Direction, Layout},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
     Terminal,
 };
 use tui_input::backend::crossterm::EventHandler;
 use tui_input::Input;
 
+mod cli;
+mod config;
 mod defaults;
+mod diff;
+mod events;
+mod keymap;
+mod predecessor;
+mod rule;
+mod table;
+mod terminal;
+mod theme;
+mod ui;
 mod world;
 
+use cli::Command;
+use config::Config;
+use events::{Event, Events};
+use keymap::Action;
+use rule::{Automaton, Rule};
+use terminal::TerminalGuard;
+use theme::Theme;
 use world::World;
 
-enum Event<Key, Pos> {
-    KeyInput(Key),
-    LeftClick(Pos),
-    Tick,
-}
+// Bounds for changing the speed while playing
+pub const MIN_TICK_RATE: Duration = Duration::from_millis(25);
+pub const MAX_TICK_RATE: Duration = Duration::from_millis(4000);
 
 // Keeps track of what the user wants to do
 #[derive(PartialEq)]
@@ -40,76 +48,94 @@ pub enum Mode {
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    enable_raw_mode().expect("can run in raw mode");
-
-    let (tx, rx) = mpsc::channel();
-    let tick_rate = Duration::from_millis(500);
-    thread::spawn(move || {
-        let mut last_tick = Instant::now();
-        loop {
-            let timeout = tick_rate
-                .checked_sub(last_tick.elapsed())
-                .unwrap_or_else(|| Duration::from_secs(0));
-
-            if event::poll(timeout).expect("poll works") {
-                match event::read().expect("can read events") {
-                    CEvent::Key(key) => tx.send(Event::KeyInput(key)).expect("can send keyevents"),
-                    CEvent::Mouse(MouseEvent {
-                        kind, column, row, ..
-                    }) => match kind {
-                        MouseEventKind::Down(MouseButton::Left) => tx
-                            .send(Event::LeftClick((row, column)))
-                            .expect("can send mouseevents"),
-                        _ => {}
-                    },
-                    _ => {}
-                };
-            }
+    let args = match cli::parse(std::env::args()) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if args.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
 
-            if last_tick.elapsed() >= tick_rate {
-                if let Ok(_) = tx.send(Event::Tick) {
-                    last_tick = Instant::now();
-                }
+    let conn = open_templates()?;
+
+    if let Command::Diff {
+        left,
+        right,
+        generations,
+        headless,
+    } = &args.command
+    {
+        let comparison = match diff::load(&conn, left, right, args.rule) {
+            Ok(comparison) => comparison,
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
             }
+        };
+        if *headless {
+            diff::run_headless(comparison, *generations);
+            return Ok(());
         }
-    });
-
-    let conn = Connection::open("templates.db")?;
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS templates (
-           id integer primary key,
-           name text not null,
-           width integer not null,
-           height integer not null,
-           alive text not null
-        )",
-        [],
-    )?;
+        return diff::run_tui(comparison, (left, right));
+    }
 
-    let mut stdout = io::stdout();
+    if let Command::Predecessor { target, save } = &args.command {
+        let found = match predecessor::run(&conn, target, args.rule, save.as_deref()) {
+            Ok(found) => found,
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        };
+        // Like grep, not finding anything is worth telling scripts about
+        std::process::exit(if found { 0 } else { 1 });
+    }
 
-    execute!(stdout, EnableMouseCapture)?;
+    // Dropped in reverse order, so the input thread is joined before the terminal is restored
+    let _guard = TerminalGuard::enter()?;
+    let config = Config::load();
+    let events = Events::new(config.speed.unwrap_or(Duration::from_millis(500)));
 
     let mut input: Input = "".into();
 
-    let backend = CrosstermBackend::new(stdout);
+    let backend = CrosstermBackend::new(io::stdout());
     let mut terminal = Terminal::new(backend)?;
     terminal.clear()?;
 
     let mut should_play = false;
     let mut mode = Mode::Insert;
+    let (mut theme, theme_warning) = Theme::pick(config.theme.as_deref());
+    let mut heatmap = false;
+    let mut show_help = false;
 
     let mut size = terminal.size()?;
-    let mut world = World::default().width(size.width).height(size.height);
-    let mut loaded_list: Vec<ListItem> = vec![];
+    let rule = args
+        .rule
+        .or(config.rule.clone())
+        .unwrap_or_else(|| Automaton::Life(Rule::conway()));
+    let mut world = World::default()
+        .width(size.width / rule.neighbourhood().cell_width())
+        .height(size.height)
+        .rule(rule);
+    let mut templates: Vec<(i64, String)> = vec![];
+    let mut selected = ListState::default();
+    // Last error, shown in the status bar until the next one replaces it
+    let mut status: Option<String> = config.warnings.first().cloned().or(theme_warning);
 
     loop {
-        if should_play == true {
-            world.next_day();
-        }
-
         terminal.draw(|f| {
-            size = f.size();
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(3), Constraint::Length(1)])
+                .split(f.size());
+            size = chunks[0];
+
+            let status_bar = ui::status_bar(&mode, &world, events.tick_rate(), status.as_deref());
+            f.render_widget(status_bar, chunks[1]);
 
             if world.alive.is_empty() && mode != Mode::Load {
                 mode = Mode::Insert;
@@ -118,25 +144,31 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
             match mode {
                 Mode::Load => {
-                    let load_list = List::new(loaded_list.clone()).block(
-                        Block::default()
-                            .title("Load Templates")
-                            .borders(Borders::ALL),
-                    );
-                    f.render_widget(load_list, size);
+                    let items = templates
+                        .iter()
+                        .map(|(_, name)| ListItem::new(name.clone()))
+                        .collect::<Vec<ListItem>>();
+                    let load_list = List::new(items)
+                        .block(
+                            Block::default()
+                                .title("Load Templates")
+                                .borders(Borders::ALL),
+                        )
+                        .highlight_symbol("> ");
+                    f.render_stateful_widget(load_list, size, &mut selected);
                 }
                 Mode::Insert | Mode::Play => {
-                    let world_grided = world.get_grid(&mode, size.height - 2, size.width - 2);
+                    let world_grided = world.get_grid(
+                        &mode,
+                        &theme,
+                        heatmap,
+                        size.height.saturating_sub(2),
+                        size.width.saturating_sub(2),
+                    );
                     let world_block = Paragraph::new(world_grided)
                         .block(
                             Block::default()
-                                .title({
-                                    match mode {
-                                        Mode::Insert => "Editor - Game of Life",
-                                        Mode::Play => "Conways - Game of Life",
-                                        _ => "",
-                                    }
-                                })
+                                .title(ui::title(&mode, &world, &theme))
                                 .borders(Borders::ALL),
                         )
                         .wrap(Wrap { trim: true });
@@ -149,87 +181,169 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     f.render_widget(input_block, size);
                 }
             }
+
+            if show_help {
+                let help = config.keymap.help();
+                let area = ui::centered(50, help.len() as u16 + 2, size);
+                f.render_widget(Clear, area);
+                f.render_widget(ui::help(&config.keymap), area);
+            }
         })?;
 
-        match rx.recv()? {
+        let event = match events.next() {
+            Ok(event) => event,
+            // The input thread is gone, there is no way to get more input
+            Err(_) => break,
+        };
+
+        match event {
+            // Any key closes the help again
+            Event::KeyInput(_) if show_help => show_help = false,
             Event::KeyInput(event) => match mode {
+                Mode::Load
+                    if matches!(
+                        event.code,
+                        KeyCode::Up | KeyCode::Down | KeyCode::Enter | KeyCode::Esc
+                    ) =>
+                {
+                    let current = selected.selected().unwrap_or(0);
+                    match event.code {
+                        KeyCode::Up => selected.select(Some(current.saturating_sub(1))),
+                        KeyCode::Down => selected
+                            .select(Some((current + 1).min(templates.len().saturating_sub(1)))),
+                        KeyCode::Enter => {
+                            if let Some((id, _)) = templates.get(current) {
+                                if let Err(err) = world.load_template(&conn, *id) {
+                                    status = Some(format!("Could not load template: {}", err));
+                                }
+                            }
+                            mode = Mode::Insert;
+                        }
+                        _ => mode = Mode::Insert,
+                    }
+                }
                 Mode::Save => match event.code {
                     KeyCode::Enter => {
-                        world.save_current_state(&conn, input.value().to_owned())?;
+                        if let Err(err) = world.save_current_state(&conn, input.value().to_owned())
+                        {
+                            status = Some(format!("Could not save template: {}", err));
+                        }
                         input.reset();
                         mode = Mode::Insert;
-                    },
+                    }
+                    KeyCode::Esc => {
+                        input.reset();
+                        mode = Mode::Insert;
+                    }
                     _ => {
                         input.handle_event(&CEvent::Key(event));
                     }
-                }
-                _ => match event.code {
-                    KeyCode::Char('q') => {
-                        disable_raw_mode()?;
-                        terminal.show_cursor()?;
-                        terminal.clear()?;
-                        break;
-                    }
-                    KeyCode::Char(' ') => {
+                },
+                _ => match config.keymap.action(event.code) {
+                    Some(Action::Quit) => break,
+                    Some(Action::TogglePlay) => {
                         should_play = !should_play;
                     }
-                    KeyCode::Char('i') => {
+                    Some(Action::Insert) => {
                         should_play = false;
                         mode = Mode::Insert;
                     }
-                    KeyCode::Char('s') => mode = Mode::Save,
-                    KeyCode::Char('l') => {
+                    Some(Action::Save) => mode = Mode::Save,
+                    Some(Action::Load) => {
                         should_play = false;
                         mode = Mode::Load;
-                        loaded_list = world
-                            .load_alive(&conn)
-                            .unwrap()
-                            .iter()
-                            .map(|i| ListItem::new(i.to_string()))
-                            .collect::<Vec<ListItem>>();
+                        templates = match world.load_alive(&conn) {
+                            Ok(templates) => templates,
+                            Err(err) => {
+                                status = Some(format!("Could not load templates: {}", err));
+                                vec![]
+                            }
+                        };
+                        selected.select(if templates.is_empty() { None } else { Some(0) });
                     }
-                    KeyCode::Enter => {
+                    Some(Action::Play) => {
                         should_play = true;
                         mode = Mode::Play;
                     }
-                    KeyCode::Delete => {
-                        world.alive.clear();
+                    Some(Action::Clear) => {
+                        world.clear();
                     }
-                    KeyCode::Char('1') => {
+                    Some(Action::CycleTheme) => theme = theme.next(),
+                    Some(Action::ToggleHeatmap) => heatmap = !heatmap,
+                    Some(Action::Pulsar) => {
                         should_play = true;
                         mode = Mode::Play;
+                        world.clear();
                         world.alive = World::pulsar().alive;
                     }
-                    _ => {}
+                    Some(Action::Faster) => {
+                        events.set_tick_rate((events.tick_rate() / 2).max(MIN_TICK_RATE))
+                    }
+                    Some(Action::Slower) => {
+                        events.set_tick_rate((events.tick_rate() * 2).min(MAX_TICK_RATE))
+                    }
+                    Some(Action::Step) => {
+                        should_play = false;
+                        world.next_day();
+                    }
+                    Some(Action::Help) => show_help = true,
+                    // Only comparisons remember earlier generations
+                    Some(Action::StepBack) | None => {}
                 },
             },
             Event::LeftClick(pos) => {
-                if pos.0 as i32 - 1 < 0
-                    || pos.0 as i32 - size.bottom() as i32 > 0
-                    || pos.1 as i32 + 1 < 0
-                    || pos.1 as i32 - size.right() as i32 > 0
-                {
+                // Clicks on the border or outside of it don't hit a cell
+                let (Some(row), Some(x)) = (
+                    pos.0.checked_sub(size.top() + 1),
+                    pos.1.checked_sub(size.left() + 1),
+                ) else {
+                    continue;
+                };
+                if pos.0 + 1 >= size.bottom() || pos.1 + 1 >= size.right() {
                     continue;
                 }
-                let position = (pos.0 - 1, pos.1 - 1);
-                if !world
-                    .alive
-                    .iter()
-                    .any(|x| x.0 == position.0 && x.1 == position.1)
-                {
-                    world.alive.push(position);
-                } else {
-                    world
-                        .alive
-                        .retain(|x| x.0 != position.0 || x.1 != position.1);
+                let Some(col) = world.rule.neighbourhood().cell_at(row, x) else {
+                    continue;
+                };
+                world.toggle((row, col));
+            }
+            Event::Tick => {
+                if should_play {
+                    world.next_day();
                 }
             }
-            Event::Tick => {}
+            Event::Failed(err) => status = Some(format!("Lost terminal input: {}", err)),
         }
     }
 
-    execute!(terminal.backend_mut(), DisableMouseCapture)?;
-    terminal.show_cursor()?;
-    terminal.clear()?;
+    Ok(())
+}
+
+fn open_templates() -> Result<Connection, Box<dyn std::error::Error>> {
+    let conn = Connection::open("templates.db")?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS templates (
+           id integer primary key,
+           name text not null,
+           width integer not null,
+           height integer not null,
+           alive text not null,
+           rule text
+        )",
+        [],
+    )?;
+    add_rule_column(&conn)?;
+    Ok(conn)
+}
+
+// Templates saved before rules were stored don't have the column yet
+fn add_rule_column(conn: &Connection) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare("SELECT name FROM pragma_table_info('templates')")?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    if !columns.iter().any(|column| column == "rule") {
+        conn.execute("ALTER TABLE templates ADD COLUMN rule text", [])?;
+    }
     Ok(())
 }
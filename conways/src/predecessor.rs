@@ -0,0 +1,287 @@
+use crate::rule::{Automaton, Neighbourhood, Rule};
+use crate::world::World;
+use rusqlite::Connection;
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
+use varisat::{ExtendFormula, Lit, Solver, Var};
+
+// Where a parent was looked for and what was found there
+pub struct Search {
+    // One cell around the target's bounding box, cut off at the edges of the world
+    pub rows: RangeInclusive<u16>,
+    pub cols: RangeInclusive<u16>,
+    // None proves that no parent fits into the box
+    pub parent: Option<World>,
+}
+
+// Every cell of the parent inside the search box is a variable, everything outside is dead.
+// For each cell whose next state depends on those variables, all combinations of the cell and
+// its neighbours that the rule would turn into the wrong state get ruled out by a clause.
+pub fn search(target: &World) -> Result<Search, String> {
+    let rule = match &target.rule {
+        Automaton::Life(rule) if rule.neighbourhood == Neighbourhood::Moore(1) => rule,
+        _ => {
+            return Err(
+                "predecessors can only be searched for Life-like rules with Moore neighbours"
+                    .to_string(),
+            )
+        }
+    };
+    // The dead cells around the box would all be born, so nothing finite can be a parent
+    if rule.next_state(false, 0) {
+        return Err("predecessors can't be searched for rules with B0".to_string());
+    }
+
+    let alive: HashSet<(u16, u16)> = target
+        .alive
+        .iter()
+        .copied()
+        .filter(|&(row, col)| row < target.height && col < target.width)
+        .collect();
+    let parent = World::default()
+        .width(target.width)
+        .height(target.height)
+        .rule(target.rule.clone());
+    let (Some(rows), Some(cols)) = (
+        around(alive.iter().map(|pos| pos.0), 1, target.height),
+        around(alive.iter().map(|pos| pos.1), 1, target.width),
+    ) else {
+        // Without B0 an empty world stays empty, so the empty box holds its parent
+        return Ok(Search {
+            rows: RangeInclusive::new(1, 0),
+            cols: RangeInclusive::new(1, 0),
+            parent: Some(parent),
+        });
+    };
+
+    let mut solver = Solver::new();
+    let mut cells: HashMap<(u16, u16), Var> = HashMap::new();
+    for row in rows.clone() {
+        for col in cols.clone() {
+            cells.insert((row, col), solver.new_var());
+        }
+    }
+
+    let checked_rows = widen(&rows, target.height);
+    let checked_cols = widen(&cols, target.width);
+    for row in checked_rows {
+        for col in checked_cols.clone() {
+            rule_out_wrong(
+                &mut solver,
+                rule,
+                &cells,
+                (row, col),
+                alive.contains(&(row, col)),
+            );
+        }
+    }
+
+    let found = solver.solve().map_err(|err| err.to_string())?;
+    let parent = found.then(|| {
+        let positions: HashMap<Var, (u16, u16)> =
+            cells.iter().map(|(pos, var)| (*var, *pos)).collect();
+        let model = solver.model().unwrap_or_default();
+        World {
+            alive: model
+                .iter()
+                .filter(|lit| lit.is_positive())
+                .filter_map(|lit| positions.get(&lit.var()).copied())
+                .collect(),
+            ..parent
+        }
+    });
+
+    Ok(Search { rows, cols, parent })
+}
+
+fn rule_out_wrong(
+    solver: &mut Solver,
+    rule: &Rule,
+    cells: &HashMap<(u16, u16), Var>,
+    (row, col): (u16, u16),
+    expected: bool,
+) {
+    let centre = cells.get(&(row, col)).copied();
+    let neighbours = Neighbourhood::Moore(1)
+        .offsets(row, col)
+        .into_iter()
+        .filter_map(|(dr, dc)| {
+            let pos = (
+                u16::try_from(row as i32 + dr).ok()?,
+                u16::try_from(col as i32 + dc).ok()?,
+            );
+            cells.get(&pos).copied()
+        });
+    let inputs: Vec<Var> = centre.into_iter().chain(neighbours).collect();
+    let first_neighbour = centre.is_some() as usize;
+
+    for assignment in 0u32..1 << inputs.len() {
+        let set = |i: usize| assignment >> i & 1 == 1;
+        let alive = centre.is_some() && set(0);
+        let count = (first_neighbour..inputs.len()).filter(|&i| set(i)).count() as u16;
+        if rule.next_state(alive, count) != expected {
+            let clause: Vec<Lit> = inputs
+                .iter()
+                .enumerate()
+                .map(|(i, var)| var.lit(!set(i)))
+                .collect();
+            solver.add_clause(&clause);
+        }
+    }
+}
+
+// Smallest range covering all values, widened by `by` but kept inside 0..limit
+fn around(values: impl Iterator<Item = u16>, by: u16, limit: u16) -> Option<RangeInclusive<u16>> {
+    let (min, max) = values.fold(None, |range: Option<(u16, u16)>, value| match range {
+        Some((min, max)) => Some((min.min(value), max.max(value))),
+        None => Some((value, value)),
+    })?;
+    Some(min.saturating_sub(by)..=(max + by).min(limit - 1))
+}
+
+fn widen(range: &RangeInclusive<u16>, limit: u16) -> RangeInclusive<u16> {
+    range.start().saturating_sub(1)..=(range.end() + 1).min(limit - 1)
+}
+
+// Prints the parent of the template, returns whether there was one
+pub fn run(
+    conn: &Connection,
+    name: &str,
+    rule: Option<Automaton>,
+    save: Option<&str>,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut target = World::load_named(conn, name)?;
+    if let Some(rule) = rule {
+        target.rule = rule;
+    }
+
+    let search = search(&target)?;
+    let size = (search.rows.clone().count(), search.cols.clone().count());
+    let Some(parent) = &search.parent else {
+        println!(
+            "{} has no parent within the {}x{} box around it, it's a Garden of Eden there",
+            name, size.1, size.0
+        );
+        return Ok(false);
+    };
+
+    println!(
+        "parent of {} with population {} (rows {}..={}, cols {}..={}):",
+        name,
+        parent.alive.len(),
+        search.rows.start(),
+        search.rows.end(),
+        search.cols.start(),
+        search.cols.end()
+    );
+    println!("{}", to_text(&search));
+    if let Some(save) = save {
+        parent.save_current_state(conn, save.to_string())?;
+        println!("saved as {}", save);
+    }
+    Ok(true)
+}
+
+// The search box as rows of O for alive and . for dead cells
+pub fn to_text(search: &Search) -> String {
+    let alive: HashSet<&(u16, u16)> = match &search.parent {
+        Some(parent) => parent.alive.iter().collect(),
+        None => HashSet::new(),
+    };
+    search
+        .rows
+        .clone()
+        .map(|row| {
+            search
+                .cols
+                .clone()
+                .map(|col| {
+                    if alive.contains(&(row, col)) {
+                        'O'
+                    } else {
+                        '.'
+                    }
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(width: u16, height: u16, alive: Vec<(u16, u16)>, rule: &str) -> World {
+        World {
+            alive,
+            ..World::default()
+                .width(width)
+                .height(height)
+                .rule(Automaton::load(rule).unwrap())
+        }
+    }
+
+    fn sorted(mut alive: Vec<(u16, u16)>) -> Vec<(u16, u16)> {
+        alive.sort();
+        alive
+    }
+
+    #[test]
+    fn found_parent_steps_into_the_target() {
+        // A glider, which has plenty of parents
+        let glider = target(
+            12,
+            12,
+            vec![(4, 5), (5, 6), (6, 4), (6, 5), (6, 6)],
+            "B3/S23",
+        );
+        let mut parent = search(&glider).unwrap().parent.unwrap();
+        parent.next_day();
+        assert_eq!(sorted(parent.alive), sorted(glider.alive));
+    }
+
+    #[test]
+    fn proves_there_is_no_parent_in_the_box() {
+        // Squeezed into a 3x3 world a full block can't come from anything
+        let full = target(
+            3,
+            3,
+            (0..3)
+                .flat_map(|row| (0..3).map(move |col| (row, col)))
+                .collect(),
+            "B3/S23",
+        );
+        let search = search(&full).unwrap();
+        assert!(search.parent.is_none());
+        assert_eq!((search.rows, search.cols), (0..=2, 0..=2));
+
+        // Which trying all 512 candidates agrees with
+        for bits in 0..1 << 9 {
+            let mut parent = target(
+                3,
+                3,
+                (0..9)
+                    .filter(|i| bits >> i & 1 == 1)
+                    .map(|i| (i / 3, i % 3))
+                    .collect(),
+                "B3/S23",
+            );
+            parent.next_day();
+            assert_ne!(parent.alive.len(), 9);
+        }
+    }
+
+    #[test]
+    fn only_life_like_moore_rules_are_searched() {
+        assert!(search(&target(12, 12, vec![(4, 4)], "B2/S34H")).is_err());
+        assert!(search(&target(12, 12, vec![(4, 4)], "WireWorld")).is_err());
+        assert!(search(&target(12, 12, vec![(4, 4)], "B03/S23")).is_err());
+    }
+
+    #[test]
+    fn empty_world_is_its_own_parent() {
+        let search = search(&target(12, 12, vec![], "B3/S23")).unwrap();
+        assert!(search.parent.unwrap().alive.is_empty());
+    }
+}
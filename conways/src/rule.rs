@@ -0,0 +1,469 @@
+use crate::table::RuleTable;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Neighbourhood {
+    // All cells within the range in both directions, range 1 is the classic 8 neighbours
+    Moore(u16),
+    // All cells within the range in manhattan distance
+    VonNeumann(u16),
+    // Six neighbours on a grid where every odd row is shifted right by half a cell
+    Hexagonal,
+    // Twelve neighbours sharing a corner, cells point up when row + col is even
+    Triangular,
+}
+
+impl Neighbourhood {
+    // How far a neighbour can be away from the cell in any direction
+    pub fn reach(&self) -> u16 {
+        match self {
+            Neighbourhood::Moore(range) | Neighbourhood::VonNeumann(range) => *range,
+            Neighbourhood::Hexagonal => 1,
+            Neighbourhood::Triangular => 2,
+        }
+    }
+
+    // (row, col) offsets of the neighbours of the given cell, hex and triangular grids
+    // depend on where the cell sits
+    pub fn offsets(&self, row: u16, col: u16) -> Vec<(i32, i32)> {
+        match self {
+            Neighbourhood::Moore(range) => {
+                let r = *range as i32;
+                square(r).filter(|&(dr, dc)| dr != 0 || dc != 0).collect()
+            }
+            Neighbourhood::VonNeumann(range) => {
+                let r = *range as i32;
+                square(r)
+                    .filter(|&(dr, dc)| (dr != 0 || dc != 0) && dr.abs() + dc.abs() <= r)
+                    .collect()
+            }
+            Neighbourhood::Hexagonal => {
+                // Odd rows are shifted right, so their diagonal neighbours lean right as well
+                let shift = (row % 2) as i32;
+                vec![
+                    (-1, shift - 1),
+                    (-1, shift),
+                    (0, -1),
+                    (0, 1),
+                    (1, shift - 1),
+                    (1, shift),
+                ]
+            }
+            Neighbourhood::Triangular => {
+                // The row a triangle points away from shares its long edge, so it reaches further
+                let (wide, narrow) = if (row + col).is_multiple_of(2) {
+                    (1, -1)
+                } else {
+                    (-1, 1)
+                };
+                let mut offsets = vec![];
+                offsets.extend((-1..=1).map(|dc| (narrow, dc)));
+                offsets.extend([(0, -2), (0, -1), (0, 1), (0, 2)]);
+                offsets.extend((-2..=2).map(|dc| (wide, dc)));
+                offsets
+            }
+        }
+    }
+
+    // Terminal columns per cell, hex cells get a gap so odd rows can sit in between
+    pub fn cell_width(&self) -> u16 {
+        match self {
+            Neighbourhood::Hexagonal => 2,
+            _ => 1,
+        }
+    }
+
+    pub fn row_offset(&self, row: u16) -> u16 {
+        match self {
+            Neighbourhood::Hexagonal => row % 2,
+            _ => 0,
+        }
+    }
+
+    // Column of the cell drawn at terminal column `x` of the grid
+    pub fn cell_at(&self, row: u16, x: u16) -> Option<u16> {
+        Some(x.checked_sub(self.row_offset(row))? / self.cell_width())
+    }
+
+    fn max_neighbours(&self) -> u16 {
+        self.offsets(0, 0).len() as u16
+    }
+}
+
+fn square(r: i32) -> impl Iterator<Item = (i32, i32)> {
+    (-r..=r).flat_map(move |dr| (-r..=r).map(move |dc| (dr, dc)))
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Rule {
+    // Neighbour counts that bring a dead cell to life
+    pub birth: Vec<u16>,
+    // Neighbour counts that keep an alive cell alive
+    pub survival: Vec<u16>,
+    pub neighbourhood: Neighbourhood,
+    // Larger than Life can count the cell itself as one of its neighbours
+    pub count_self: bool,
+}
+
+impl Rule {
+    pub fn conway() -> Self {
+        Self {
+            birth: vec![3],
+            survival: vec![2, 3],
+            neighbourhood: Neighbourhood::Moore(1),
+            count_self: false,
+        }
+    }
+
+    pub fn next_state(&self, alive: bool, neighbours: u16) -> bool {
+        let count = if alive && self.count_self {
+            neighbours + 1
+        } else {
+            neighbours
+        };
+        if alive {
+            self.survival.contains(&count)
+        } else {
+            self.birth.contains(&count)
+        }
+    }
+
+    // B3/S23 style, with a V, H or T suffix for von Neumann, hexagonal or triangular
+    fn parse_life(s: &str) -> Result<Self, String> {
+        let (counts, neighbourhood) = match s.chars().last() {
+            Some('V' | 'v') => (&s[..s.len() - 1], Neighbourhood::VonNeumann(1)),
+            Some('H' | 'h') => (&s[..s.len() - 1], Neighbourhood::Hexagonal),
+            Some('T' | 't') => (&s[..s.len() - 1], Neighbourhood::Triangular),
+            _ => (s, Neighbourhood::Moore(1)),
+        };
+
+        let (birth, survival) = counts
+            .split_once('/')
+            .ok_or_else(|| format!("{} is missing the / between birth and survival", s))?;
+        let birth = birth
+            .strip_prefix(['B', 'b'])
+            .ok_or_else(|| format!("{} has to start with B", s))?;
+        let survival = survival
+            .strip_prefix(['S', 's'])
+            .ok_or_else(|| format!("{} needs an S after the /", s))?;
+
+        let digits = |part: &str| -> Result<Vec<u16>, String> {
+            part.chars()
+                .map(|c| {
+                    c.to_digit(10)
+                        .map(|d| d as u16)
+                        .ok_or_else(|| format!("{} is not a neighbour count", c))
+                })
+                .collect()
+        };
+
+        let rule = Self {
+            birth: digits(birth)?,
+            survival: digits(survival)?,
+            neighbourhood,
+            count_self: false,
+        };
+        rule.check_counts()?;
+        Ok(rule)
+    }
+
+    // Larger than Life in Golly's notation, e.g. R5,C0,M1,S34..58,B34..45,NM
+    fn parse_larger_than_life(s: &str) -> Result<Self, String> {
+        let mut range = None;
+        let mut count_self = false;
+        let mut birth = None;
+        let mut survival = None;
+        let mut neighbourhood = 'M';
+
+        let counts = |value: &str| -> Result<Vec<u16>, String> {
+            let (from, to) = value.split_once("..").unwrap_or((value, value));
+            let from: u16 = from
+                .parse()
+                .map_err(|_| format!("{} is not a count", from))?;
+            let to: u16 = to.parse().map_err(|_| format!("{} is not a count", to))?;
+            Ok((from..=to).collect())
+        };
+
+        for part in s.split(',') {
+            let mut chars = part.chars();
+            let key = chars.next().map(|c| c.to_ascii_uppercase());
+            let value = chars.as_str();
+            match key {
+                Some('R') => {
+                    range = Some(
+                        value
+                            .parse::<u16>()
+                            .map_err(|_| format!("{} is not a range", value))?,
+                    )
+                }
+                // Only two state automata are supported
+                Some('C') if matches!(value, "0" | "2") => {}
+                Some('C') => return Err(format!("{} states are not supported", value)),
+                Some('M') => {
+                    count_self = match value {
+                        "0" => false,
+                        "1" => true,
+                        _ => return Err(format!("M{} has to be M0 or M1", value)),
+                    }
+                }
+                Some('S') => survival = Some(counts(value)?),
+                Some('B') => birth = Some(counts(value)?),
+                Some('N') => {
+                    neighbourhood = value
+                        .chars()
+                        .next()
+                        .map(|c| c.to_ascii_uppercase())
+                        .ok_or("N needs M or N")?
+                }
+                _ => return Err(format!("unknown part {} in {}", part, s)),
+            }
+        }
+
+        let range = range.ok_or_else(|| format!("{} has no range", s))?;
+        if range == 0 {
+            return Err("the range has to be at least 1".to_string());
+        }
+        let rule = Self {
+            birth: birth.ok_or_else(|| format!("{} has no B", s))?,
+            survival: survival.ok_or_else(|| format!("{} has no S", s))?,
+            neighbourhood: match neighbourhood {
+                'M' => Neighbourhood::Moore(range),
+                'N' => Neighbourhood::VonNeumann(range),
+                other => return Err(format!("unknown neighbourhood N{}", other)),
+            },
+            count_self,
+        };
+        rule.check_counts()?;
+        Ok(rule)
+    }
+
+    fn check_counts(&self) -> Result<(), String> {
+        let max = self.neighbourhood.max_neighbours() + self.count_self as u16;
+        match self.birth.iter().chain(&self.survival).find(|&&n| n > max) {
+            Some(n) => Err(format!("a cell can't have {} neighbours here", n)),
+            None => Ok(()),
+        }
+    }
+
+    fn is_larger_than_life(&self) -> bool {
+        self.count_self
+            || (self.neighbourhood.reach() > 1 && self.neighbourhood != Neighbourhood::Triangular)
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.starts_with(['R', 'r']) {
+            Self::parse_larger_than_life(s)
+        } else {
+            Self::parse_life(s)
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let range = |counts: &[u16]| match (counts.iter().min(), counts.iter().max()) {
+            (Some(min), Some(max)) => format!("{}..{}", min, max),
+            _ => "0..0".to_string(),
+        };
+        let digits = |counts: &[u16]| counts.iter().map(|n| n.to_string()).collect::<String>();
+
+        if self.is_larger_than_life() {
+            let (r, n) = match self.neighbourhood {
+                Neighbourhood::VonNeumann(r) => (r, 'N'),
+                other => (other.reach(), 'M'),
+            };
+            return write!(
+                f,
+                "R{},C0,M{},S{},B{},N{}",
+                r,
+                self.count_self as u8,
+                range(&self.survival),
+                range(&self.birth),
+                n
+            );
+        }
+
+        let suffix = match self.neighbourhood {
+            Neighbourhood::VonNeumann(_) => "V",
+            Neighbourhood::Hexagonal => "H",
+            Neighbourhood::Triangular => "T",
+            Neighbourhood::Moore(_) => "",
+        };
+        write!(
+            f,
+            "B{}/S{}{}",
+            digits(&self.birth),
+            digits(&self.survival),
+            suffix
+        )
+    }
+}
+
+// Everything a world can run, either a Life-like rule or a transition table
+#[derive(Clone, Debug)]
+pub enum Automaton {
+    Life(Rule),
+    Table(RuleTable),
+}
+
+impl Automaton {
+    // A rule like B3/S23, the name of a built-in table or the path to a Golly .rule file
+    pub fn load(spec: &str) -> Result<Self, String> {
+        if spec.eq_ignore_ascii_case("wireworld") {
+            Ok(Automaton::Table(RuleTable::wireworld()))
+        } else if spec.ends_with(".rule") {
+            Ok(Automaton::Table(RuleTable::load(spec)?))
+        } else {
+            Ok(Automaton::Life(spec.parse()?))
+        }
+    }
+
+    // Templates keep the notation of Life-like rules and the whole file of tables,
+    // so they still load when the .rule file is gone
+    pub fn to_template(&self) -> String {
+        match self {
+            Automaton::Life(rule) => rule.to_string(),
+            Automaton::Table(table) => table.source.clone(),
+        }
+    }
+
+    pub fn from_template(stored: &str) -> Result<Self, String> {
+        if stored.trim_start().starts_with("@RULE") {
+            Ok(Automaton::Table(RuleTable::parse(stored)?))
+        } else {
+            Ok(Automaton::Life(stored.parse()?))
+        }
+    }
+
+    pub fn neighbourhood(&self) -> Neighbourhood {
+        match self {
+            Automaton::Life(rule) => rule.neighbourhood,
+            Automaton::Table(table) => table.neighbourhood,
+        }
+    }
+
+    pub fn states(&self) -> u16 {
+        match self {
+            Automaton::Life(_) => 2,
+            Automaton::Table(table) => table.states,
+        }
+    }
+}
+
+impl fmt::Display for Automaton {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Automaton::Life(rule) => write!(f, "{}", rule),
+            Automaton::Table(table) => write!(f, "{}", table.name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every neighbour has to see the cell as its neighbour too
+    fn assert_symmetric(n: Neighbourhood) {
+        for row in 3..7u16 {
+            for col in 3..7u16 {
+                for (dr, dc) in n.offsets(row, col) {
+                    let (r, c) = ((row as i32 + dr) as u16, (col as i32 + dc) as u16);
+                    assert!(
+                        n.offsets(r, c)
+                            .contains(&(row as i32 - r as i32, col as i32 - c as i32)),
+                        "{:?}: ({}, {}) is not a neighbour of ({}, {})",
+                        n,
+                        row,
+                        col,
+                        r,
+                        c
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn moore() {
+        assert_eq!(Neighbourhood::Moore(1).offsets(0, 0).len(), 8);
+        assert_eq!(Neighbourhood::Moore(2).offsets(0, 0).len(), 24);
+        assert_symmetric(Neighbourhood::Moore(3));
+    }
+
+    #[test]
+    fn von_neumann() {
+        let mut offsets = Neighbourhood::VonNeumann(1).offsets(0, 0);
+        offsets.sort();
+        assert_eq!(offsets, vec![(-1, 0), (0, -1), (0, 1), (1, 0)]);
+        assert_eq!(Neighbourhood::VonNeumann(2).offsets(0, 0).len(), 12);
+        assert_symmetric(Neighbourhood::VonNeumann(2));
+    }
+
+    #[test]
+    fn hexagonal() {
+        assert_eq!(Neighbourhood::Hexagonal.offsets(0, 0).len(), 6);
+        assert_eq!(Neighbourhood::Hexagonal.offsets(1, 0).len(), 6);
+        assert!(Neighbourhood::Hexagonal.offsets(0, 0).contains(&(1, -1)));
+        assert!(Neighbourhood::Hexagonal.offsets(1, 0).contains(&(1, 1)));
+        assert_symmetric(Neighbourhood::Hexagonal);
+    }
+
+    #[test]
+    fn triangular() {
+        assert_eq!(Neighbourhood::Triangular.offsets(0, 0).len(), 12);
+        assert_eq!(Neighbourhood::Triangular.offsets(0, 1).len(), 12);
+        assert_symmetric(Neighbourhood::Triangular);
+    }
+
+    #[test]
+    fn hex_cells_under_the_cursor() {
+        let hex = Neighbourhood::Hexagonal;
+        assert_eq!(hex.cell_at(0, 4), Some(2));
+        assert_eq!(hex.cell_at(1, 5), Some(2));
+        assert_eq!(hex.cell_at(1, 0), None);
+    }
+
+    #[test]
+    fn parses_life_notation() {
+        assert_eq!("B3/S23".parse::<Rule>(), Ok(Rule::conway()));
+        let hex: Rule = "b2/s34h".parse().unwrap();
+        assert_eq!(hex.neighbourhood, Neighbourhood::Hexagonal);
+        assert_eq!(hex.birth, vec![2]);
+        assert_eq!(hex.survival, vec![3, 4]);
+        assert!("B3S23".parse::<Rule>().is_err());
+        assert!("B5/S2V".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn parses_larger_than_life() {
+        let bugs: Rule = "R5,C0,M1,S34..58,B34..45,NM".parse().unwrap();
+        assert_eq!(bugs.neighbourhood, Neighbourhood::Moore(5));
+        assert!(bugs.count_self);
+        assert_eq!(bugs.survival, (34..=58).collect::<Vec<u16>>());
+        assert_eq!(bugs.birth, (34..=45).collect::<Vec<u16>>());
+        assert!("R2,C0,M0,S1..30,B1..2,NM".parse::<Rule>().is_err());
+        assert!("R5,C0,M2,S34..58,B34..45,NM".parse::<Rule>().is_err());
+        assert!("R5,C0,M,S34..58,B34..45,NM".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn displays_what_it_parses() {
+        for notation in [
+            "B3/S23",
+            "B2/S34H",
+            "B1/S13V",
+            "B2/S3T",
+            "R5,C0,M1,S34..58,B34..45,NM",
+            "R2,C0,M0,S3..5,B2..3,NN",
+        ] {
+            assert_eq!(notation.parse::<Rule>().unwrap().to_string(), notation);
+        }
+    }
+}
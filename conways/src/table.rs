@@ -0,0 +1,579 @@
+use crate::rule::Neighbourhood;
+use std::collections::HashMap;
+use std::fs;
+use tui::style::Color;
+
+pub const WIREWORLD: &str = "@RULE WireWorld
+
+Electrons travelling along wires, cells are either empty, electron heads,
+electron tails or conductors.
+
+@TABLE
+n_states:4
+neighborhood:Moore
+symmetries:permute
+
+var a={0,1,2,3}
+var b={0,1,2,3}
+var c={0,1,2,3}
+var d={0,1,2,3}
+var e={0,1,2,3}
+var f={0,1,2,3}
+var g={0,1,2,3}
+var h={0,1,2,3}
+var i={0,2,3}
+var j={0,2,3}
+var k={0,2,3}
+var l={0,2,3}
+var m={0,2,3}
+var n={0,2,3}
+var o={0,2,3}
+
+# heads turn into tails and tails into conductors
+1,a,b,c,d,e,f,g,h,2
+2,a,b,c,d,e,f,g,h,3
+# conductors next to one or two heads turn into heads
+3,1,i,j,k,l,m,n,o,1
+3,1,1,j,k,l,m,n,o,1
+
+@COLORS
+0 48 48 48
+1 0 128 255
+2 255 255 255
+3 255 128 0
+
+@NAMES
+1 electron head
+2 electron tail
+3 conductor
+";
+
+// Used for states the rule doesn't give a colour
+const PALETTE: [Color; 6] = [
+    Color::Red,
+    Color::Yellow,
+    Color::Blue,
+    Color::Green,
+    Color::Magenta,
+    Color::Cyan,
+];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Token {
+    Value(u8),
+    // Index into the variables, the same variable has to be the same state within a transition
+    Var(usize),
+}
+
+#[derive(Clone, Debug)]
+struct Transition {
+    // The cell itself followed by its neighbours in Golly's order
+    inputs: Vec<Token>,
+    output: Token,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Symmetry {
+    // Rotations by multiples of `step` neighbours, optionally mirrored as well
+    Rotate { step: usize, reflect: bool },
+    // Only the number of neighbours in each state matters
+    Permute,
+}
+
+// A cellular automaton given by a transition table in Golly's .rule format
+#[derive(Clone, Debug)]
+pub struct RuleTable {
+    pub name: String,
+    pub states: u16,
+    pub neighbourhood: Neighbourhood,
+    symmetry: Symmetry,
+    vars: Vec<Vec<u8>>,
+    transitions: Vec<Transition>,
+    pub colours: HashMap<u8, Color>,
+    pub names: HashMap<u8, String>,
+    // The whole file, so templates can store the rule they were made with
+    pub source: String,
+}
+
+impl RuleTable {
+    pub fn wireworld() -> Self {
+        Self::parse(WIREWORLD).expect("the built-in WireWorld table is valid")
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let source =
+            fs::read_to_string(path).map_err(|err| format!("can't read {}: {}", path, err))?;
+        Self::parse(&source)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut table = Self {
+            name: String::new(),
+            states: 2,
+            neighbourhood: Neighbourhood::Moore(1),
+            symmetry: Symmetry::Rotate {
+                step: 8,
+                reflect: false,
+            },
+            vars: vec![],
+            transitions: vec![],
+            colours: HashMap::new(),
+            names: HashMap::new(),
+            source: source.to_string(),
+        };
+        let mut var_names: HashMap<String, usize> = HashMap::new();
+        let mut symmetry = "none".to_string();
+        let mut section = "";
+        let mut has_table = false;
+
+        for (number, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let at = |message: String| format!("line {}: {}", number + 1, message);
+
+            if let Some(header) = line.strip_prefix('@') {
+                let (name, rest) = header.split_once(' ').unwrap_or((header, ""));
+                section = match name {
+                    "RULE" => {
+                        table.name = rest.trim().to_string();
+                        "RULE"
+                    }
+                    "TABLE" => {
+                        has_table = true;
+                        "TABLE"
+                    }
+                    "COLORS" => "COLORS",
+                    "NAMES" => "NAMES",
+                    // @ICONS, @TREE and friends are not supported and skipped
+                    _ => "",
+                };
+                continue;
+            }
+            if line.is_empty() {
+                continue;
+            }
+
+            match section {
+                "TABLE" => {
+                    if let Some(value) = line.strip_prefix("n_states:") {
+                        table.states = value
+                            .trim()
+                            .parse()
+                            .ok()
+                            .filter(|n| (2..=256).contains(n))
+                            .ok_or_else(|| at(format!("{} is not a number of states", value)))?;
+                    } else if let Some(value) = line.strip_prefix("neighborhood:") {
+                        table.neighbourhood = match value.trim() {
+                            "Moore" => Neighbourhood::Moore(1),
+                            "vonNeumann" => Neighbourhood::VonNeumann(1),
+                            "hexagonal" => Neighbourhood::Hexagonal,
+                            other => {
+                                return Err(at(format!("{} neighborhood is not supported", other)))
+                            }
+                        };
+                    } else if let Some(value) = line.strip_prefix("symmetries:") {
+                        symmetry = value.trim().to_string();
+                    } else if let Some(var) = line.strip_prefix("var ") {
+                        let (name, values) = var
+                            .split_once('=')
+                            .ok_or_else(|| at("expected var name={...}".to_string()))?;
+                        let values = table.parse_set(values.trim(), &var_names).map_err(at)?;
+                        var_names.insert(name.trim().to_string(), table.vars.len());
+                        table.vars.push(values);
+                    } else {
+                        let transition = table.parse_transition(line, &var_names).map_err(at)?;
+                        table.transitions.push(transition);
+                    }
+                }
+                "COLORS" => {
+                    let numbers = line
+                        .split_whitespace()
+                        .map(|n| n.parse::<u8>())
+                        .collect::<Result<Vec<u8>, _>>()
+                        .map_err(|_| at(format!("{} is not a colour", line)))?;
+                    table.add_colours(&numbers);
+                }
+                "NAMES" => {
+                    let (state, name) = line
+                        .split_once(' ')
+                        .ok_or_else(|| at("expected a state and its name".to_string()))?;
+                    let state = state
+                        .parse()
+                        .map_err(|_| at(format!("{} is not a state", state)))?;
+                    table.names.insert(state, name.trim().to_string());
+                }
+                _ => {}
+            }
+        }
+
+        if !has_table {
+            return Err("only rules with a @TABLE are supported".to_string());
+        }
+        if table.name.is_empty() {
+            return Err("the rule has no @RULE name".to_string());
+        }
+        table.symmetry = parse_symmetry(&symmetry, table.neighbours())?;
+        Ok(table)
+    }
+
+    // Either `{0,1,a}` or a single state or variable
+    fn parse_set(&self, set: &str, var_names: &HashMap<String, usize>) -> Result<Vec<u8>, String> {
+        let inner = set
+            .strip_prefix('{')
+            .and_then(|s| s.strip_suffix('}'))
+            .unwrap_or(set);
+        let mut values = vec![];
+        for item in inner.split(',').map(str::trim) {
+            match var_names.get(item) {
+                Some(&var) => values.extend(&self.vars[var]),
+                None => values.push(self.parse_state(item)?),
+            }
+        }
+        Ok(values)
+    }
+
+    fn parse_state(&self, s: &str) -> Result<u8, String> {
+        s.parse::<u8>()
+            .ok()
+            .filter(|&state| (state as u16) < self.states)
+            .ok_or_else(|| format!("{} is not a state or variable", s))
+    }
+
+    fn parse_transition(
+        &mut self,
+        line: &str,
+        var_names: &HashMap<String, usize>,
+    ) -> Result<Transition, String> {
+        let parts: Vec<String> = if line.contains(',') || line.contains('{') {
+            split_top_level(line)
+        } else {
+            // Compact form with one character per state, only works below 10 states
+            line.chars().map(|c| c.to_string()).collect()
+        };
+
+        let expected = self.neighbours() + 2;
+        if parts.len() != expected {
+            return Err(format!(
+                "expected {} entries in a transition, got {}",
+                expected,
+                parts.len()
+            ));
+        }
+
+        let mut tokens = vec![];
+        for part in &parts {
+            let token = match var_names.get(part.as_str()) {
+                Some(&var) => Token::Var(var),
+                // An inline set is a variable of its own
+                None if part.starts_with('{') => {
+                    let values = self.parse_set(part, var_names)?;
+                    self.vars.push(values);
+                    Token::Var(self.vars.len() - 1)
+                }
+                None => Token::Value(self.parse_state(part)?),
+            };
+            tokens.push(token);
+        }
+
+        let output = tokens.pop().expect("transitions have at least two entries");
+        if matches!(output, Token::Var(_)) && !tokens.contains(&output) {
+            return Err(format!(
+                "output {} is not bound by an input",
+                parts[parts.len() - 1]
+            ));
+        }
+        Ok(Transition {
+            inputs: tokens,
+            output,
+        })
+    }
+
+    fn add_colours(&mut self, numbers: &[u8]) {
+        match numbers.len() {
+            // A gradient from the first to the second colour over all living states
+            6 => {
+                let last = (self.states - 1).max(1) as f32;
+                for state in 1..self.states {
+                    let t = if last > 1.0 {
+                        (state - 1) as f32 / (last - 1.0)
+                    } else {
+                        0.0
+                    };
+                    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t) as u8;
+                    self.colours.insert(
+                        state as u8,
+                        Color::Rgb(
+                            mix(numbers[0], numbers[3]),
+                            mix(numbers[1], numbers[4]),
+                            mix(numbers[2], numbers[5]),
+                        ),
+                    );
+                }
+            }
+            n if n >= 4 => {
+                let (states, rgb) = numbers.split_at(n - 3);
+                for &state in states {
+                    self.colours
+                        .insert(state, Color::Rgb(rgb[0], rgb[1], rgb[2]));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn colour(&self, state: u8) -> Color {
+        self.colours
+            .get(&state)
+            .copied()
+            .unwrap_or(PALETTE[(state as usize).saturating_sub(1) % PALETTE.len()])
+    }
+
+    pub fn state_name(&self, state: u8) -> String {
+        self.names
+            .get(&state)
+            .cloned()
+            .unwrap_or_else(|| format!("state {}", state))
+    }
+
+    pub fn neighbours(&self) -> usize {
+        match self.neighbourhood {
+            Neighbourhood::VonNeumann(_) => 4,
+            Neighbourhood::Hexagonal => 6,
+            _ => 8,
+        }
+    }
+
+    // Neighbour offsets in Golly's order, going clockwise starting north
+    pub fn offsets(&self, row: u16) -> Vec<(i32, i32)> {
+        match self.neighbourhood {
+            Neighbourhood::VonNeumann(_) => vec![(-1, 0), (0, 1), (1, 0), (0, -1)],
+            // Golly's N, E, SE, S, W and NW on a grid where odd rows are shifted right
+            Neighbourhood::Hexagonal => {
+                let shift = (row % 2) as i32;
+                vec![
+                    (-1, shift),
+                    (0, 1),
+                    (1, shift),
+                    (1, shift - 1),
+                    (0, -1),
+                    (-1, shift - 1),
+                ]
+            }
+            _ => vec![
+                (-1, 0),
+                (-1, 1),
+                (0, 1),
+                (1, 1),
+                (1, 0),
+                (1, -1),
+                (0, -1),
+                (-1, -1),
+            ],
+        }
+    }
+
+    // The first matching transition wins, cells without one keep their state
+    pub fn next_state(&self, centre: u8, neighbours: &[u8]) -> u8 {
+        for transition in &self.transitions {
+            let matched = match self.symmetry {
+                Symmetry::Permute => self.matches_permuted(transition, centre, neighbours),
+                Symmetry::Rotate { step, reflect } => {
+                    let n = neighbours.len();
+                    let mirrors: &[bool] = if reflect { &[false, true] } else { &[false] };
+                    (0..n).step_by(step).find_map(|rotation| {
+                        mirrors.iter().find_map(|&mirror| {
+                            let order = |i: usize| {
+                                let i = if mirror { (n - i) % n } else { i };
+                                (i + rotation) % n
+                            };
+                            self.matches(transition, centre, neighbours, order)
+                        })
+                    })
+                }
+            };
+            if let Some(state) = matched {
+                return state;
+            }
+        }
+        centre
+    }
+
+    fn matches(
+        &self,
+        transition: &Transition,
+        centre: u8,
+        neighbours: &[u8],
+        order: impl Fn(usize) -> usize,
+    ) -> Option<u8> {
+        let mut bound = vec![None; self.vars.len()];
+        if !self.accepts(transition.inputs[0], centre, &mut bound) {
+            return None;
+        }
+        for (i, &token) in transition.inputs[1..].iter().enumerate() {
+            if !self.accepts(token, neighbours[order(i)], &mut bound) {
+                return None;
+            }
+        }
+        Some(self.resolve(transition.output, &bound))
+    }
+
+    fn matches_permuted(
+        &self,
+        transition: &Transition,
+        centre: u8,
+        neighbours: &[u8],
+    ) -> Option<u8> {
+        let mut bound = vec![None; self.vars.len()];
+        if !self.accepts(transition.inputs[0], centre, &mut bound) {
+            return None;
+        }
+        let mut used = vec![false; neighbours.len()];
+        if self.assign(&transition.inputs[1..], neighbours, &mut used, &mut bound) {
+            Some(self.resolve(transition.output, &bound))
+        } else {
+            None
+        }
+    }
+
+    // Tries to give every input its own neighbour, backtracking when a binding doesn't work out
+    fn assign(
+        &self,
+        inputs: &[Token],
+        neighbours: &[u8],
+        used: &mut [bool],
+        bound: &mut Vec<Option<u8>>,
+    ) -> bool {
+        let Some((&input, rest)) = inputs.split_first() else {
+            return true;
+        };
+        let mut tried = vec![];
+        for (i, &state) in neighbours.iter().enumerate() {
+            if used[i] || tried.contains(&state) {
+                continue;
+            }
+            tried.push(state);
+            let before = bound.clone();
+            if self.accepts(input, state, bound) {
+                used[i] = true;
+                if self.assign(rest, neighbours, used, bound) {
+                    return true;
+                }
+                used[i] = false;
+            }
+            *bound = before;
+        }
+        false
+    }
+
+    fn accepts(&self, token: Token, state: u8, bound: &mut [Option<u8>]) -> bool {
+        match token {
+            Token::Value(value) => value == state,
+            Token::Var(var) => match bound[var] {
+                Some(value) => value == state,
+                None if self.vars[var].contains(&state) => {
+                    bound[var] = Some(state);
+                    true
+                }
+                None => false,
+            },
+        }
+    }
+
+    fn resolve(&self, token: Token, bound: &[Option<u8>]) -> u8 {
+        match token {
+            Token::Value(value) => value,
+            Token::Var(var) => bound[var].unwrap_or(0),
+        }
+    }
+}
+
+// Splits at commas that are not inside of {}
+fn split_top_level(line: &str) -> Vec<String> {
+    let mut parts = vec![];
+    let mut current = String::new();
+    let mut depth = 0;
+    for c in line.chars() {
+        match c {
+            '{' => {
+                depth += 1;
+                current.push(c);
+            }
+            '}' => {
+                depth -= 1;
+                current.push(c);
+            }
+            ',' if depth == 0 => parts.push(std::mem::take(&mut current).trim().to_string()),
+            c if !c.is_whitespace() => current.push(c),
+            _ => {}
+        }
+    }
+    parts.push(current.trim().to_string());
+    parts
+}
+
+fn parse_symmetry(name: &str, neighbours: usize) -> Result<Symmetry, String> {
+    if name == "permute" {
+        return Ok(Symmetry::Permute);
+    }
+    let reflect = name.ends_with("reflect") || name == "reflect_horizontal";
+    let rotations = match name.trim_end_matches("reflect") {
+        "none" | "reflect_horizontal" | "" => 1,
+        rotate => rotate
+            .strip_prefix("rotate")
+            .and_then(|n| n.parse::<usize>().ok())
+            .ok_or_else(|| format!("{} symmetry is not supported", name))?,
+    };
+    if rotations == 0 || !neighbours.is_multiple_of(rotations) {
+        return Err(format!("{} symmetry doesn't fit the neighborhood", name));
+    }
+    Ok(Symmetry::Rotate {
+        step: neighbours / rotations,
+        reflect,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wireworld_transitions() {
+        let table = RuleTable::wireworld();
+        assert_eq!(table.states, 4);
+        assert_eq!(table.next_state(1, &[3, 3, 0, 0, 0, 0, 0, 0]), 2);
+        assert_eq!(table.next_state(2, &[1, 3, 0, 0, 0, 0, 0, 0]), 3);
+        assert_eq!(table.next_state(3, &[0, 0, 0, 1, 0, 0, 0, 0]), 1);
+        assert_eq!(table.next_state(3, &[1, 0, 0, 1, 0, 0, 0, 0]), 1);
+        assert_eq!(table.next_state(3, &[1, 1, 0, 1, 0, 0, 0, 0]), 3);
+        assert_eq!(table.next_state(0, &[1, 1, 0, 0, 0, 0, 0, 0]), 0);
+        assert_eq!(table.state_name(3), "conductor");
+        assert_eq!(table.colour(1), Color::Rgb(0, 128, 255));
+    }
+
+    #[test]
+    fn rotations_and_bound_variables() {
+        let table = RuleTable::parse(
+            "@RULE Spin
+@TABLE
+n_states:3
+neighborhood:vonNeumann
+symmetries:rotate4
+var a={1,2}
+# a cell between two equal neighbours turns into them
+0,a,0,a,0,a
+020001",
+        )
+        .unwrap();
+        assert_eq!(table.next_state(0, &[1, 0, 1, 0]), 1);
+        assert_eq!(table.next_state(0, &[0, 2, 0, 2]), 2);
+        assert_eq!(table.next_state(0, &[1, 0, 2, 0]), 0);
+        // The compact line only matches north, rotated it matches every side
+        assert_eq!(table.next_state(0, &[0, 0, 0, 2]), 1);
+    }
+
+    #[test]
+    fn rejects_broken_tables() {
+        assert!(RuleTable::parse("@RULE X\n@TABLE\nn_states:2\n0,1,1\n").is_err());
+        assert!(RuleTable::parse("@RULE X\n@TREE\nnum_states=2\n").is_err());
+        assert!(RuleTable::parse("@RULE X\n@TABLE\nn_states:2\n0,1,1,1,1,1,1,1,1,5\n").is_err());
+    }
+}
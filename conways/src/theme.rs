@@ -0,0 +1,232 @@
+use tui::{
+    style::{Color, Modifier, Style},
+    text::Span,
+};
+
+pub const THEMES: [&str; 3] = ["classic", "ocean", "mono"];
+
+const DIGITS: [&str; 10] = ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"];
+
+// How a single cell is drawn
+#[derive(Clone, Copy)]
+pub struct Look {
+    pub glyph: &'static str,
+    pub style: Style,
+}
+
+impl Look {
+    fn new(glyph: &'static str, style: Style) -> Self {
+        Self { glyph, style }
+    }
+
+    fn plain(glyph: &'static str) -> Self {
+        Self::new(glyph, Style::default())
+    }
+
+    fn colored(glyph: &'static str, color: Color) -> Self {
+        Self::new(glyph, Style::default().fg(color))
+    }
+
+    pub fn span(&self) -> Span<'static> {
+        Span::styled(self.glyph, self.style)
+    }
+}
+
+pub struct Theme {
+    pub name: &'static str,
+    // Whether rule tables may colour their states
+    pub colored: bool,
+    pub editor_alive: Look,
+    pub editor_dead: Look,
+    pub alive: Look,
+    pub dead: Look,
+    // Alive cells in the age heatmap, from newborn to oldest
+    pub heat: Vec<Look>,
+    // Recently dead cells, from just died to almost forgotten
+    pub trail: Vec<Look>,
+    // Cells that differ between two compared worlds
+    pub diff_alive: Look,
+    pub diff_dead: Look,
+}
+
+impl Theme {
+    pub fn classic() -> Self {
+        Self {
+            name: "classic",
+            colored: true,
+            editor_alive: Look::colored("█", Color::Green),
+            editor_dead: Look::plain("."),
+            alive: Look::colored("█", Color::Red),
+            dead: Look::new("█", Style::default().add_modifier(Modifier::REVERSED)),
+            heat: vec![
+                Look::colored("█", Color::Yellow),
+                Look::colored("█", Color::LightRed),
+                Look::colored("█", Color::Red),
+                Look::colored("█", Color::Magenta),
+                Look::colored("█", Color::Blue),
+            ],
+            trail: vec![
+                Look::colored("▓", Color::DarkGray),
+                Look::colored("▒", Color::DarkGray),
+                Look::colored("░", Color::DarkGray),
+            ],
+            diff_alive: Look::colored("█", Color::Yellow),
+            diff_dead: Look::colored("░", Color::Yellow),
+        }
+    }
+
+    pub fn ocean() -> Self {
+        Self {
+            name: "ocean",
+            colored: true,
+            editor_alive: Look::colored("█", Color::Cyan),
+            editor_dead: Look::colored(".", Color::DarkGray),
+            alive: Look::colored("█", Color::LightCyan),
+            dead: Look::colored("█", Color::Blue),
+            heat: vec![
+                Look::colored("█", Color::White),
+                Look::colored("█", Color::LightCyan),
+                Look::colored("█", Color::Cyan),
+                Look::colored("█", Color::LightGreen),
+                Look::colored("█", Color::Green),
+            ],
+            trail: vec![
+                Look::new("▓", Style::default().fg(Color::LightBlue).bg(Color::Blue)),
+                Look::new("▒", Style::default().fg(Color::LightBlue).bg(Color::Blue)),
+                Look::new("░", Style::default().fg(Color::LightBlue).bg(Color::Blue)),
+            ],
+            diff_alive: Look::colored("█", Color::LightMagenta),
+            diff_dead: Look::new(
+                "░",
+                Style::default().fg(Color::LightMagenta).bg(Color::Blue),
+            ),
+        }
+    }
+
+    // No colours and only ascii glyphs for limited terminals
+    pub fn mono() -> Self {
+        Self {
+            name: "mono",
+            colored: false,
+            editor_alive: Look::plain("#"),
+            editor_dead: Look::plain("."),
+            alive: Look::plain("#"),
+            dead: Look::plain(" "),
+            heat: vec![
+                Look::plain("o"),
+                Look::plain("O"),
+                Look::plain("0"),
+                Look::plain("@"),
+                Look::plain("#"),
+            ],
+            trail: vec![Look::plain("+"), Look::plain(":"), Look::plain(".")],
+            diff_alive: Look::plain("X"),
+            diff_dead: Look::plain("x"),
+        }
+    }
+
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "classic" => Some(Self::classic()),
+            "ocean" => Some(Self::ocean()),
+            "mono" => Some(Self::mono()),
+            _ => None,
+        }
+    }
+
+    // CONWAYS_THEME wins over the configured theme, without either NO_COLOR picks mono.
+    // An unknown CONWAYS_THEME comes back as a warning for the status bar.
+    pub fn pick(configured: Option<&str>) -> (Self, Option<String>) {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        Self::choose(
+            std::env::var("CONWAYS_THEME").ok().as_deref(),
+            configured,
+            no_color,
+        )
+    }
+
+    fn choose(
+        from_env: Option<&str>,
+        configured: Option<&str>,
+        no_color: bool,
+    ) -> (Self, Option<String>) {
+        let warning = from_env
+            .filter(|name| Self::named(name).is_none())
+            .map(|name| format!("CONWAYS_THEME: unknown theme {}", name));
+        if let Some(theme) = from_env
+            .and_then(Self::named)
+            .or_else(|| configured.and_then(Self::named))
+        {
+            return (theme, warning);
+        }
+        match no_color {
+            true => (Self::mono(), warning),
+            false => (Self::classic(), warning),
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        let current = THEMES.iter().position(|&n| n == self.name).unwrap_or(0);
+        Self::named(THEMES[(current + 1) % THEMES.len()]).unwrap_or_else(Self::classic)
+    }
+
+    // Cells age on a log scale so long-lived still lifes don't wash out the gradient
+    pub fn heat(&self, age: u32) -> Look {
+        let step = (age + 1).ilog2() as usize;
+        self.heat[step.min(self.heat.len() - 1)]
+    }
+
+    // Cells of rule tables in their own colour, or as the state number without colours
+    pub fn state(&self, state: u8, colour: Color) -> Look {
+        match self.colored {
+            true => Look::colored("█", colour),
+            false => Look::plain(DIGITS.get(state as usize).copied().unwrap_or("#")),
+        }
+    }
+
+    pub fn trail(&self, dead_for: u8) -> Option<Look> {
+        self.trail.get(dead_for as usize).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chosen(from_env: Option<&str>, configured: Option<&str>, no_color: bool) -> &'static str {
+        Theme::choose(from_env, configured, no_color).0.name
+    }
+
+    #[test]
+    fn environment_beats_config_beats_no_color() {
+        assert_eq!(chosen(Some("ocean"), Some("mono"), true), "ocean");
+        assert_eq!(chosen(None, Some("ocean"), true), "ocean");
+        assert_eq!(chosen(None, None, true), "mono");
+        assert_eq!(chosen(None, None, false), "classic");
+        // Unknown names are skipped, and reported if they come from the environment
+        assert_eq!(chosen(Some("neon"), Some("ocean"), false), "ocean");
+        assert_eq!(
+            Theme::choose(Some("neon"), None, false).1.as_deref(),
+            Some("CONWAYS_THEME: unknown theme neon")
+        );
+        assert_eq!(Theme::choose(Some("mono"), None, false).1, None);
+    }
+
+    #[test]
+    fn heat_doubles_the_age_per_step() {
+        let theme = Theme::mono();
+        let glyph = |age| theme.heat(age).span().content.into_owned();
+        for (ages, expected) in [
+            (0..=0, "o"),
+            (1..=2, "O"),
+            (3..=6, "0"),
+            (7..=14, "@"),
+            (15..=1000, "#"),
+        ] {
+            for age in ages {
+                assert_eq!(glyph(age), expected, "age {}", age);
+            }
+        }
+        assert_eq!(glyph(u32::MAX - 1), "#");
+    }
+}
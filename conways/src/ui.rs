@@ -0,0 +1,129 @@
+use crate::diff::Comparison;
+use crate::keymap::Keymap;
+use crate::rule::Automaton;
+use crate::theme::Theme;
+use crate::world::World;
+use crate::Mode;
+use std::time::Duration;
+use tui::{
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Paragraph, Row, Table},
+};
+
+pub fn status_bar<'a>(
+    mode: &Mode,
+    world: &World,
+    speed: Duration,
+    status: Option<&'a str>,
+) -> Paragraph<'a> {
+    let mode_name = match mode {
+        Mode::Insert => " INSERT ",
+        Mode::Play => " PLAY ",
+        Mode::Load => " LOAD ",
+        Mode::Save => " SAVE ",
+    };
+
+    let mut spans = vec![
+        Span::styled(
+            mode_name,
+            Style::default().add_modifier(Modifier::REVERSED | Modifier::BOLD),
+        ),
+        Span::raw(format!(
+            " gen {} │ pop {} │ {} │ {}ms │ ? help ",
+            world.generation,
+            world.alive.len(),
+            world.rule,
+            speed.as_millis()
+        )),
+    ];
+    if let Some(status) = status {
+        spans.push(Span::styled(status, Style::default().fg(Color::Red)));
+    }
+    Paragraph::new(Spans::from(spans))
+}
+
+pub fn diff_status_bar<'a>(
+    comparison: &Comparison,
+    differing: usize,
+    speed: Duration,
+    status: Option<&'a str>,
+) -> Paragraph<'a> {
+    let diverged = match comparison.diverged_at {
+        Some(generation) => format!("diverged at gen {}", generation),
+        None => "not diverged".to_string(),
+    };
+    let mut spans = vec![
+        Span::styled(
+            " DIFF ",
+            Style::default().add_modifier(Modifier::REVERSED | Modifier::BOLD),
+        ),
+        Span::raw(format!(
+            " gen {} │ pop {} / {} │ differ {} │ {} │ {}ms │ ? help ",
+            comparison.generation(),
+            comparison.left.alive.len(),
+            comparison.right.alive.len(),
+            differing,
+            diverged,
+            speed.as_millis()
+        )),
+    ];
+    if let Some(status) = status {
+        spans.push(Span::styled(status, Style::default().fg(Color::Red)));
+    }
+    Paragraph::new(Spans::from(spans))
+}
+
+// Tables list their states next to the name, so it's clear what clicking cycles through
+pub fn title(mode: &Mode, world: &World, theme: &Theme) -> Spans<'static> {
+    let name = match (&world.rule, mode) {
+        (Automaton::Table(table), Mode::Insert) => format!("Editor - {}", table.name),
+        (Automaton::Table(table), _) => table.name.clone(),
+        (_, Mode::Insert) => "Editor - Game of Life".to_string(),
+        _ => "Conways - Game of Life".to_string(),
+    };
+    let mut spans = vec![Span::raw(name)];
+
+    if let Automaton::Table(table) = &world.rule {
+        for state in 1..table.states {
+            let state = state as u8;
+            spans.push(Span::raw(" "));
+            spans.push(theme.state(state, table.colour(state)).span());
+            spans.push(Span::raw(format!(" {}", table.state_name(state))));
+        }
+    }
+    Spans::from(spans)
+}
+
+pub fn help(keymap: &Keymap) -> Table<'static> {
+    let rows = keymap
+        .help()
+        .into_iter()
+        .map(|(keys, description)| Row::new(vec![keys, description.to_string()]));
+
+    Table::new(rows)
+        .block(Block::default().title("Help").borders(Borders::ALL))
+        .widths(&[Constraint::Length(12), Constraint::Min(10)])
+        .column_spacing(2)
+}
+
+// A rect of the given size in the middle of `area`, shrunk to fit if needed
+pub fn centered(width: u16, height: u16, area: Rect) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(0),
+            Constraint::Length(height.min(area.height)),
+            Constraint::Min(0),
+        ])
+        .split(area);
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Min(0),
+            Constraint::Length(width.min(area.width)),
+            Constraint::Min(0),
+        ])
+        .split(vertical[1])[1]
+}
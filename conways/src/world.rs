@@ -1,97 +1,240 @@
-use fallible_iterator::FallibleIterator;
-use rusqlite::{Connection, Result};
-use tui::{
-    style::{Color, Modifier, Style},
-    text::{Span, Spans},
-};
+use crate::rule::{Automaton, Rule};
+use crate::theme::{Look, Theme};
 use crate::Mode;
+use rusqlite::{Connection, OptionalExtension, Result};
+use std::collections::{HashMap, HashSet};
+use tui::text::{Span, Spans};
+
+// Number of generations a dead cell stays visible in the heatmap trail
+pub const TRAIL_LENGTH: u8 = 3;
+
+// Size of the world for the built-in pulsar, templates bring their own
+const PULSAR_SIZE: (u16, u16) = (40, 20);
+
+// State of every alive cell by position
+pub type Cells = HashMap<(u16, u16), u8>;
 
 pub struct World {
     pub width: u16,
     pub height: u16,
     pub alive: Vec<(u16, u16)>,
+    // States of alive cells other than 1, only rule tables have those
+    pub states: HashMap<(u16, u16), u8>,
+    // Generations each alive cell has survived
+    pub ages: HashMap<(u16, u16), u32>,
+    // Generations since each recently dead cell died
+    pub trail: HashMap<(u16, u16), u8>,
+    pub generation: u64,
+    pub rule: Automaton,
 }
 
 impl World {
     pub fn width(self, w: u16) -> Self {
-        Self {
-            width: w,
-            height: self.height,
-            alive: self.alive,
-        }
+        Self { width: w, ..self }
     }
     pub fn height(self, h: u16) -> Self {
-        Self {
-            width: self.width,
-            height: h,
-            alive: self.alive,
-        }
+        Self { height: h, ..self }
+    }
+    pub fn rule(self, rule: Automaton) -> Self {
+        Self { rule, ..self }
     }
 
-    pub fn get_grid(&self, mode: &Mode, height: u16, width: u16) -> Vec<Spans> {
+    pub fn get_grid(
+        &self,
+        mode: &Mode,
+        theme: &Theme,
+        heatmap: bool,
+        height: u16,
+        width: u16,
+    ) -> Vec<Spans<'static>> {
+        self.get_diff_grid(mode, theme, heatmap, &HashSet::new(), height, width)
+    }
+
+    // Same as get_grid, with the given cells drawn in the theme's diff colours
+    pub fn get_diff_grid(
+        &self,
+        mode: &Mode,
+        theme: &Theme,
+        heatmap: bool,
+        differences: &HashSet<(u16, u16)>,
+        height: u16,
+        width: u16,
+    ) -> Vec<Spans<'static>> {
+        let cells = self.cells();
         let mut spans = vec![];
 
+        let neighbourhood = self.rule.neighbourhood();
         for row in 0..height {
-            spans.push(vec![Spans::from({
-                let mut cols = vec![];
-                for col in 0..width {
-                    cols.push({
-                        if self.alive.iter().any(|&x| x == (row, col)) {
-                            Span::styled(
-                                "█",
-                                Style::default().fg({
-                                    match mode {
-                                        Mode::Insert => Color::Green,
-                                        _ => Color::Red,
-                                    }
-                                }),
-                            )
-                        } else {
-                            match mode {
-                                Mode::Insert => Span::raw("."),
-                                _ => Span::styled(
-                                    "█",
-                                    Style::default().add_modifier(Modifier::REVERSED),
-                                ),
-                            }
-                        }
-                    });
+            let mut cols = vec![];
+            let offset = neighbourhood.row_offset(row);
+            if offset > 0 {
+                cols.push(Span::raw(" ".repeat(offset as usize)));
+            }
+            for col in 0..width.saturating_sub(offset) / neighbourhood.cell_width() {
+                if col > 0 && neighbourhood.cell_width() > 1 {
+                    cols.push(Span::raw(
+                        " ".repeat(neighbourhood.cell_width() as usize - 1),
+                    ));
                 }
-                cols
-            })])
+                let state = cells.get(&(row, col)).copied().unwrap_or(0);
+                let look = match differences.contains(&(row, col)) {
+                    true if state > 0 => theme.diff_alive,
+                    true => theme.diff_dead,
+                    false => self.get_look(mode, theme, heatmap, state, (row, col)),
+                };
+                cols.push(look.span());
+            }
+            spans.push(Spans::from(cols));
+        }
+        spans
+    }
+
+    fn get_look(
+        &self,
+        mode: &Mode,
+        theme: &Theme,
+        heatmap: bool,
+        state: u8,
+        pos: (u16, u16),
+    ) -> Look {
+        if let (Automaton::Table(table), 1..) = (&self.rule, state) {
+            return theme.state(state, table.colour(state));
+        }
+        match (mode, state > 0) {
+            (Mode::Insert, true) => theme.editor_alive,
+            (Mode::Insert, false) => theme.editor_dead,
+            (_, true) if heatmap => theme.heat(self.ages.get(&pos).copied().unwrap_or(0)),
+            (_, true) => theme.alive,
+            (_, false) if heatmap => self
+                .trail
+                .get(&pos)
+                .and_then(|&dead_for| theme.trail(dead_for))
+                .unwrap_or(theme.dead),
+            (_, false) => theme.dead,
+        }
+    }
+
+    // State of every alive cell
+    pub fn cells(&self) -> Cells {
+        self.alive
+            .iter()
+            .map(|pos| (*pos, self.states.get(pos).copied().unwrap_or(1)))
+            .collect()
+    }
+
+    pub fn set_cells(&mut self, cells: Cells) {
+        self.alive = cells.keys().copied().collect();
+        self.states = cells.into_iter().filter(|(_, state)| *state > 1).collect();
+    }
+
+    // Positions where the two worlds have a different state
+    pub fn differences(&self, other: &World) -> HashSet<(u16, u16)> {
+        let (mine, theirs) = (self.cells(), other.cells());
+        mine.iter()
+            .filter(|(pos, state)| theirs.get(pos) != Some(state))
+            .chain(theirs.iter().filter(|(pos, _)| !mine.contains_key(pos)))
+            .map(|(pos, _)| *pos)
+            .collect()
+    }
+
+    // Moves the cell on to its next state, wrapping around to dead after the last one
+    pub fn toggle(&mut self, pos: (u16, u16)) {
+        let state = match self.alive.contains(&pos) {
+            true => self.states.get(&pos).copied().unwrap_or(1),
+            false => 0,
+        };
+        let next = ((state as u16 + 1) % self.rule.states()) as u8;
+
+        self.alive.retain(|&x| x != pos);
+        self.states.remove(&pos);
+        if next > 0 {
+            self.alive.push(pos);
+        }
+        if next > 1 {
+            self.states.insert(pos, next);
         }
-        return spans.into_iter().flatten().collect();
+    }
+
+    pub fn clear(&mut self) {
+        self.generation = 0;
+        self.alive.clear();
+        self.states.clear();
+        self.ages.clear();
+        self.trail.clear();
     }
 
     pub fn next_day(&mut self) {
+        let before = self.alive.clone();
+        self.remove_not_in_world();
         let alive_as_matrix = self.get_alives_as_matrix_with_puffer();
+        let pad = self.rule.neighbourhood().reach() as usize;
 
-        for row in 1..self.height + 1 {
-            for col in 1..self.width + 1 {
-                let row = row as usize;
-                let col = col as usize;
-
-                if alive_as_matrix[row][col] == 0
-                    && self.get_num_neighbours(&alive_as_matrix, row, col) == 3
-                {
-                    self.alive.push((row as u16, col as u16));
-                } else if alive_as_matrix[row][col] == 1
-                    && self.get_num_neighbours(&alive_as_matrix, row, col) != 2
-                    && self.get_num_neighbours(&alive_as_matrix, row, col) != 3
-                {
-                    self.alive
-                        .retain(|&x| x.0 != row as u16 || x.1 != col as u16);
+        let mut alive = vec![];
+        let mut states = HashMap::new();
+        // Tables are slow to match, but most cells share a handful of neighbourhoods
+        let mut seen: HashMap<(u8, Vec<u8>), u8> = HashMap::new();
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let state = alive_as_matrix[row as usize + pad][col as usize + pad];
+                let next = match &self.rule {
+                    Automaton::Life(rule) => {
+                        let neighbours = self.get_num_neighbours(rule, &alive_as_matrix, row, col);
+                        rule.next_state(state > 0, neighbours) as u8
+                    }
+                    Automaton::Table(table) => {
+                        let neighbours = table
+                            .offsets(row)
+                            .iter()
+                            .map(|(dr, dc)| {
+                                alive_as_matrix[(row as i32 + pad as i32 + dr) as usize]
+                                    [(col as i32 + pad as i32 + dc) as usize]
+                            })
+                            .collect::<Vec<u8>>();
+                        *seen.entry((state, neighbours)).or_insert_with_key(
+                            |(state, neighbours)| table.next_state(*state, neighbours),
+                        )
+                    }
+                };
+                if next > 0 {
+                    alive.push((row, col));
+                }
+                if next > 1 {
+                    states.insert((row, col), next);
                 }
             }
         }
+        self.alive = alive;
+        self.states = states;
 
-        self.remove_not_in_world();
+        self.age_cells(&before);
+        self.generation += 1;
+    }
+
+    fn age_cells(&mut self, before: &[(u16, u16)]) {
+        let now: HashSet<&(u16, u16)> = self.alive.iter().collect();
+
+        self.trail.retain(|pos, dead_for| {
+            *dead_for += 1;
+            *dead_for < TRAIL_LENGTH && !now.contains(pos)
+        });
+        for pos in before.iter().filter(|pos| !now.contains(pos)) {
+            self.trail.insert(*pos, 0);
+        }
+
+        self.ages = self
+            .alive
+            .iter()
+            .map(|pos| (*pos, self.ages.get(pos).map_or(0, |age| age + 1)))
+            .collect();
     }
 
-    pub fn load_alive(&self, conn: &Connection) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-        let mut stmt = conn.prepare("SELECT name FROM templates")?;
-        let rows = stmt.query_map([], |row| row.get(0))?;
-    
+    pub fn load_alive(
+        &self,
+        conn: &Connection,
+    ) -> Result<Vec<(i64, String)>, Box<dyn std::error::Error>> {
+        let mut stmt = conn.prepare("SELECT id, name FROM templates")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
         let mut entrys = Vec::new();
         for entry in rows {
             entrys.push(entry?);
@@ -99,56 +242,109 @@ impl World {
         Ok(entrys)
     }
 
-    pub fn save_current_state(&self, conn: &Connection, name: String) -> Result<(), Box<dyn std::error::Error>> {
+    // Replaces the world with a saved template, templates from before rules were saved are Conway's
+    pub fn load_template(
+        &mut self,
+        conn: &Connection,
+        id: i64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (alive, rule): (String, Option<String>) = conn
+            .query_row(
+                "SELECT alive, rule FROM templates WHERE id = ?1",
+                [id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?
+            .ok_or("the template is gone")?;
+
+        let rule = match rule {
+            Some(rule) => Automaton::from_template(&rule)?,
+            None => Automaton::Life(Rule::conway()),
+        };
+        let cells = string_to_alive(&alive, rule.states())?;
+
+        // The world keeps its size on screen, a hexagonal cell takes two columns
+        self.width =
+            self.width * self.rule.neighbourhood().cell_width() / rule.neighbourhood().cell_width();
+        self.clear();
+        self.rule = rule;
+        self.set_cells(cells);
+        Ok(())
+    }
+
+    // Id and size of the newest template with that name
+    pub fn find_template(conn: &Connection, name: &str) -> Result<Option<(i64, u16, u16)>> {
+        conn.query_row(
+            "SELECT id, width, height FROM templates WHERE name = ?1 ORDER BY id DESC LIMIT 1",
+            [name],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()
+    }
+
+    // The newest template with that name, or the built-in pulsar
+    pub fn load_named(conn: &Connection, name: &str) -> Result<World, Box<dyn std::error::Error>> {
+        if let Some((id, width, height)) = World::find_template(conn, name)? {
+            // Saved in cells of the template's own rule
+            let mut world = World::default().height(height);
+            world.load_template(conn, id)?;
+            return Ok(world.width(width));
+        }
+        if name == "pulsar" {
+            return Ok(World::pulsar().width(PULSAR_SIZE.0).height(PULSAR_SIZE.1));
+        }
+        Err(format!("no template named {}", name).into())
+    }
+
+    pub fn save_current_state(
+        &self,
+        conn: &Connection,
+        name: String,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         conn.execute(
-            "INSERT INTO templates (name, width, height, alive) VALUES (?1, ?2, ?3, ?4)",
-            (name, &self.width, &self.height, &self.alive_to_string()),
+            "INSERT INTO templates (name, width, height, alive, rule) VALUES (?1, ?2, ?3, ?4, ?5)",
+            (
+                name,
+                &self.width,
+                &self.height,
+                &self.alive_to_string(),
+                &self.rule.to_template(),
+            ),
         )?;
         Ok(())
     }
 
+    // row,col for plain alive cells and row,col,state for the other states of rule tables
     fn alive_to_string(&self) -> String {
         self.alive
             .iter()
-            .map(|(x, y)| format!("{},{}", x.to_string(), y.to_string()))
+            .map(|(x, y)| match self.states.get(&(*x, *y)) {
+                Some(state) => format!("{},{},{}", x, y, state),
+                None => format!("{},{}", x, y),
+            })
             .collect::<Vec<String>>()
             .join(":")
     }
 
-    fn get_num_neighbours(&self, m: &Vec<Vec<u32>>, row: usize, col: usize) -> u16 {
-        let mut num_neighbours = 0;
-
-        if m[row - 1][col - 1] == 1 {
-            num_neighbours += 1
-        }
-        if m[row - 1][col] == 1 {
-            num_neighbours += 1
-        }
-        if m[row - 1][col + 1] == 1 {
-            num_neighbours += 1
-        }
-        if m[row][col - 1] == 1 {
-            num_neighbours += 1
-        }
-        if m[row][col + 1] == 1 {
-            num_neighbours += 1
-        }
-        if m[row + 1][col - 1] == 1 {
-            num_neighbours += 1
-        }
-        if m[row + 1][col] == 1 {
-            num_neighbours += 1
-        }
-        if m[row + 1][col + 1] == 1 {
-            num_neighbours += 1
-        }
-        num_neighbours
+    fn get_num_neighbours(&self, rule: &Rule, m: &[Vec<u8>], row: u16, col: u16) -> u16 {
+        let pad = rule.neighbourhood.reach() as i32;
+        rule.neighbourhood
+            .offsets(row, col)
+            .iter()
+            .filter(|(dr, dc)| {
+                m[(row as i32 + pad + dr) as usize][(col as i32 + pad + dc) as usize] > 0
+            })
+            .count() as u16
     }
 
-    fn get_alives_as_matrix_with_puffer(&self) -> Vec<Vec<u32>> {
-        let mut alive_as_matrix = vec![vec![0; self.width as usize + 2]; self.height as usize + 2];
-        for alive in self.alive.iter() {
-            alive_as_matrix[alive.0 as usize][alive.1 as usize] = 1;
+    // The puffer around the world is as wide as the neighbourhood reaches,
+    // so looking at neighbours at the edges never leaves the matrix
+    fn get_alives_as_matrix_with_puffer(&self) -> Vec<Vec<u8>> {
+        let pad = self.rule.neighbourhood().reach() as usize;
+        let mut alive_as_matrix =
+            vec![vec![0; self.width as usize + 2 * pad]; self.height as usize + 2 * pad];
+        for (pos, state) in self.cells() {
+            alive_as_matrix[pos.0 as usize + pad][pos.1 as usize + pad] = state;
         }
         alive_as_matrix
     }
@@ -156,5 +352,189 @@ impl World {
     fn remove_not_in_world(&mut self) {
         self.alive
             .retain(|&x| x.0 < self.height && x.1 < self.width);
+        self.states
+            .retain(|&x, _| x.0 < self.height && x.1 < self.width);
+    }
+}
+
+// States have to be alive ones of the rule, 0 is dead and isn't stored
+fn string_to_alive(alive: &str, states: u16) -> Result<Cells, Box<dyn std::error::Error>> {
+    let mut cells = Cells::new();
+    for cell in alive.split(':').filter(|cell| !cell.is_empty()) {
+        let numbers = cell
+            .split(',')
+            .map(|n| n.parse::<u16>())
+            .collect::<Result<Vec<u16>, _>>()?;
+        match numbers[..] {
+            [row, col] => cells.insert((row, col), 1),
+            [row, col, state] if (1..states).contains(&state) => {
+                cells.insert((row, col), state as u8)
+            }
+            _ => return Err(format!("{} is not a cell", cell).into()),
+        };
+    }
+    Ok(cells)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world(rule: &str, alive: Vec<(u16, u16)>) -> World {
+        let mut world = World::default()
+            .width(12)
+            .height(12)
+            .rule(Automaton::load(rule).unwrap());
+        world.alive = alive;
+        world
+    }
+
+    fn sorted(mut alive: Vec<(u16, u16)>) -> Vec<(u16, u16)> {
+        alive.sort();
+        alive
+    }
+
+    #[test]
+    fn moore_blinker_oscillates() {
+        let mut world = world("B3/S23", vec![(5, 4), (5, 5), (5, 6)]);
+        world.next_day();
+        assert_eq!(sorted(world.alive.clone()), vec![(4, 5), (5, 5), (6, 5)]);
+        world.next_day();
+        assert_eq!(sorted(world.alive.clone()), vec![(5, 4), (5, 5), (5, 6)]);
+    }
+
+    #[test]
+    fn von_neumann_grows_a_plus() {
+        let mut world = world("B1/SV", vec![(5, 5)]);
+        world.next_day();
+        assert_eq!(
+            sorted(world.alive.clone()),
+            vec![(4, 5), (5, 4), (5, 6), (6, 5)]
+        );
+    }
+
+    #[test]
+    fn hexagonal_grows_a_ring() {
+        // Row 5 is odd, so it is shifted right and its diagonal neighbours lean right
+        let mut world = world("B1/SH", vec![(5, 5)]);
+        world.next_day();
+        assert_eq!(
+            sorted(world.alive.clone()),
+            vec![(4, 5), (4, 6), (5, 4), (5, 6), (6, 5), (6, 6)]
+        );
+    }
+
+    #[test]
+    fn triangular_grows_twelve() {
+        let mut world = world("B1/ST", vec![(5, 5)]);
+        world.next_day();
+        assert_eq!(world.alive.len(), 12);
+        assert!(!world.alive.contains(&(5, 5)));
+    }
+
+    #[test]
+    fn larger_than_life_reaches_further() {
+        let mut world = world("R2,C0,M1,S1..1,B1..1,NM", vec![(5, 5)]);
+        world.next_day();
+        // Every cell in range was born and the cell itself counts as its only neighbour
+        assert_eq!(world.alive.len(), 25);
+    }
+
+    #[test]
+    fn edges_do_not_wrap() {
+        let mut world = world("B1/SV", vec![(0, 0)]);
+        world.next_day();
+        assert_eq!(sorted(world.alive.clone()), vec![(0, 1), (1, 0)]);
+    }
+
+    #[test]
+    fn wireworld_electron_travels_along_a_wire() {
+        let mut world = world("WireWorld", vec![(5, 3), (5, 4), (5, 5), (5, 6)]);
+        world.states = [((5, 3), 2), ((5, 5), 3), ((5, 6), 3)].into();
+        world.next_day();
+        assert_eq!(
+            world.cells(),
+            [((5, 3), 3), ((5, 4), 2), ((5, 5), 1), ((5, 6), 3)].into()
+        );
+    }
+
+    #[test]
+    fn toggling_cycles_through_the_states() {
+        let mut world = world("WireWorld", vec![]);
+        world.toggle((1, 1));
+        world.toggle((1, 1));
+        assert_eq!(world.cells(), [((1, 1), 2)].into());
+        world.toggle((1, 1));
+        world.toggle((1, 1));
+        assert!(world.alive.is_empty());
+    }
+
+    #[test]
+    fn templates_keep_the_rule_and_states() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE templates (
+               id integer primary key,
+               name text not null,
+               width integer not null,
+               height integer not null,
+               alive text not null,
+               rule text
+            )",
+            [],
+        )
+        .unwrap();
+        let mut saved = world("WireWorld", vec![(2, 2), (2, 3)]);
+        saved.states.insert((2, 3), 3);
+        saved.save_current_state(&conn, "wire".to_string()).unwrap();
+
+        let mut loaded = world("B3/S23", vec![]);
+        let (id, name) = loaded.load_alive(&conn).unwrap().remove(0);
+        loaded.load_template(&conn, id).unwrap();
+        assert_eq!(name, "wire");
+        assert_eq!(loaded.rule.to_string(), "WireWorld");
+        assert_eq!(loaded.cells(), saved.cells());
+    }
+
+    #[test]
+    fn templates_of_other_neighbourhoods_keep_the_size_on_screen() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE templates (
+               id integer primary key,
+               name text not null,
+               width integer not null,
+               height integer not null,
+               alive text not null,
+               rule text
+            )",
+            [],
+        )
+        .unwrap();
+        world("B2/S34H", vec![(1, 1)])
+            .save_current_state(&conn, "hex".to_string())
+            .unwrap();
+        world("B3/S23", vec![(1, 1)])
+            .save_current_state(&conn, "moore".to_string())
+            .unwrap();
+
+        let mut loaded = world("B3/S23", vec![]);
+        loaded.load_template(&conn, 1).unwrap();
+        assert_eq!(loaded.width, 6);
+        loaded.load_template(&conn, 2).unwrap();
+        assert_eq!(loaded.width, 12);
+        assert_eq!(World::load_named(&conn, "hex").unwrap().width, 12);
+    }
+
+    #[test]
+    fn cells_need_a_living_state_of_the_rule() {
+        assert_eq!(
+            string_to_alive("1,2:3,4,3", 4).unwrap(),
+            [((1, 2), 1), ((3, 4), 3)].into()
+        );
+        assert!(string_to_alive("3,4,0", 4).is_err());
+        assert!(string_to_alive("3,4,4", 4).is_err());
+        assert!(string_to_alive("3,4,2", 2).is_err());
+        assert!(string_to_alive("3", 2).is_err());
     }
 }
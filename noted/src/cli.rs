@@ -0,0 +1,783 @@
+use crate::editor::edit;
+use crate::notebooks::{get_notebooks, set_notebook};
+use crate::store::{ListOptions, Note, NoteStore, Priority, Sort};
+use crate::tags::{get_tags, normalise, rename_tag};
+use crate::{
+    due, export, highlight, links, markdown, print_conflicts, print_history, print_links,
+    print_notes, print_reminders, session, sync,
+};
+use serde_json::json;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+pub const USAGE: &str = "usage: noted [--db PATH | --profile NAME] [--json] [COMMAND]
+
+without a command noted starts the interactive prompt
+
+  tui                      browse, search and edit notes full-screen
+  add [--title T] TEXT...  add a note and print its id
+  new                      write a new note in $EDITOR and print its id
+  show ID                  print the whole note
+  list [OPTIONS]           print all notes, pinned ones first
+    --sort created|updated   oldest first (default) or latest change first
+    --since DATE             only notes from that day on, as YYYY-MM-DD
+    --until DATE             only notes up to that day
+    --deleted                the deleted notes instead
+    --tag TAG                only notes with that #tag
+    --notebook NAME          only notes in that notebook
+  rm ID                    delete a note
+  edit ID [TEXT...]        replace the text of a note, without TEXT in $EDITOR
+                           unless the note is locked
+  history ID               print every version of a note and what changed
+  restore ID REV           go back to a version from history, undeleting the
+                           note if it was deleted
+  links ID                 print the [[links]] in a note and the notes linking
+                           to it. [[ID]] or [[TITLE]] in a note links to another
+  links                    print the links that lead to no note
+  lock ID                  seal the text of a note with the passphrase, which
+                           is asked for the first time. Only the title stays
+                           readable and locked notes aren't searched
+  unlock ID                turn a locked note back into a plain one
+  lock, unlock             forget the passphrase or type it once, it's kept
+                           for 15 minutes otherwise. $NOTED_PASSPHRASE is
+                           used instead of asking if it's set
+  title ID [TEXT...]       name a note, without a title to remove it
+  pin ID, unpin ID         keep a note at the top of the list or not
+  remind ID [WHEN...]      make a note due, like tomorrow 9:00, friday 5pm,
+                           2024-01-31 or in 2 hours. Without WHEN it no
+                           longer is
+  due [--check]            print overdue and upcoming notes
+    --check                  only the ones that came due since the last
+                             check, for a login script
+  todo [--done]            print the open todos by priority, or the done ones
+  todo ID                  turn a note into a todo
+  todo TEXT...             add a todo and print its id
+  done ID, undone ID       check a todo off or open it again
+  priority ID LEVEL        high, medium, low or none
+  notebook ID [NAME]       move a note into a notebook, without a name out of it
+  notebooks                print all notebooks and how many notes they have
+  tags                     print all #tags and how many notes use them
+  tags rename OLD NEW      rename a tag in every note, merging it into NEW if
+                           that's already used
+  search QUERY...          find notes, best matches first
+  export [--format F] PATH
+                           write every note to PATH, the format is guessed
+                           from the name unless given
+    markdown                 a directory of ID-TITLE.md files with the title,
+                             dates, notebook and tags as front matter
+    json                     a backup of everything, deleted notes included,
+                             as one JSON object per line
+    csv                      the same for spreadsheets
+  sync DIR                 exchange changes with other devices through a shared
+                           directory, like a mounted drive
+  conflicts                print the notes that were changed on two devices
+                           and how the other version differs
+  resolve N keep|other     keep the note as it is or take the other version
+                           of conflict N
+  import [--format F] PATH
+                           read notes exported before, notes with an id that
+                           already exists are updated instead of added.
+                           json and csv keep their dates. PATH - is stdin or
+                           stdout for json and csv
+
+  --json, -j               print results as JSON, before the command
+  --db PATH                the database to use instead of $NOTED_DB or the
+                           default ~/.local/share/noted/notes.db
+  --profile NAME, -p NAME  the database of a profile from
+                           ~/.config/noted/config.toml, or NAME.db next to
+                           the default one
+
+exit codes: 0 done, 1 no such note, nothing found or nothing saved, 2 wrong usage,
+3 anything else";
+
+pub const SUCCESS: i32 = 0;
+pub const NOT_FOUND: i32 = 1;
+pub const USAGE_ERROR: i32 = 2;
+pub const FAILED: i32 = 3;
+
+// Runs a single command and returns the exit code
+pub fn run(args: &[String], store: &NoteStore) -> Result<i32, Box<dyn std::error::Error>> {
+    // Only before the command, after it --json could be part of a note
+    let mut args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let json = matches!(args.first(), Some(&"--json" | &"-j"));
+    if json {
+        args.remove(0);
+    }
+
+    let Some((&cmd, rest)) = args.split_first() else {
+        return Ok(usage("--json needs a command"));
+    };
+
+    match cmd {
+        "add" => {
+            let (title, rest) = match rest {
+                ["--title", title, rest @ ..] => (Some(*title), rest),
+                _ => (None, rest),
+            };
+            if rest.is_empty() {
+                return Ok(usage("add needs the text of the note"));
+            }
+            let id = store.create(title, &rest.join(" "))?;
+            if json {
+                println!("{}", json!({ "id": id }));
+            } else {
+                println!("{}", id);
+            }
+        }
+        "list" => {
+            let options = match ListOptions::parse(rest) {
+                Ok(options) => options,
+                Err(err) => return Ok(usage(&err)),
+            };
+            let notes = store.list(&options)?;
+            if json {
+                let notes: Vec<_> = notes.iter().map(note_json).collect();
+                println!("{}", json!(notes));
+            } else {
+                print_notes(&notes, options.sort);
+            }
+        }
+        "rm" => {
+            let [id] = rest else {
+                return Ok(usage("rm needs the id of a note"));
+            };
+            let id = match find(id, store)? {
+                Ok(id) => id,
+                Err(code) => return Ok(code),
+            };
+            let backlinks = links::get_backlinks(id, store.conn())?;
+            store.delete(id)?;
+            if !backlinks.is_empty() {
+                let sources: Vec<String> = backlinks
+                    .iter()
+                    .map(|(source, _)| source.to_string())
+                    .collect();
+                eprintln!(
+                    "noted: note {} is still linked from {}",
+                    id,
+                    sources.join(", ")
+                );
+            }
+            if json {
+                println!("{}", json!({ "id": id }));
+            }
+        }
+        "new" => {
+            let Some(body) = edit("")?.filter(|body| !body.is_empty()) else {
+                eprintln!("noted: empty note, nothing saved");
+                return Ok(NOT_FOUND);
+            };
+            let id = store.create(None, &body)?;
+            if json {
+                println!("{}", json!({ "id": id }));
+            } else {
+                println!("{}", id);
+            }
+        }
+        "show" => {
+            let [id] = rest else {
+                return Ok(usage("show needs the id of a note"));
+            };
+            let id = match find(id, store)? {
+                Ok(id) => id,
+                Err(code) => return Ok(code),
+            };
+            if store.is_locked(id)? {
+                session::unlock(store)?;
+            }
+            let body = store.body(id)?.unwrap_or_default();
+            if json {
+                println!("{}", json!({ "id": id, "body": body }));
+            } else {
+                println!("{}", body);
+            }
+        }
+        "edit" => {
+            let Some((id, body)) = rest.split_first() else {
+                return Ok(usage("edit needs the id of a note"));
+            };
+            let id = match find(id, store)? {
+                Ok(id) => id,
+                Err(code) => return Ok(code),
+            };
+            if store.is_locked(id)? {
+                // The editor works on a file, which a locked text must never be written to
+                if body.is_empty() {
+                    return Err(format!(
+                        "note {} is locked, give the new text like `noted edit {} TEXT`",
+                        id, id
+                    )
+                    .into());
+                }
+                session::unlock(store)?;
+            }
+            let body = match body {
+                [] => match edit(&store.body(id)?.unwrap_or_default())? {
+                    Some(body) if body.is_empty() => {
+                        eprintln!("noted: empty note, nothing saved");
+                        return Ok(NOT_FOUND);
+                    }
+                    Some(body) => body,
+                    None => {
+                        eprintln!("noted: nothing changed");
+                        return Ok(SUCCESS);
+                    }
+                },
+                body => body.join(" "),
+            };
+            store.update(id, &body)?;
+            if json {
+                println!("{}", json!({ "id": id, "body": body }));
+            }
+        }
+        "title" => {
+            let Some((id, title)) = rest.split_first() else {
+                return Ok(usage("title needs the id of a note"));
+            };
+            let id = match find(id, store)? {
+                Ok(id) => id,
+                Err(code) => return Ok(code),
+            };
+            let title = title.join(" ");
+            let title = Some(title.as_str()).filter(|title| !title.is_empty());
+            store.set_title(id, title)?;
+            if json {
+                println!("{}", json!({ "id": id, "title": title }));
+            }
+        }
+        "pin" | "unpin" => {
+            let [id] = rest else {
+                return Ok(usage(&format!("{} needs the id of a note", cmd)));
+            };
+            let id = match find(id, store)? {
+                Ok(id) => id,
+                Err(code) => return Ok(code),
+            };
+            let pinned = cmd == "pin";
+            store.set_pinned(id, pinned)?;
+            if json {
+                println!("{}", json!({ "id": id, "pinned": pinned }));
+            }
+        }
+        // Deleted notes have a history too, so these don't use find
+        "history" | "restore" => {
+            let (id, rev) = match (cmd, rest) {
+                ("history", [id]) => (id, None),
+                ("restore", [id, rev]) => match rev.parse::<usize>() {
+                    Ok(rev) => (id, Some(rev)),
+                    Err(_) => return Ok(usage(&format!("{} is not a revision", rev))),
+                },
+                ("history", _) => return Ok(usage("history needs the id of a note")),
+                _ => return Ok(usage("restore needs the id of a note and a revision")),
+            };
+            let Ok(id) = id.parse::<usize>() else {
+                return Ok(usage(&format!("{} is not a note id", id)));
+            };
+            if rev.is_none() && store.is_locked(id)? {
+                session::unlock(store)?;
+            }
+            let revisions = store.revisions(id)?;
+            if revisions.is_empty() {
+                eprintln!("noted: there is no note {}", id);
+                return Ok(NOT_FOUND);
+            }
+            match rev {
+                None if json => {
+                    let revisions: Vec<_> = revisions
+                        .iter()
+                        .map(|revision| {
+                            json!({
+                                "rev": revision.rev,
+                                "title": revision.title,
+                                "body": revision.body,
+                                "deleted": revision.deleted,
+                                "saved_at": revision.saved_at,
+                            })
+                        })
+                        .collect();
+                    println!("{}", json!(revisions));
+                }
+                None => print_history(&revisions),
+                Some(rev) => {
+                    if !store.restore(id, rev)? {
+                        eprintln!("noted: note {} has no revision {}", id, rev);
+                        return Ok(NOT_FOUND);
+                    }
+                    if json {
+                        println!("{}", json!({ "id": id, "rev": rev }));
+                    }
+                }
+            }
+        }
+        "links" => match rest {
+            [] => {
+                let dangling = links::get_dangling(store.conn())?;
+                if json {
+                    let dangling: Vec<_> = dangling
+                        .iter()
+                        .map(|(id, target)| json!({ "id": id, "target": target }))
+                        .collect();
+                    println!("{}", json!(dangling));
+                } else {
+                    for (id, target) in &dangling {
+                        println!("{}: [[{}]]", id, target);
+                    }
+                }
+            }
+            [id] => {
+                let id = match find(id, store)? {
+                    Ok(id) => id,
+                    Err(code) => return Ok(code),
+                };
+                let outgoing = links::get_links(id, store.conn())?;
+                let backlinks = links::get_backlinks(id, store.conn())?;
+                if json {
+                    let outgoing: Vec<_> = outgoing
+                        .iter()
+                        .map(|link| {
+                            json!({
+                                "target": link.target,
+                                "id": link.note.as_ref().map(|(id, _)| id),
+                            })
+                        })
+                        .collect();
+                    let backlinks: Vec<_> = backlinks.iter().map(|(id, _)| id).collect();
+                    println!("{}", json!({ "links": outgoing, "backlinks": backlinks }));
+                } else {
+                    print_links(&outgoing, &backlinks);
+                }
+            }
+            _ => return Ok(usage("links takes the id of a note or nothing")),
+        },
+        "lock" | "unlock" => match rest {
+            [] if cmd == "lock" => session::forget(store)?,
+            // Unlocking shouldn't be the way a passphrase is set
+            [] if !store.has_passphrase()? => {
+                eprintln!("noted: nothing is locked yet, lock ID locks a note");
+                return Ok(NOT_FOUND);
+            }
+            [] => session::unlock(store)?,
+            [id] => {
+                let id = match find(id, store)? {
+                    Ok(id) => id,
+                    Err(code) => return Ok(code),
+                };
+                if cmd == "unlock" && !store.is_locked(id)? {
+                    eprintln!("noted: note {} isn't locked", id);
+                    return Ok(SUCCESS);
+                }
+                session::unlock(store)?;
+                match cmd {
+                    "lock" => store.lock(id)?,
+                    _ => store.unlock_note(id)?,
+                }
+                if json {
+                    println!("{}", json!({ "id": id, "locked": cmd == "lock" }));
+                }
+            }
+            _ => return Ok(usage(&format!("{} takes the id of a note or nothing", cmd))),
+        },
+        "remind" => {
+            let Some((id, when)) = rest.split_first() else {
+                return Ok(usage("remind needs the id of a note"));
+            };
+            let id = match find(id, store)? {
+                Ok(id) => id,
+                Err(code) => return Ok(code),
+            };
+            let when = match when {
+                [] => None,
+                when => match due::parse(&when.join(" ")) {
+                    Ok(when) => Some(when),
+                    Err(err) => return Ok(usage(&err)),
+                },
+            };
+            let due = store.set_due(id, when.as_ref())?;
+            if json {
+                println!("{}", json!({ "id": id, "due": due }));
+            } else if let Some(due) = due {
+                println!("due {}", due);
+            }
+        }
+        "due" => {
+            let check = match rest {
+                [] => false,
+                ["--check"] => true,
+                _ => return Ok(usage("due only takes --check")),
+            };
+            let reminders = match check {
+                true => store.fire_reminders()?,
+                false => store.reminders()?,
+            };
+            if json {
+                let reminders: Vec<_> = reminders
+                    .iter()
+                    .map(|reminder| {
+                        json!({
+                            "id": reminder.id,
+                            "title": reminder.title,
+                            "body": reminder.body,
+                            "due": reminder.due,
+                            "overdue": reminder.overdue,
+                        })
+                    })
+                    .collect();
+                println!("{}", json!(reminders));
+            } else {
+                print_reminders(&reminders);
+            }
+            // Login scripts shouldn't fail just because nothing is due
+            if reminders.is_empty() && !check {
+                return Ok(NOT_FOUND);
+            }
+        }
+        "todo" => match rest {
+            [] | ["--done"] => {
+                let todos = store.todos(!rest.is_empty())?;
+                if json {
+                    let todos: Vec<_> = todos.iter().map(note_json).collect();
+                    println!("{}", json!(todos));
+                } else {
+                    print_notes(&todos, Sort::Created);
+                }
+            }
+            [id] if id.parse::<usize>().is_ok() => {
+                let id = match find(id, store)? {
+                    Ok(id) => id,
+                    Err(code) => return Ok(code),
+                };
+                store.make_todo(id)?;
+                if json {
+                    println!("{}", json!({ "id": id, "todo": true }));
+                }
+            }
+            text => {
+                let id = store.create_todo(&text.join(" "))?;
+                if json {
+                    println!("{}", json!({ "id": id }));
+                } else {
+                    println!("{}", id);
+                }
+            }
+        },
+        "done" | "undone" => {
+            let [id] = rest else {
+                return Ok(usage(&format!("{} needs the id of a note", cmd)));
+            };
+            let id = match find(id, store)? {
+                Ok(id) => id,
+                Err(code) => return Ok(code),
+            };
+            let done = cmd == "done";
+            store.set_done(id, done)?;
+            if json {
+                println!("{}", json!({ "id": id, "done": done }));
+            }
+        }
+        "priority" => {
+            let [id, level] = rest else {
+                return Ok(usage("priority needs the id of a note and a level"));
+            };
+            let Some(priority) = Priority::parse(level) else {
+                return Ok(usage("the priority is high, medium, low or none"));
+            };
+            let id = match find(id, store)? {
+                Ok(id) => id,
+                Err(code) => return Ok(code),
+            };
+            store.set_priority(id, priority)?;
+            if json {
+                println!("{}", json!({ "id": id, "priority": priority.name() }));
+            }
+        }
+        "notebook" => {
+            let Some((id, notebook)) = rest.split_first() else {
+                return Ok(usage("notebook needs the id of a note"));
+            };
+            let id = match find(id, store)? {
+                Ok(id) => id,
+                Err(code) => return Ok(code),
+            };
+            let notebook = notebook.join(" ");
+            let notebook = Some(notebook.as_str()).filter(|notebook| !notebook.is_empty());
+            set_notebook(id, notebook, store.conn())?;
+            if json {
+                println!("{}", json!({ "id": id, "notebook": notebook }));
+            }
+        }
+        "notebooks" => {
+            let notebooks = get_notebooks(store.conn())?;
+            if json {
+                let notebooks: Vec<_> = notebooks
+                    .iter()
+                    .map(|(name, notes)| json!({ "name": name, "notes": notes }))
+                    .collect();
+                println!("{}", json!(notebooks));
+            } else {
+                for (name, notes) in notebooks {
+                    println!("{} ({})", name, notes);
+                }
+            }
+        }
+        "tags" => match rest {
+            [] => {
+                let tags = get_tags(store.conn())?;
+                if json {
+                    let tags: Vec<_> = tags
+                        .iter()
+                        .map(|(name, notes)| json!({ "name": name, "notes": notes }))
+                        .collect();
+                    println!("{}", json!(tags));
+                } else {
+                    for (name, notes) in tags {
+                        println!("#{} ({})", name, notes);
+                    }
+                }
+            }
+            ["rename" | "merge", old, new] => {
+                let (Some(old), Some(new)) = (normalise(old), normalise(new)) else {
+                    return Ok(usage("tags are made of letters, digits, -, _ and /"));
+                };
+                if old == new {
+                    eprintln!("noted: #{} is already called that, nothing changed", old);
+                    return Ok(NOT_FOUND);
+                }
+                let (notes, merged) = rename_tag(&old, &new, store.conn())?;
+                if json {
+                    println!(
+                        "{}",
+                        json!({ "from": old, "to": new, "notes": notes, "merged": merged })
+                    );
+                }
+            }
+            _ => return Ok(usage("tags takes no arguments or rename OLD NEW")),
+        },
+        "search" => {
+            if rest.is_empty() {
+                return Ok(usage("search needs a query"));
+            }
+            let highlight = if json { ("[", "]") } else { highlight() };
+            let results = match store.search(&rest.join(" "), highlight) {
+                Ok(results) => results,
+                Err(err) => return Ok(usage(&format!("not a valid search: {}", err))),
+            };
+            if json {
+                let results: Vec<_> = results
+                    .iter()
+                    .map(|(id, snippet)| json!({ "id": id, "snippet": snippet }))
+                    .collect();
+                println!("{}", json!(results));
+            } else {
+                for (id, snippet) in &results {
+                    println!("{}: {}", id, snippet);
+                }
+            }
+            // Like grep, finding nothing is worth telling scripts about
+            if results.is_empty() {
+                return Ok(NOT_FOUND);
+            }
+        }
+        "export" => {
+            let (format, path) = match format_and_path(cmd, rest) {
+                Ok(format_and_path) => format_and_path,
+                Err(code) => return Ok(code),
+            };
+            let exported = match format {
+                "markdown" => markdown::export(Path::new(path), store.conn())?,
+                _ => {
+                    let out: Box<dyn Write> = match path {
+                        "-" => Box::new(io::stdout().lock()),
+                        path => Box::new(File::create(path)?),
+                    };
+                    let out = BufWriter::new(out);
+                    match format {
+                        "json" => export::export_json(out, store.conn())?,
+                        _ => export::export_csv(out, store.conn())?,
+                    }
+                }
+            };
+            if json && path != "-" {
+                println!(
+                    "{}",
+                    json!({ "exported": exported.written, "locked": exported.locked })
+                );
+            } else {
+                eprintln!("exported {} notes", exported.written);
+            }
+            if exported.locked > 0 {
+                eprintln!(
+                    "left out {} locked notes, only json backups have them",
+                    exported.locked
+                );
+            }
+        }
+        "import" => {
+            let (format, path) = match format_and_path(cmd, rest) {
+                Ok(format_and_path) => format_and_path,
+                Err(code) => return Ok(code),
+            };
+            let imported = match format {
+                "markdown" => markdown::import(Path::new(path), store.conn())?,
+                _ => {
+                    let input: Box<dyn Read> = match path {
+                        "-" => Box::new(io::stdin().lock()),
+                        path => Box::new(File::open(path)?),
+                    };
+                    let input = BufReader::new(input);
+                    match format {
+                        "json" => export::import_json(input, store.conn())?,
+                        _ => export::import_csv(input, store.conn())?,
+                    }
+                }
+            };
+            if json {
+                println!(
+                    "{}",
+                    json!({
+                        "created": imported.created,
+                        "updated": imported.updated,
+                        "unchanged": imported.unchanged,
+                    })
+                );
+            } else {
+                eprintln!(
+                    "{} new, {} updated, {} unchanged",
+                    imported.created, imported.updated, imported.unchanged
+                );
+            }
+        }
+        "sync" => {
+            let [dir] = rest else {
+                return Ok(usage("sync needs the shared directory"));
+            };
+            session::resume(store)?;
+            let synced = sync::sync(Path::new(dir), store.conn(), store.key().as_deref())?;
+            if json {
+                println!(
+                    "{}",
+                    json!({
+                        "sent": synced.sent,
+                        "received": synced.received,
+                        "conflicts": synced.conflicts,
+                        "locked": synced.locked,
+                    })
+                );
+            } else {
+                eprintln!(
+                    "sent {}, received {}, {} conflicts",
+                    synced.sent, synced.received, synced.conflicts
+                );
+                if synced.locked > 0 {
+                    eprintln!("noted: {} locked notes weren't sent", synced.locked);
+                }
+            }
+        }
+        "conflicts" => {
+            if !rest.is_empty() {
+                return Ok(usage("conflicts takes no arguments"));
+            }
+            let conflicts = sync::get_conflicts(store.conn())?;
+            if json {
+                let side = |side: &sync::Side| json!({ "title": side.title, "body": side.body, "deleted": side.deleted });
+                let conflicts: Vec<_> = conflicts
+                    .iter()
+                    .map(|conflict| {
+                        json!({
+                            "id": conflict.id,
+                            "note": conflict.note_id,
+                            "current": side(&conflict.current),
+                            "other": side(&conflict.other),
+                            "locked": conflict.locked,
+                            "found_at": conflict.found_at,
+                        })
+                    })
+                    .collect();
+                println!("{}", json!(conflicts));
+            } else {
+                print_conflicts(&conflicts);
+            }
+        }
+        "resolve" => {
+            let [conflict, choice] = rest else {
+                return Ok(usage(
+                    "resolve needs the number of a conflict and keep or other",
+                ));
+            };
+            let Ok(conflict) = conflict.parse::<usize>() else {
+                return Ok(usage(&format!("{} is not a conflict", conflict)));
+            };
+            let take_other = match *choice {
+                "keep" => false,
+                "other" => true,
+                _ => return Ok(usage("resolve takes keep or other")),
+            };
+            if !sync::resolve(conflict, take_other, store.conn())? {
+                eprintln!("noted: there is no conflict {}", conflict);
+                return Ok(NOT_FOUND);
+            }
+            if json {
+                println!("{}", json!({ "id": conflict, "other": take_other }));
+            }
+        }
+        "tui" => crate::tui::run(store)?,
+        "help" | "--help" | "-h" => println!("{}", USAGE),
+        other => return Ok(usage(&format!("unknown command {}", other))),
+    }
+
+    Ok(SUCCESS)
+}
+
+// [--format markdown|json|csv] PATH, without --format it's guessed from the file name.
+// - is stdin or stdout, which only works for json and csv.
+fn format_and_path<'a>(cmd: &str, args: &[&'a str]) -> Result<(&'a str, &'a str), i32> {
+    let (format, path) = match args {
+        ["--format", format, path] => (*format, *path),
+        [path] if path.ends_with(".json") || path.ends_with(".jsonl") => ("json", *path),
+        [path] if path.ends_with(".csv") => ("csv", *path),
+        [path] => ("markdown", *path),
+        _ => return Err(usage(&format!("{} needs a directory or file", cmd))),
+    };
+    match (format, path) {
+        ("markdown", "-") => Err(usage(
+            "markdown needs a directory, - only works for json and csv",
+        )),
+        ("markdown" | "json" | "csv", _) => Ok((format, path)),
+        _ => Err(usage(&format!("unknown format {}", format))),
+    }
+}
+
+fn note_json(note: &Note) -> serde_json::Value {
+    json!({
+        "id": note.id,
+        "title": note.title,
+        "body": note.body,
+        "created_at": note.created_at,
+        "updated_at": note.updated_at,
+        "pinned": note.pinned,
+        "notebook": note.notebook,
+        "tags": note.tags,
+        "due": note.due,
+        "todo": note.todo,
+        "priority": note.priority.name(),
+        "done_at": note.done_at,
+        "locked": note.locked,
+    })
+}
+
+// The exit code to stop with for ids that aren't numbers or don't belong to a note
+fn find(id: &str, store: &NoteStore) -> Result<Result<usize, i32>, Box<dyn std::error::Error>> {
+    let Ok(parsed) = id.parse::<usize>() else {
+        return Ok(Err(usage(&format!("{} is not a note id", id))));
+    };
+    if !store.exists(parsed)? {
+        eprintln!("noted: there is no note {}", id);
+        return Ok(Err(NOT_FOUND));
+    }
+    Ok(Ok(parsed))
+}
+
+fn usage(message: &str) -> i32 {
+    eprintln!("noted: {}\n\n{}", message, USAGE);
+    USAGE_ERROR
+}
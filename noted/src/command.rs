@@ -0,0 +1,708 @@
+use crate::due::{self, When};
+use crate::store::{ListOptions, Priority};
+
+// Everything that can be typed at the prompt
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    // A line that isn't a command is a new entry
+    Create(String),
+    Delete(usize),
+    // Without a text the entry is opened in the editor
+    Edit(usize, Option<String>),
+    New,
+    Show(usize),
+    // None removes the title or takes the entry out of its notebook
+    Title(usize, Option<String>),
+    Pin(usize, bool),
+    // None means it's no longer due
+    Remind(usize, Option<When>),
+    Due,
+    NewTodo(String),
+    // Turns an entry into a todo
+    Todo(usize),
+    // The done ones instead of the open ones
+    Todos(bool),
+    Done(usize, bool),
+    Priority(usize, Priority),
+    Notebook(usize, Option<String>),
+    History(usize),
+    // The id of the entry and the revision to go back to
+    Restore(usize, usize),
+    // Without an id it's about the passphrase of the session instead of an entry
+    Lock(Option<usize>),
+    // The links of an entry and the ones to it, without an id the links that lead nowhere
+    Links(Option<usize>),
+    Unlock(Option<usize>),
+    // The shared directory
+    Sync(String),
+    Conflicts,
+    // The id of the conflict and whether to take the other version instead of keeping the entry
+    Resolve(usize, bool),
+    Notebooks,
+    Tags,
+    RenameTag(String, String),
+    List(ListOptions),
+    Search(String),
+    Help,
+    Quit,
+}
+
+struct Spec {
+    name: &'static str,
+    short: Option<&'static str>,
+    // Whether the first argument is the id of an entry, for completion
+    takes_id: bool,
+    help: &'static str,
+}
+
+const COMMANDS: &[Spec] = &[
+    Spec {
+        name: "/del",
+        short: Some("/d"),
+        takes_id: true,
+        help: "/del [id] for deleting an entry",
+    },
+    Spec {
+        name: "/new",
+        short: Some("/n"),
+        takes_id: false,
+        help: "/new for writing a new entry in $EDITOR",
+    },
+    Spec {
+        name: "/edit",
+        short: Some("/e"),
+        takes_id: true,
+        help: "/edit [id] [entry] for editing an entry, without [entry] in $EDITOR",
+    },
+    Spec {
+        name: "/show",
+        short: None,
+        takes_id: true,
+        help: "/show [id] for the whole entry",
+    },
+    Spec {
+        name: "/title",
+        short: Some("/t"),
+        takes_id: true,
+        help: "/title [id] [title] for naming an entry, without a title to remove it",
+    },
+    Spec {
+        name: "/pin",
+        short: None,
+        takes_id: true,
+        help: "/pin [id] and /unpin [id] for keeping an entry at the top of the list",
+    },
+    Spec {
+        name: "/unpin",
+        short: None,
+        takes_id: true,
+        help: "",
+    },
+    Spec {
+        name: "/history",
+        short: None,
+        takes_id: true,
+        help: "/history [id] for every version of an entry and what changed in it",
+    },
+    Spec {
+        name: "/restore",
+        short: None,
+        takes_id: true,
+        help: "/restore [id] [rev] for going back to a version from /history, also for deleted entries",
+    },
+    Spec {
+        name: "/links",
+        short: None,
+        takes_id: true,
+        help: "/links [id] for the [[links]] in an entry and the entries linking to it,\n\t\t \
+               /links alone for the links to entries that don't exist (anymore)",
+    },
+    Spec {
+        name: "/lock",
+        short: None,
+        takes_id: true,
+        help: "/lock [id] for sealing an entry with the passphrase, /lock alone forgets the passphrase",
+    },
+    Spec {
+        name: "/unlock",
+        short: None,
+        takes_id: true,
+        help: "/unlock [id] for turning a locked entry back into a plain one,\n\t\t \
+               /unlock alone asks for the passphrase once for the locked entries",
+    },
+    Spec {
+        name: "/remind",
+        short: Some("/r"),
+        takes_id: true,
+        help: "/remind [id] [when] for a due date like tomorrow 9:00, friday 5pm or in 2 hours,
+\t\t without [when] the entry is no longer due",
+    },
+    Spec {
+        name: "/due",
+        short: None,
+        takes_id: false,
+        help: "/due for listing overdue and upcoming entries",
+    },
+    Spec {
+        name: "/todo",
+        short: None,
+        takes_id: true,
+        help: "/todo for listing the open todos, /todo --done for the done ones
+\t /todo [entry] for writing a new todo and /todo [id] for turning an entry into one",
+    },
+    Spec {
+        name: "/done",
+        short: None,
+        takes_id: true,
+        help: "/done [id] and /undone [id] for checking a todo off or opening it again",
+    },
+    Spec {
+        name: "/undone",
+        short: None,
+        takes_id: true,
+        help: "",
+    },
+    Spec {
+        name: "/priority",
+        short: Some("/p"),
+        takes_id: true,
+        help: "/priority [id] high|medium|low|none for how urgent a todo is",
+    },
+    Spec {
+        name: "/list",
+        short: Some("/l"),
+        takes_id: false,
+        help: "/list for listing all entries, options:
+\t\t --sort created|updated, oldest first or latest change first
+\t\t --since and --until [YYYY-MM-DD] to only show some days
+\t\t --deleted for the deleted entries
+\t\t --tag [tag] and --notebook [name] for the entries with that tag or in that notebook",
+    },
+    Spec {
+        name: "/notebook",
+        short: Some("/nb"),
+        takes_id: true,
+        help: "/notebook [id] [name] for moving an entry into a notebook, without a name to take it out",
+    },
+    Spec {
+        name: "/notebooks",
+        short: None,
+        takes_id: false,
+        help: "/notebooks for listing all notebooks",
+    },
+    Spec {
+        name: "/tags",
+        short: None,
+        takes_id: false,
+        help: "/tags for listing all #tags used in entries
+\t /tags rename [old] [new] for renaming a tag in every entry, merging it if the new one exists",
+    },
+    Spec {
+        name: "/search",
+        short: Some("/s"),
+        takes_id: false,
+        help: "/search [query] for finding entries, best matches first",
+    },
+    Spec {
+        name: "/sync",
+        short: None,
+        takes_id: false,
+        help: "/sync [dir] for exchanging changes with other devices through a shared directory",
+    },
+    Spec {
+        name: "/conflicts",
+        short: None,
+        takes_id: false,
+        help: "/conflicts for the entries that were changed on two devices at once",
+    },
+    Spec {
+        name: "/resolve",
+        short: None,
+        takes_id: false,
+        help: "/resolve [conflict] keep|other for keeping the entry as it is or taking the other version",
+    },
+    Spec {
+        name: "/help",
+        short: Some("/h"),
+        takes_id: false,
+        help: "",
+    },
+    Spec {
+        name: "/quit",
+        short: None,
+        takes_id: false,
+        help: "/quit, /exit or Ctrl-D for leaving",
+    },
+    Spec {
+        name: "/exit",
+        short: None,
+        takes_id: false,
+        help: "",
+    },
+];
+
+const LIST_OPTIONS: &[&str] = &[
+    "--sort",
+    "--since",
+    "--until",
+    "--deleted",
+    "--tag",
+    "--notebook",
+];
+
+fn spec(name: &str) -> Option<&'static Spec> {
+    COMMANDS
+        .iter()
+        .find(|spec| spec.name == name || spec.short == Some(name))
+}
+
+pub fn help() -> String {
+    let mut help: Vec<String> = COMMANDS
+        .iter()
+        .filter(|spec| !spec.help.is_empty())
+        .map(|spec| format!("\t {}", spec.help))
+        .collect();
+    let shorts: Vec<&str> = COMMANDS.iter().filter_map(|spec| spec.short).collect();
+    help.push(format!(
+        "\t Hint: {} and {} also work, arguments with spaces can be \"quoted\"
+\t and a \\ at the end of a line goes on in the next one",
+        shorts[..shorts.len() - 1].join(", "),
+        shorts[shorts.len() - 1]
+    ));
+    help.join("\n")
+}
+
+// The arguments after the command. Words are split at whitespace unless it's inside
+// "double" quotes (where \" and \\ are escapes) or 'single' quotes.
+struct Words<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Words<'a> {
+    fn next(&mut self) -> Result<Option<String>, String> {
+        self.rest = self.rest.trim_start();
+        if self.rest.is_empty() {
+            return Ok(None);
+        }
+
+        let mut word = String::new();
+        let mut chars = self.rest.char_indices();
+        let mut end = self.rest.len();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                c if c.is_whitespace() => {
+                    end = i;
+                    break;
+                }
+                '"' => loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c)) => word.push(c),
+                            None => return Err("missing a closing \"".to_string()),
+                        },
+                        Some((_, c)) => word.push(c),
+                        None => return Err("missing a closing \"".to_string()),
+                    }
+                },
+                '\'' => loop {
+                    match chars.next() {
+                        Some((_, '\'')) => break,
+                        Some((_, c)) => word.push(c),
+                        None => return Err("missing a closing '".to_string()),
+                    }
+                },
+                c => word.push(c),
+            }
+        }
+        self.rest = &self.rest[end..];
+        Ok(Some(word))
+    }
+
+    // Everything that's left as typed, for texts where quotes may be part of the entry
+    fn raw(&mut self) -> Option<String> {
+        let rest = std::mem::take(&mut self.rest).trim();
+        (!rest.is_empty()).then(|| rest.to_string())
+    }
+
+    // Everything that's left, without the quotes if it's a single quoted word
+    fn text(&mut self) -> Option<String> {
+        let rest = self.rest;
+        if rest.trim_start().starts_with(['"', '\'']) {
+            if let Ok(Some(word)) = self.next() {
+                if self.rest.trim().is_empty() {
+                    return Some(word);
+                }
+            }
+        }
+        self.rest = rest;
+        self.raw()
+    }
+
+    fn all(&mut self) -> Result<Vec<String>, String> {
+        let mut words = vec![];
+        while let Some(word) = self.next()? {
+            words.push(word);
+        }
+        Ok(words)
+    }
+
+    fn id(&mut self, cmd: &str) -> Result<usize, String> {
+        let word = self
+            .next()?
+            .ok_or_else(|| format!("{} needs the id of an entry", cmd))?;
+        word.parse()
+            .map_err(|_| format!("{} is not the id of an entry", word))
+    }
+
+    fn only_id(&mut self, cmd: &str) -> Result<usize, String> {
+        let id = self.id(cmd)?;
+        match self.next()? {
+            Some(_) => Err(format!("{} only takes the id of an entry", cmd)),
+            None => Ok(id),
+        }
+    }
+}
+
+pub fn parse(line: &str) -> Result<Command, String> {
+    let line = line.trim();
+    if !line.starts_with('/') {
+        return Ok(Command::Create(line.to_string()));
+    }
+
+    let (cmd, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let Some(spec) = spec(cmd) else {
+        return Err(format!(
+            "{} is not a valid command, /help lists all of them",
+            cmd
+        ));
+    };
+    let cmd = spec.name;
+    let mut words = Words { rest };
+
+    let command = match cmd {
+        "/del" => Command::Delete(words.only_id(cmd)?),
+        "/new" => Command::New,
+        "/edit" => Command::Edit(words.id(cmd)?, words.raw()),
+        "/show" => Command::Show(words.only_id(cmd)?),
+        "/title" => Command::Title(words.id(cmd)?, words.text()),
+        "/pin" => Command::Pin(words.only_id(cmd)?, true),
+        "/unpin" => Command::Pin(words.only_id(cmd)?, false),
+        "/history" => Command::History(words.only_id(cmd)?),
+        "/restore" => {
+            let id = words.id(cmd)?;
+            let rev = match words.all()?.as_slice() {
+                [rev] => rev
+                    .parse()
+                    .map_err(|_| format!("{} is not a revision", rev))?,
+                _ => return Err(format!("{} needs the id of an entry and a revision", cmd)),
+            };
+            Command::Restore(id, rev)
+        }
+        "/links" => match rest.trim() {
+            "" => Command::Links(None),
+            _ => Command::Links(Some(words.only_id(cmd)?)),
+        },
+        "/lock" | "/unlock" => {
+            let id = match rest.trim() {
+                "" => None,
+                _ => Some(words.only_id(cmd)?),
+            };
+            match cmd {
+                "/lock" => Command::Lock(id),
+                _ => Command::Unlock(id),
+            }
+        }
+        "/remind" => Command::Remind(
+            words.id(cmd)?,
+            words.raw().map(|when| due::parse(&when)).transpose()?,
+        ),
+        "/due" => Command::Due,
+        "/todo" => match words.raw() {
+            None => Command::Todos(false),
+            Some(rest) if rest == "--done" => Command::Todos(true),
+            Some(rest) => match rest.parse() {
+                Ok(id) => Command::Todo(id),
+                Err(_) => Command::NewTodo(rest),
+            },
+        },
+        "/done" => Command::Done(words.only_id(cmd)?, true),
+        "/undone" => Command::Done(words.only_id(cmd)?, false),
+        "/priority" => {
+            let id = words.id(cmd)?;
+            let priority = words
+                .next()?
+                .and_then(|word| Priority::parse(&word))
+                .ok_or("The priority is high, medium, low or none")?;
+            if words.next()?.is_some() {
+                return Err(format!("{} only takes an id and a priority", cmd));
+            }
+            Command::Priority(id, priority)
+        }
+        "/notebook" => Command::Notebook(words.id(cmd)?, words.text()),
+        "/notebooks" => Command::Notebooks,
+        "/tags" => match words.all()?.as_slice() {
+            [] => Command::Tags,
+            [verb, old, new] if verb == "rename" || verb == "merge" => {
+                Command::RenameTag(old.clone(), new.clone())
+            }
+            _ => return Err("Use /tags or /tags rename [old] [new]".to_string()),
+        },
+        "/list" => {
+            let words = words.all()?;
+            let words: Vec<&str> = words.iter().map(String::as_str).collect();
+            Command::List(ListOptions::parse(&words)?)
+        }
+        // FTS5 has its own quotes for phrases, so the query stays as it was typed
+        "/search" => Command::Search(words.raw().ok_or("What are you looking for?")?),
+        "/sync" => Command::Sync(words.text().ok_or("/sync needs the shared directory")?),
+        "/conflicts" => Command::Conflicts,
+        "/resolve" => {
+            let conflict = words
+                .next()?
+                .and_then(|word| word.parse().ok())
+                .ok_or("/resolve needs the number of a conflict, /conflicts lists them")?;
+            let take_other = match words.all()?.as_slice() {
+                [choice] if choice == "keep" => false,
+                [choice] if choice == "other" => true,
+                _ => {
+                    return Err(
+                        "Use /resolve [conflict] keep or /resolve [conflict] other".to_string()
+                    )
+                }
+            };
+            Command::Resolve(conflict, take_other)
+        }
+        "/help" => Command::Help,
+        _ => Command::Quit,
+    };
+
+    // Commands that don't take anything shouldn't silently ignore what was typed
+    let takes_nothing = matches!(
+        command,
+        Command::New
+            | Command::Due
+            | Command::Conflicts
+            | Command::Notebooks
+            | Command::Help
+            | Command::Quit
+    );
+    if takes_nothing && !rest.trim().is_empty() {
+        return Err(format!("{} doesn't take any arguments", cmd));
+    }
+    Ok(command)
+}
+
+// Start of the word being completed and (shown, replacement) pairs for it.
+// Completes commands, the ids of entries with their first line and options of /list and /tags.
+pub fn complete(line: &str, entries: &[(usize, String)]) -> (usize, Vec<(String, String)>) {
+    let start = line.rfind(char::is_whitespace).map_or(0, |space| space + 1);
+    let word = &line[start..];
+
+    if start == 0 {
+        if !word.starts_with('/') {
+            return (0, vec![]);
+        }
+        let commands = COMMANDS
+            .iter()
+            .filter(|spec| spec.name.starts_with(word))
+            .map(|spec| (spec.name.to_string(), format!("{} ", spec.name)))
+            .collect();
+        return (0, commands);
+    }
+
+    let mut before = line[..start].split_whitespace();
+    let Some(spec) = before.next().and_then(spec) else {
+        return (start, vec![]);
+    };
+    let argument = before.count();
+
+    let candidates = match (spec.name, argument) {
+        (_, 0) if spec.takes_id => entries
+            .iter()
+            .filter(|(id, _)| id.to_string().starts_with(word))
+            .map(|(id, label)| (format!("{}: {}", id, label), format!("{} ", id)))
+            .collect(),
+        ("/tags", 0) => ["rename", "merge"]
+            .iter()
+            .filter(|verb| verb.starts_with(word))
+            .map(|verb| (verb.to_string(), format!("{} ", verb)))
+            .collect(),
+        ("/list", _) if word.starts_with('-') => LIST_OPTIONS
+            .iter()
+            .filter(|option| option.starts_with(word))
+            .map(|option| (option.to_string(), format!("{} ", option)))
+            .collect(),
+        _ => vec![],
+    };
+    (start, candidates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_and_their_short_forms() {
+        assert_eq!(parse("/d 3"), Ok(Command::Delete(3)));
+        assert_eq!(parse("/del 3"), Ok(Command::Delete(3)));
+        assert_eq!(parse("/unpin 2"), Ok(Command::Pin(2, false)));
+        assert_eq!(parse("/exit"), Ok(Command::Quit));
+        assert_eq!(parse("/h"), Ok(Command::Help));
+        assert_eq!(parse("  /notebooks  "), Ok(Command::Notebooks));
+        assert_eq!(
+            parse("/remind 3 tomorrow 9:00"),
+            Ok(Command::Remind(
+                3,
+                Some(When::At(Some(due::Day::Tomorrow), (9, 0)))
+            ))
+        );
+        assert_eq!(parse("/r 3"), Ok(Command::Remind(3, None)));
+        assert!(parse("/remind 3 someday").is_err());
+        assert_eq!(parse("/restore 3 2"), Ok(Command::Restore(3, 2)));
+        assert!(parse("/restore 3").is_err());
+        assert!(parse("/restore 3 two").is_err());
+        assert_eq!(parse("/links"), Ok(Command::Links(None)));
+        assert_eq!(parse("/links 3"), Ok(Command::Links(Some(3))));
+        assert_eq!(parse("/lock"), Ok(Command::Lock(None)));
+        assert_eq!(parse("/unlock 3"), Ok(Command::Unlock(Some(3))));
+        assert!(parse("/lock 3 4").is_err());
+        assert_eq!(
+            parse("/sync \"/mnt/usb stick/noted\""),
+            Ok(Command::Sync("/mnt/usb stick/noted".to_string()))
+        );
+        assert_eq!(parse("/resolve 2 other"), Ok(Command::Resolve(2, true)));
+        assert!(parse("/resolve 2").is_err());
+    }
+
+    #[test]
+    fn todo_takes_an_id_or_a_new_entry() {
+        assert_eq!(parse("/todo"), Ok(Command::Todos(false)));
+        assert_eq!(parse("/todo --done"), Ok(Command::Todos(true)));
+        assert_eq!(parse("/todo 4"), Ok(Command::Todo(4)));
+        assert_eq!(
+            parse("/todo 2 eggs"),
+            Ok(Command::NewTodo("2 eggs".to_string()))
+        );
+        assert_eq!(parse("/undone 4"), Ok(Command::Done(4, false)));
+        assert_eq!(parse("/p 4 High"), Ok(Command::Priority(4, Priority::High)));
+        assert!(parse("/p 4 urgent").is_err());
+        assert!(parse("/p 4 high now").is_err());
+    }
+
+    #[test]
+    fn other_lines_are_new_entries() {
+        assert_eq!(
+            parse("buy \"oat\" milk #home"),
+            Ok(Command::Create("buy \"oat\" milk #home".to_string()))
+        );
+    }
+
+    #[test]
+    fn entries_can_have_more_than_one_line() {
+        assert_eq!(
+            parse("first\nsecond"),
+            Ok(Command::Create("first\nsecond".to_string()))
+        );
+        assert_eq!(
+            parse("/e 3\nfirst\n  second"),
+            Ok(Command::Edit(3, Some("first\n  second".to_string())))
+        );
+    }
+
+    #[test]
+    fn ids_are_checked() {
+        assert_eq!(
+            parse("/d abc"),
+            Err("abc is not the id of an entry".to_string())
+        );
+        assert_eq!(
+            parse("/d"),
+            Err("/del needs the id of an entry".to_string())
+        );
+        assert_eq!(
+            parse("/d 1 2"),
+            Err("/del only takes the id of an entry".to_string())
+        );
+        assert!(parse("/show -1").is_err());
+        assert!(parse("/new 3").is_err());
+    }
+
+    #[test]
+    fn unknown_commands_are_errors() {
+        assert!(parse("/dance").unwrap_err().contains("/dance"));
+    }
+
+    #[test]
+    fn texts_keep_what_was_typed() {
+        assert_eq!(
+            parse("/e 3 she said \"hi\""),
+            Ok(Command::Edit(3, Some("she said \"hi\"".to_string())))
+        );
+        assert_eq!(parse("/e 3"), Ok(Command::Edit(3, None)));
+        assert_eq!(
+            parse("/s \"exact phrase\""),
+            Ok(Command::Search("\"exact phrase\"".to_string()))
+        );
+        assert!(parse("/s").is_err());
+    }
+
+    #[test]
+    fn quotes_group_words() {
+        assert_eq!(
+            parse("/nb 3 \"Work Stuff\""),
+            Ok(Command::Notebook(3, Some("Work Stuff".to_string())))
+        );
+        assert_eq!(
+            parse("/nb 3 Work Stuff"),
+            Ok(Command::Notebook(3, Some("Work Stuff".to_string())))
+        );
+        assert_eq!(
+            parse("/t 3 'it''s' \"a \\\"b\\\"\""),
+            Ok(Command::Title(
+                3,
+                Some("'it''s' \"a \\\"b\\\"\"".to_string())
+            ))
+        );
+        assert_eq!(
+            parse("/t 3 \"say \\\"hi\\\"\""),
+            Ok(Command::Title(3, Some("say \"hi\"".to_string())))
+        );
+        assert_eq!(parse("/t 3"), Ok(Command::Title(3, None)));
+        assert_eq!(
+            parse("/\"d\" 3"),
+            Err("/\"d\" is not a valid command, /help lists all of them".to_string())
+        );
+        assert_eq!(
+            parse("/list --notebook \"Work Stuff\" --deleted"),
+            Ok(Command::List(
+                ListOptions::parse(&["--notebook", "Work Stuff", "--deleted"]).unwrap()
+            ))
+        );
+        assert_eq!(
+            parse("/tags rename \"home"),
+            Err("missing a closing \"".to_string())
+        );
+    }
+
+    #[test]
+    fn completes_commands_ids_and_options() {
+        let entries = [(3, "milk".to_string()), (12, "bread".to_string())];
+        let replacements = |line: &str| -> Vec<String> {
+            complete(line, &entries)
+                .1
+                .into_iter()
+                .map(|(_, replacement)| replacement)
+                .collect()
+        };
+
+        assert_eq!(replacements("/no"), vec!["/notebook ", "/notebooks "]);
+        assert_eq!(replacements("/d "), vec!["3 ", "12 "]);
+        assert_eq!(replacements("/e 1"), vec!["12 "]);
+        assert_eq!(complete("/e 1", &entries).0, 3);
+        assert!(replacements("/e 12 ").is_empty());
+        assert_eq!(replacements("/tags r"), vec!["rename "]);
+        assert_eq!(replacements("/l --sort updated --d"), vec!["--deleted "]);
+        assert!(replacements("buy mi").is_empty());
+    }
+}
@@ -0,0 +1,232 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Takes --db PATH and --profile NAME (or --db=PATH, --profile=NAME) out of the arguments
+// before the command, after it they're part of the command, like the text of a note
+pub fn take_options(
+    args: &mut Vec<String>,
+) -> Result<(Option<String>, Option<String>), Box<dyn std::error::Error>> {
+    let mut db = None;
+    let mut profile = None;
+    let mut i = 0;
+    while i < args.len() && args[i].starts_with('-') {
+        let (option, value) = match args[i].split_once('=') {
+            Some((option, value)) => (option.to_string(), Some(value.to_string())),
+            None => (args[i].clone(), None),
+        };
+        let target = match option.as_str() {
+            "--db" => &mut db,
+            "--profile" | "-p" => &mut profile,
+            // --json is for the command line, it stays in place
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        let value = match value {
+            Some(value) => value,
+            None if i + 1 < args.len() => args.remove(i + 1),
+            None => return Err(format!("{} needs a value", option).into()),
+        };
+        *target = Some(value);
+        args.remove(i);
+    }
+    Ok((db, profile))
+}
+
+// Where the notes are, from most to least specific: --db, --profile, $NOTED_DB,
+// the default profile of the config file and notes.db in the data directory
+// (~/.local/share/noted on Linux)
+pub fn database_path(
+    db: Option<&str>,
+    profile: Option<&str>,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if let Some(db) = db {
+        return Ok(PathBuf::from(db));
+    }
+    let config = read_config()?;
+    if let Some(profile) = profile {
+        return profile_path(profile, &config);
+    }
+    if let Some(db) = env::var_os("NOTED_DB").filter(|db| !db.is_empty()) {
+        return Ok(PathBuf::from(db));
+    }
+    match config.get("default") {
+        Some(toml::Value::String(profile)) => return profile_path(profile, &config),
+        Some(_) => return Err("default in the config should be the name of a profile".into()),
+        None => {}
+    }
+
+    let path = data_dir()?.join("notes.db");
+    move_legacy_database(&path)?;
+    Ok(path)
+}
+
+// Opens the database, creating the directories it's in if needed
+pub fn open(path: &Path) -> Result<rusqlite::Connection, Box<dyn std::error::Error>> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    rusqlite::Connection::open(path)
+        .map_err(|err| format!("can't open {}: {}", path.display(), err).into())
+}
+
+// Where the prompt keeps what was typed into it for this database,
+// ~/.local/state/noted/history for the default one on Linux
+pub fn history_path(db: &Path) -> Option<PathBuf> {
+    let dir = dirs::state_dir().or_else(dirs::data_dir)?.join("noted");
+    let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let default = data_dir()
+        .ok()
+        .map(|data| canonical(&data.join("notes.db")));
+    let db = canonical(db);
+    let path = history_file(&dir, &db, default.as_deref());
+    fs::create_dir_all(path.parent()?).ok()?;
+    Some(path)
+}
+
+// Every other database gets a file named after its path, profiles and --db alike
+fn history_file(dir: &Path, db: &Path, default: Option<&Path>) -> PathBuf {
+    if default == Some(db) {
+        return dir.join("history");
+    }
+    let name = db
+        .to_string_lossy()
+        .replace('%', "%25")
+        .replace(std::path::MAIN_SEPARATOR, "%2F");
+    dir.join("histories").join(name)
+}
+
+fn data_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let dir = dirs::data_dir().ok_or("there is no home directory, use --db or $NOTED_DB")?;
+    Ok(dir.join("noted"))
+}
+
+fn config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("noted").join("config.toml"))
+}
+
+fn read_config() -> Result<toml::Table, Box<dyn std::error::Error>> {
+    let Some(path) = config_path() else {
+        return Ok(toml::Table::new());
+    };
+    match fs::read_to_string(&path) {
+        Ok(text) => text
+            .parse()
+            .map_err(|err| format!("{}: {}", path.display(), err).into()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(toml::Table::new()),
+        Err(err) => Err(format!("{}: {}", path.display(), err).into()),
+    }
+}
+
+fn profile_path(name: &str, config: &toml::Table) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let configured = config
+        .get("profiles")
+        .and_then(|profiles| profiles.get(name));
+    match configured {
+        Some(toml::Value::String(path)) => Ok(expand(path)),
+        Some(_) => Err(format!("the path of profile {} should be a string", name).into()),
+        // The name becomes a file name, so it can't lead anywhere else
+        None if !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_') =>
+        {
+            Ok(data_dir()?.join(format!("{}.db", name)))
+        }
+        None => Err(format!("{} is not a profile name", name).into()),
+    }
+}
+
+// ~ is the home directory and relative paths start at the config directory
+fn expand(path: &str) -> PathBuf {
+    let home = dirs::home_dir();
+    let path = match (path.strip_prefix("~/"), home) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    };
+    match config_path().as_deref().and_then(Path::parent) {
+        Some(dir) if path.is_relative() => dir.join(path),
+        _ => path,
+    }
+}
+
+// Older versions kept the notes in ~/notes.db, they move to the default location once
+fn move_legacy_database(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(legacy) = dirs::home_dir().map(|home| home.join("notes.db")) else {
+        return Ok(());
+    };
+    if path.exists() || !legacy.is_file() {
+        return Ok(());
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // Renaming doesn't work across file systems
+    if fs::rename(&legacy, path).is_err() {
+        fs::copy(&legacy, path)?;
+        fs::remove_file(&legacy)?;
+    }
+    eprintln!("noted: moved {} to {}", legacy.display(), path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn takes_database_options_before_the_command() {
+        let mut rest = args(&["--db", "a.db", "--json", "--profile=work", "list"]);
+        let options = take_options(&mut rest).unwrap();
+        assert_eq!(
+            options,
+            (Some("a.db".to_string()), Some("work".to_string()))
+        );
+        assert_eq!(rest, args(&["--json", "list"]));
+
+        // After the command it's the text of the note
+        let mut rest = args(&["add", "buy", "-p", "5", "apples", "--db=x"]);
+        assert_eq!(take_options(&mut rest).unwrap(), (None, None));
+        assert_eq!(rest, args(&["add", "buy", "-p", "5", "apples", "--db=x"]));
+
+        assert!(take_options(&mut args(&["-p"])).is_err());
+    }
+
+    #[test]
+    fn every_database_has_its_own_history() {
+        let dir = Path::new("/state/noted");
+        let default = Path::new("/data/noted/notes.db");
+        assert_eq!(
+            history_file(dir, default, Some(default)),
+            PathBuf::from("/state/noted/history")
+        );
+        assert_eq!(
+            history_file(dir, Path::new("/srv/work.db"), Some(default)),
+            PathBuf::from("/state/noted/histories/%2Fsrv%2Fwork.db")
+        );
+        assert_eq!(
+            history_file(dir, Path::new("/srv/50%.db"), None),
+            PathBuf::from("/state/noted/histories/%2Fsrv%2F50%25.db")
+        );
+    }
+
+    #[test]
+    fn configured_profiles_win_over_the_default_location() {
+        let config: toml::Table = "[profiles]\nwork = \"/srv/work.db\"".parse().unwrap();
+        assert_eq!(
+            profile_path("work", &config).unwrap(),
+            PathBuf::from("/srv/work.db")
+        );
+        let other = profile_path("other", &config).unwrap();
+        assert!(other.ends_with("noted/other.db"));
+        assert!(profile_path("../other", &config).is_err());
+    }
+}
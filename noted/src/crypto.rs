@@ -0,0 +1,174 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use std::fmt;
+use zeroize::{Zeroize, Zeroizing};
+
+// How hard deriving the key is, kept in the database so it can change later.
+// (memory in KiB, iterations, lanes), the defaults Argon2id recommends.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cost(pub u32, pub u32, pub u32);
+
+#[cfg(not(test))]
+pub const COST: Cost = Cost(19 * 1024, 2, 1);
+// Unit tests derive keys all the time, the real cost would only make them slow
+#[cfg(test)]
+pub const COST: Cost = Cost(64, 1, 1);
+
+pub const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+// Sealed with the key to tell a wrong passphrase from a right one
+const CHECK: &[u8] = b"noted";
+
+// Not Copy, so there's only ever the one that gets wiped when it's dropped
+pub struct Key([u8; 32]);
+
+impl Drop for Key {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+// Not derived, keys shouldn't end up in logs or panics
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Key(..)")
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct WrongKey;
+
+impl fmt::Display for WrongKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("wrong passphrase")
+    }
+}
+
+impl std::error::Error for WrongKey {}
+
+impl Key {
+    // Argon2id over the passphrase and the salt of the database
+    pub fn derive(passphrase: &str, salt: &[u8], cost: Cost) -> Result<Self, String> {
+        let mut key = Self([0; 32]);
+        Params::new(cost.0, cost.1, cost.2, Some(32))
+            .and_then(|params| {
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params).hash_password_into(
+                    passphrase.as_bytes(),
+                    salt,
+                    &mut key.0,
+                )
+            })
+            .map_err(|err| format!("can't derive the key: {}", err))?;
+        Ok(key)
+    }
+
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let bytes = Zeroizing::new(from_hex(hex.trim())?);
+        let mut key = Self([0; 32]);
+        if bytes.len() != key.0.len() {
+            return None;
+        }
+        key.0.copy_from_slice(&bytes);
+        Some(key)
+    }
+
+    pub fn to_hex(&self) -> Zeroizing<String> {
+        Zeroizing::new(to_hex(&self.0))
+    }
+
+    // A fresh random nonce followed by the XChaCha20-Poly1305 ciphertext
+    pub fn seal(&self, plaintext: &[u8]) -> Vec<u8> {
+        let cipher = XChaCha20Poly1305::new(&self.0.into());
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let mut sealed = nonce.to_vec();
+        // Only fails for plaintexts longer than 256 GiB
+        sealed.extend(cipher.encrypt(&nonce, plaintext).expect("note too long"));
+        sealed
+    }
+
+    // Fails if the key is wrong or the data was changed
+    pub fn open(&self, sealed: &[u8]) -> Result<Vec<u8>, WrongKey> {
+        if sealed.len() < NONCE_LEN {
+            return Err(WrongKey);
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        XChaCha20Poly1305::new(&self.0.into())
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| WrongKey)
+    }
+
+    pub fn open_text(&self, sealed: &[u8]) -> Result<String, WrongKey> {
+        String::from_utf8(self.open(sealed)?).map_err(|_| WrongKey)
+    }
+
+    pub fn check_value(&self) -> Vec<u8> {
+        self.seal(CHECK)
+    }
+
+    pub fn fits(&self, check_value: &[u8]) -> bool {
+        self.open(check_value).is_ok_and(|check| check == CHECK)
+    }
+}
+
+pub fn salt() -> [u8; SALT_LEN] {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let mut salt = [0; SALT_LEN];
+    salt.copy_from_slice(&nonce[..SALT_LEN]);
+    salt
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_right_passphrase_opens_it() {
+        let salt = salt();
+        let key = Key::derive("correct horse", &salt, COST).unwrap();
+        let sealed = key.seal(b"the wifi password is hunter2");
+        assert!(!sealed.windows(7).any(|window| window == b"hunter2"));
+        assert_eq!(
+            key.open_text(&sealed).unwrap(),
+            "the wifi password is hunter2"
+        );
+
+        let wrong = Key::derive("battery staple", &salt, COST).unwrap();
+        assert_eq!(wrong.open(&sealed), Err(WrongKey));
+        assert!(key.fits(&key.check_value()));
+        assert!(!wrong.fits(&key.check_value()));
+    }
+
+    #[test]
+    fn sealing_twice_looks_different() {
+        let key = Key::derive("pass", &salt(), COST).unwrap();
+        assert_ne!(key.seal(b"same"), key.seal(b"same"));
+
+        let mut sealed = key.seal(b"same");
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert_eq!(key.open(&sealed), Err(WrongKey));
+    }
+
+    #[test]
+    fn keys_survive_hex() {
+        let key = Key::derive("pass", &salt(), COST).unwrap();
+        let again = Key::from_hex(&key.to_hex()).unwrap();
+        assert!(again.fits(&key.check_value()));
+        assert!(Key::from_hex("abc").is_none());
+    }
+}
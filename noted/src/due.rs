@@ -0,0 +1,281 @@
+use rusqlite::Connection;
+
+// When a reminder is due, as typed after /remind or `noted remind`, like `tomorrow 9:00`,
+// `friday 5pm`, `in 2 hours` or `2024-01-31 14:30`
+#[derive(Debug, PartialEq)]
+pub enum When {
+    // Minutes from now
+    In(u32),
+    // A time of day in local time, the next time the clock shows it without a day
+    At(Option<Day>, (u32, u32)),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Day {
+    Today,
+    Tomorrow,
+    InDays(u32),
+    // 0 is Sunday, like in SQLite. Always the next one, so on a Monday `monday` is in a week.
+    Weekday(u32),
+    // YYYY-MM-DD
+    Date(String),
+}
+
+const WEEKDAYS: [&str; 7] = [
+    "sunday",
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+];
+
+// Days without a time are due in the morning
+const MORNING: (u32, u32) = (9, 0);
+
+pub fn parse(text: &str) -> Result<When, String> {
+    let text = text.to_lowercase();
+    let mut words = text
+        .split_whitespace()
+        .filter(|word| !matches!(*word, "at" | "on" | "next"))
+        .peekable();
+    let mut day = None;
+    let mut time = None;
+
+    while let Some(word) = words.next() {
+        if word == "in" {
+            let amount = words
+                .next()
+                .and_then(|amount| amount.parse::<u32>().ok())
+                .ok_or("in takes a number, like in 2 hours")?;
+            let unit = words.next().unwrap_or_default();
+            let too_far = || format!("in {} {} is too far away", amount, unit);
+            let minutes = match unit.trim_end_matches('s') {
+                "min" | "minute" | "m" => amount,
+                "hour" | "h" => amount.checked_mul(60).ok_or_else(too_far)?,
+                "day" | "d" => {
+                    set(&mut day, Day::InDays(amount))?;
+                    continue;
+                }
+                "week" | "w" => {
+                    let days = amount.checked_mul(7).ok_or_else(too_far)?;
+                    set(&mut day, Day::InDays(days))?;
+                    continue;
+                }
+                _ => return Err(format!("{} is not minutes, hours, days or weeks", unit)),
+            };
+            if day.is_some() || time.is_some() || words.peek().is_some() {
+                return Err(format!("in {} {} can't have a day or time", amount, unit));
+            }
+            return Ok(When::In(minutes));
+        }
+
+        // 5 pm is the same as 5pm
+        let joined;
+        let word = match words.peek() {
+            Some(&suffix) if matches!(suffix, "am" | "pm") => {
+                joined = format!("{}{}", word, suffix);
+                words.next();
+                joined.as_str()
+            }
+            _ => word,
+        };
+
+        if let Some(parsed) = parse_time(word) {
+            if time.replace(parsed?).is_some() {
+                return Err("a reminder can only have one time".to_string());
+            }
+            continue;
+        }
+        let parsed = match word {
+            "today" => Day::Today,
+            "tomorrow" => Day::Tomorrow,
+            date if is_date(date) => Day::Date(date.to_string()),
+            name => match WEEKDAYS
+                .iter()
+                .position(|weekday| name.len() >= 3 && weekday.starts_with(name))
+            {
+                Some(weekday) => Day::Weekday(weekday as u32),
+                None => return Err(format!("can't tell when {} is", name)),
+            },
+        };
+        set(&mut day, parsed)?;
+    }
+
+    match (day, time) {
+        (None, None) => Err("when should it be due? Like tomorrow 9:00 or in 2 hours".to_string()),
+        (day @ Some(_), None) => Ok(When::At(day, MORNING)),
+        (day, Some(time)) => Ok(When::At(day, time)),
+    }
+}
+
+fn set(day: &mut Option<Day>, parsed: Day) -> Result<(), String> {
+    match day.replace(parsed) {
+        Some(_) => Err("a reminder can only be on one day".to_string()),
+        None => Ok(()),
+    }
+}
+
+// None for words that don't look like a time, like 9:00, 17:30, 9am or 12:15pm
+fn parse_time(word: &str) -> Option<Result<(u32, u32), String>> {
+    let (clock, offset) = match (word.strip_suffix("am"), word.strip_suffix("pm")) {
+        (Some(clock), _) => (clock, Some(0)),
+        (_, Some(clock)) => (clock, Some(12)),
+        _ if word.contains(':') => (word, None),
+        _ => return None,
+    };
+    let (hour, minute) = clock.split_once(':').unwrap_or((clock, "0"));
+    let (Ok(hour), Ok(minute)) = (hour.parse::<u32>(), minute.parse::<u32>()) else {
+        return Some(Err(format!("{} is not a time", word)));
+    };
+    let hour = match offset {
+        // 12am is midnight and 12pm noon
+        Some(offset) if (1..=12).contains(&hour) => hour % 12 + offset,
+        Some(_) => return Some(Err(format!("{} is not a time", word))),
+        None => hour,
+    };
+    if hour > 23 || minute > 59 {
+        return Some(Err(format!("{} is not a time", word)));
+    }
+    Some(Ok((hour, minute)))
+}
+
+fn is_date(word: &str) -> bool {
+    word.len() == 10
+        && word.char_indices().all(|(i, c)| match i {
+            4 | 7 => c == '-',
+            _ => c.is_ascii_digit(),
+        })
+}
+
+// The UTC date and time it's due at, as stored in the database. SQLite does the calendar
+// and time zone math.
+pub fn resolve(when: &When, conn: &Connection) -> Result<String, Box<dyn std::error::Error>> {
+    let (start, modifiers) = match when {
+        When::In(minutes) => ("now".to_string(), vec![format!("+{} minutes", minutes)]),
+        When::At(day, (hour, minute)) => {
+            let (start, mut modifiers) = match day {
+                Some(Day::Date(date)) => (date.clone(), vec![]),
+                _ => ("now".to_string(), vec!["localtime".to_string()]),
+            };
+            modifiers.push("start of day".to_string());
+            match day {
+                Some(Day::Tomorrow) => modifiers.push("+1 day".to_string()),
+                Some(Day::InDays(days)) => modifiers.push(format!("+{} days", days)),
+                Some(Day::Weekday(weekday)) => {
+                    modifiers.push("+1 day".to_string());
+                    modifiers.push(format!("weekday {}", weekday));
+                }
+                _ => {}
+            }
+            modifiers.push(format!("+{} hours", hour));
+            modifiers.push(format!("+{} minutes", minute));
+            modifiers.push("utc".to_string());
+            (start, modifiers)
+        }
+    };
+
+    let placeholders: Vec<String> = (1..=modifiers.len() + 1)
+        .map(|i| format!("?{}", i))
+        .collect();
+    let sql = format!(
+        "SELECT datetime({}), datetime({}) <= datetime('now')",
+        placeholders.join(", "),
+        placeholders.join(", ")
+    );
+    let params = std::iter::once(&start).chain(&modifiers);
+    let (due, passed): (Option<String>, Option<bool>) =
+        conn.query_row(&sql, rusqlite::params_from_iter(params), |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+    // SQLite only knows the years up to 9999
+    let due = due.ok_or_else(|| match when {
+        When::At(Some(Day::Date(_)), _) => format!("{} is not a date", start),
+        _ => "that is too far away".to_string(),
+    })?;
+
+    // A time without a day that already passed today means tomorrow
+    match when {
+        When::At(None, time) if passed == Some(true) => {
+            resolve(&When::At(Some(Day::Tomorrow), *time), conn)
+        }
+        _ => Ok(due),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn understands_days_and_times() {
+        assert_eq!(
+            parse("tomorrow 9:00"),
+            Ok(When::At(Some(Day::Tomorrow), (9, 0)))
+        );
+        assert_eq!(
+            parse("Next Friday at 5 pm"),
+            Ok(When::At(Some(Day::Weekday(5)), (17, 0)))
+        );
+        assert_eq!(
+            parse("2024-01-31"),
+            Ok(When::At(Some(Day::Date("2024-01-31".to_string())), MORNING))
+        );
+        assert_eq!(parse("12:30am"), Ok(When::At(None, (0, 30))));
+        assert_eq!(parse("in 2 hours"), Ok(When::In(120)));
+        assert_eq!(
+            parse("in 1 week 8:15"),
+            Ok(When::At(Some(Day::InDays(7)), (8, 15)))
+        );
+    }
+
+    #[test]
+    fn rejects_what_it_doesnt_understand() {
+        assert!(parse("").is_err());
+        assert!(parse("someday").is_err());
+        assert!(parse("25:00").is_err());
+        assert!(parse("13pm").is_err());
+        assert!(parse("today tomorrow").is_err());
+        assert!(parse("in 2 hours tomorrow").is_err());
+        assert!(parse("in a while").is_err());
+        assert_eq!(
+            parse("in 99999999 hours").unwrap_err(),
+            "in 99999999 hours is too far away"
+        );
+        assert!(parse("in 999999999 weeks").is_err());
+    }
+
+    #[test]
+    fn resolves_in_utc() {
+        let conn = Connection::open_in_memory().unwrap();
+        let due = |when| resolve(&when, &conn).unwrap();
+        let local = |sql: &str| -> String { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+
+        assert_eq!(
+            due(parse("2024-01-31 14:30").unwrap()),
+            local("SELECT datetime('2024-01-31 14:30', 'utc')")
+        );
+        let soon: bool = conn
+            .query_row(
+                "SELECT ?1 BETWEEN datetime('now', '+89 minutes') AND datetime('now', '+91 minutes')",
+                [due(parse("in 90 min").unwrap())],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(soon);
+        let friday = due(parse("friday").unwrap());
+        assert_eq!(
+            local(&format!(
+                "SELECT strftime('%w %H:%M', '{}', 'localtime')",
+                friday
+            )),
+            "5 09:00"
+        );
+        assert!(resolve(&When::In(u32::MAX), &conn).is_err());
+        assert!(resolve(&When::At(Some(Day::InDays(u32::MAX)), (9, 0)), &conn).is_err());
+        let later = due(When::At(None, (0, 0)));
+        assert!(later > local("SELECT datetime('now')"));
+        assert!(resolve(&parse("2024-13-45").unwrap(), &conn).is_err());
+    }
+}
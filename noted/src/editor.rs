@@ -0,0 +1,145 @@
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Opens $VISUAL or $EDITOR (vi if neither is set) on the text and returns what was saved,
+// or None if nothing changed
+pub fn edit(text: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    edit_with(&editor, text)
+}
+
+// The editor may come with arguments like `code --wait`, so the shell gets to split it
+fn edit_with(editor: &str, text: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    // Files end with a newline, so editors don't add one or glue text onto the last line
+    let path = match text.trim_end() {
+        "" => temp_file("")?,
+        text => temp_file(&format!("{}\n", text))?,
+    };
+
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("noted")
+        .arg(&path)
+        .status();
+    let edited = fs::read_to_string(&path);
+    let _ = fs::remove_file(&path);
+    if let Some(dir) = path.parent() {
+        let _ = fs::remove_dir(dir);
+    }
+
+    let status = status?;
+    if !status.success() {
+        return Err(format!("{} exited with {}, nothing was saved", editor, status).into());
+    }
+
+    // Most editors end the file with a newline, which isn't part of the note
+    let edited = edited?.trim_end().to_string();
+    Ok((edited != text.trim_end()).then_some(edited))
+}
+
+// A file only we can read in a directory of its own, so nobody else can read the note or
+// put a file or link in its place first. .md so editors highlight markdown.
+fn temp_file(text: &str) -> io::Result<PathBuf> {
+    let mut dir = fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut dir, 0o700);
+    let mut tries = 0;
+    let dir_path = loop {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.subsec_nanos())
+            .unwrap_or(0);
+        let path = env::temp_dir().join(format!("noted-{}-{}", std::process::id(), nanos));
+        match dir.create(&path) {
+            Ok(()) => break path,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists && tries < 100 => tries += 1,
+            Err(err) => return Err(err),
+        }
+    };
+
+    let path = dir_path.join("note.md");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let written = options
+        .open(&path)
+        .and_then(|mut file| file.write_all(text.as_bytes()));
+    if let Err(err) = written {
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_dir(&dir_path);
+        return Err(err);
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A shell script standing in for the editor, it gets the file as $1
+    fn stub(name: &str, script: &str) -> String {
+        let path = env::temp_dir().join(format!("noted-stub-{}-{}", std::process::id(), name));
+        fs::write(&path, script).unwrap();
+        format!("sh {}", path.display())
+    }
+
+    #[test]
+    fn returns_what_the_editor_saved() {
+        let editor = stub("append", "printf 'second line\\n' >> \"$1\"");
+        let edited = edit_with(&editor, "first line\n").unwrap();
+        assert_eq!(edited.as_deref(), Some("first line\nsecond line"));
+    }
+
+    #[test]
+    fn starts_with_the_text_of_the_note() {
+        let editor = stub(
+            "check",
+            "grep -q '^old text$' \"$1\" && echo new text > \"$1\"",
+        );
+        assert_eq!(
+            edit_with(&editor, "old text").unwrap().as_deref(),
+            Some("new text")
+        );
+    }
+
+    #[test]
+    fn closing_without_changes_is_a_no_op() {
+        assert_eq!(edit_with("true", "unchanged").unwrap(), None);
+        // Only a trailing newline added
+        let editor = stub("newline", "echo >> \"$1\"");
+        assert_eq!(edit_with(&editor, "unchanged").unwrap(), None);
+    }
+
+    #[test]
+    fn only_we_can_read_the_file_and_it_goes_away() {
+        let seen = env::temp_dir().join(format!("noted-seen-{}", std::process::id()));
+        let editor = stub(
+            "private",
+            &format!(
+                "stat -c '%a' \"$1\" \"$(dirname \"$1\")\" > {0}; echo \"$1\" >> {0}",
+                seen.display()
+            ),
+        );
+        edit_with(&editor, "secret").unwrap();
+
+        let seen = fs::read_to_string(&seen).unwrap();
+        let lines: Vec<&str> = seen.lines().collect();
+        assert_eq!(lines[..2], ["600", "700"]);
+        let path = PathBuf::from(lines[2]);
+        assert!(!path.exists() && !path.parent().unwrap().exists());
+    }
+
+    #[test]
+    fn failing_editor_saves_nothing() {
+        let editor = stub("fail", "echo changed > \"$1\"; exit 1");
+        assert!(edit_with(&editor, "text").is_err());
+    }
+}
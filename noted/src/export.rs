@@ -0,0 +1,639 @@
+use crate::crypto;
+use crate::links::sync_links;
+use crate::store::Priority;
+use crate::tags::sync_tags;
+use rusqlite::{Connection, OptionalExtension};
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+
+// A note as it's written to and read from a file. Timestamps are UTC like 2024-01-31T09:00:00Z.
+#[derive(Default)]
+pub struct Entry {
+    pub id: Option<i64>,
+    pub title: Option<String>,
+    pub body: String,
+    pub created: Option<String>,
+    pub updated: Option<String>,
+    pub pinned: bool,
+    pub deleted: Option<String>,
+    pub notebook: Option<String>,
+    // Only written for other tools, the #tags in the body are what counts
+    pub tags: Vec<String>,
+    pub due: Option<String>,
+    pub todo: bool,
+    pub priority: Priority,
+    pub done: Option<String>,
+    // The text sealed with the passphrase of a locked note, the body is empty then
+    pub sealed: Option<Vec<u8>>,
+    // Which passphrase that was, see NoteStore::vault_id
+    pub vault: Option<String>,
+}
+
+#[derive(Default)]
+pub struct Exported {
+    pub written: usize,
+    // Left out, only JSON backups have locked notes
+    pub locked: usize,
+}
+
+#[derive(Default)]
+pub struct Imported {
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+}
+
+pub enum Change {
+    Created(i64),
+    Updated,
+    Unchanged,
+}
+
+// Hands the notes to f one at a time, so exports don't need the whole database in memory.
+// Returns how many there were. Locked notes come with their sealed text only.
+pub fn each_entry(
+    deleted: bool,
+    conn: &Connection,
+    mut f: impl FnMut(Entry) -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT notes.id, title, body, strftime('%Y-%m-%dT%H:%M:%SZ', created_at),
+                strftime('%Y-%m-%dT%H:%M:%SZ', updated_at), pinned,
+                strftime('%Y-%m-%dT%H:%M:%SZ', deleted_at), notebooks.name,
+                (SELECT group_concat(tags.name, ' ') FROM note_tags
+                 JOIN tags ON tags.id = note_tags.tag_id WHERE note_tags.note_id = notes.id),
+                strftime('%Y-%m-%dT%H:%M:%SZ', due_at), kind = 'todo', priority,
+                strftime('%Y-%m-%dT%H:%M:%SZ', done_at), sealed,
+                (SELECT lower(hex(salt)) FROM vault)
+         FROM notes LEFT JOIN notebooks ON notebooks.id = notes.notebook_id
+         WHERE ?1 OR deleted_at IS NULL ORDER BY notes.id",
+    )?;
+    let mut rows = stmt.query([deleted])?;
+    let mut count = 0;
+    while let Some(row) = rows.next()? {
+        let sealed: Option<Vec<u8>> = row.get(13)?;
+        let tags: Option<String> = row.get(8)?;
+        let mut tags: Vec<String> = tags
+            .map(|tags| tags.split(' ').map(str::to_string).collect())
+            .unwrap_or_default();
+        tags.sort();
+        f(Entry {
+            id: row.get(0)?,
+            title: row.get(1)?,
+            body: row.get(2)?,
+            created: row.get(3)?,
+            updated: row.get(4)?,
+            pinned: row.get(5)?,
+            deleted: row.get(6)?,
+            notebook: row.get(7)?,
+            tags,
+            due: row.get(9)?,
+            todo: row.get(10)?,
+            priority: Priority::from_level(row.get(11)?),
+            done: row.get(12)?,
+            vault: match sealed {
+                Some(_) => row.get(14)?,
+                None => None,
+            },
+            sealed,
+        })?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+// Creates an entry without an id or with one that isn't taken and updates the note otherwise.
+// Restoring a backup keeps the dates of the entry, anything else counts as changed now.
+// Tags come from the body like for every other note. Meant to run inside a transaction.
+pub fn import_entry(
+    entry: &Entry,
+    keep_dates: bool,
+    conn: &Connection,
+) -> Result<Change, Box<dyn std::error::Error>> {
+    for date in [
+        &entry.created,
+        &entry.updated,
+        &entry.deleted,
+        &entry.due,
+        &entry.done,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        let valid: bool =
+            conn.query_row("SELECT datetime(?1) IS NOT NULL", [date], |row| row.get(0))?;
+        if !valid {
+            return Err(format!("{} is not a date", date).into());
+        }
+    }
+    // Sealed text only opens with the passphrase it was sealed with
+    if entry.sealed.is_some() {
+        let vault: Option<String> = conn
+            .query_row("SELECT lower(hex(salt)) FROM vault", [], |row| row.get(0))
+            .optional()?;
+        if vault.is_none() || vault != entry.vault {
+            return Err(format!(
+                "note {} was locked with the passphrase of another database, unlock it there \
+                 before exporting it",
+                entry.id.map(|id| id.to_string()).unwrap_or_default()
+            )
+            .into());
+        }
+    }
+    let (created, updated) = match keep_dates {
+        true => (entry.created.as_deref(), entry.updated.as_deref()),
+        false => (None, None),
+    };
+
+    let existing = match entry.id {
+        Some(id) => conn
+            .query_row(
+                "SELECT title, body, pinned, notebooks.name, deleted_at IS NOT NULL,
+                        (?2 IS NULL OR created_at IS datetime(?2))
+                        AND (?3 IS NULL OR updated_at IS datetime(?3))
+                        AND (?4 IS NULL OR deleted_at IS datetime(?4))
+                        AND due_at IS datetime(?5) AND done_at IS datetime(?6),
+                        kind = 'todo', priority, sealed
+                 FROM notes LEFT JOIN notebooks ON notebooks.id = notes.notebook_id
+                 WHERE notes.id = ?1",
+                rusqlite::params![id, created, updated, entry.deleted, entry.due, entry.done],
+                |row| {
+                    let note = Entry {
+                        title: row.get(0)?,
+                        body: row.get(1)?,
+                        pinned: row.get(2)?,
+                        notebook: row.get(3)?,
+                        todo: row.get(6)?,
+                        priority: Priority::from_level(row.get(7)?),
+                        sealed: row.get(8)?,
+                        ..Entry::default()
+                    };
+                    Ok((note, row.get::<_, bool>(4)?, row.get::<_, bool>(5)?))
+                },
+            )
+            .optional()?,
+        None => None,
+    };
+
+    let (id, change) = match (entry.id, existing) {
+        // Its text and history are sealed, a plain one would end up next to them
+        (Some(id), Some((note, _, _))) if note.sealed.is_some() && entry.sealed.is_none() => {
+            return Err(format!("note {} is locked, unlock it before importing over it", id).into())
+        }
+        // And the other way round its history would stay readable
+        (Some(id), Some((note, _, _))) if note.sealed.is_none() && entry.sealed.is_some() => {
+            return Err(format!(
+                "note {} isn't locked here, lock it before importing a locked version over it",
+                id
+            )
+            .into())
+        }
+        (Some(_), Some((note, deleted, same_dates)))
+            if note.title == entry.title
+                && note.body == entry.body
+                && note.sealed == entry.sealed
+                && note.pinned == entry.pinned
+                && note.notebook == entry.notebook
+                && note.todo == entry.todo
+                && note.priority == entry.priority
+                && deleted == entry.deleted.is_some()
+                && same_dates =>
+        {
+            return Ok(Change::Unchanged)
+        }
+        // Without a deletion date the note is there, even if it was deleted here
+        (Some(id), Some(_)) => {
+            conn.execute(
+                "UPDATE notes SET title = ?1, body = ?2, pinned = ?3, deleted_at = datetime(?4),
+                     created_at = coalesce(datetime(?5), created_at),
+                     updated_at = coalesce(datetime(?6), datetime('now')),
+                     reminded_at = CASE WHEN due_at IS datetime(?8) THEN reminded_at END,
+                     due_at = datetime(?8), kind = ?9, priority = ?10, done_at = datetime(?11),
+                     sealed = ?12
+                 WHERE id = ?7",
+                rusqlite::params![
+                    entry.title,
+                    entry.body,
+                    entry.pinned,
+                    entry.deleted,
+                    created,
+                    updated,
+                    id,
+                    entry.due,
+                    kind(entry),
+                    entry.priority.level(),
+                    entry.done,
+                    entry.sealed
+                ],
+            )?;
+            (id, Change::Updated)
+        }
+        _ => {
+            conn.execute(
+                "INSERT INTO notes (id, title, body, pinned, deleted_at, created_at, updated_at,
+                                    due_at, kind, priority, done_at, sealed)
+                 VALUES (?1, ?2, ?3, ?4, datetime(?5), coalesce(datetime(?6), datetime('now')),
+                         coalesce(datetime(?7), datetime(?6), datetime('now')), datetime(?8),
+                         ?9, ?10, datetime(?11), ?12)",
+                rusqlite::params![
+                    entry.id,
+                    entry.title,
+                    entry.body,
+                    entry.pinned,
+                    entry.deleted,
+                    entry.created,
+                    entry.updated,
+                    entry.due,
+                    kind(entry),
+                    entry.priority.level(),
+                    entry.done,
+                    entry.sealed
+                ],
+            )?;
+            let id = conn.last_insert_rowid();
+            (id, Change::Created(id))
+        }
+    };
+
+    sync_tags(id, &entry.body, conn)?;
+    sync_links(id, &entry.body, conn)?;
+    if let Some(notebook) = &entry.notebook {
+        conn.execute(
+            "INSERT OR IGNORE INTO notebooks (name) VALUES (?1)",
+            [notebook],
+        )?;
+    }
+    conn.execute(
+        "UPDATE notes SET notebook_id = (SELECT id FROM notebooks WHERE name = ?1) WHERE id = ?2",
+        rusqlite::params![entry.notebook, id],
+    )?;
+    Ok(change)
+}
+
+// A done entry is a todo, even if the file didn't say so
+fn kind(entry: &Entry) -> &'static str {
+    match entry.todo || entry.done.is_some() {
+        true => "todo",
+        false => "note",
+    }
+}
+
+// Restores entries as they come, all or nothing
+fn import_all(
+    entries: impl Iterator<Item = Result<Entry, Box<dyn std::error::Error>>>,
+    conn: &Connection,
+) -> Result<Imported, Box<dyn std::error::Error>> {
+    let tx = conn.unchecked_transaction()?;
+    let mut imported = Imported::default();
+    for entry in entries {
+        match import_entry(&entry?, true, &tx)? {
+            Change::Created(_) => imported.created += 1,
+            Change::Updated => imported.updated += 1,
+            Change::Unchanged => imported.unchanged += 1,
+        }
+    }
+    tx.commit()?;
+    Ok(imported)
+}
+
+// JSON lines, one note per line with everything noted knows about it, deleted notes included.
+// Importing it again restores the notes as they were. Locked notes stay sealed.
+pub fn export_json(
+    mut out: impl Write,
+    conn: &Connection,
+) -> Result<Exported, Box<dyn std::error::Error>> {
+    let written = each_entry(true, conn, |entry| {
+        writeln!(out, "{}", entry_json(&entry))?;
+        Ok(())
+    })?;
+    out.flush()?;
+    Ok(Exported { written, locked: 0 })
+}
+
+pub fn import_json(
+    input: impl BufRead,
+    conn: &Connection,
+) -> Result<Imported, Box<dyn std::error::Error>> {
+    let lines = input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+        .map(|(number, line)| {
+            let entry = serde_json::from_str(&line?)
+                .map_err(|err| err.to_string())
+                .and_then(entry_from_json);
+            entry.map_err(|err| format!("line {}: {}", number + 1, err).into())
+        });
+    import_all(lines, conn)
+}
+
+fn entry_json(entry: &Entry) -> Value {
+    let mut json = json!({
+        "id": entry.id,
+        "title": entry.title,
+        "body": entry.body,
+        "created_at": entry.created,
+        "updated_at": entry.updated,
+        "pinned": entry.pinned,
+        "deleted_at": entry.deleted,
+        "notebook": entry.notebook,
+        "tags": entry.tags,
+        "due_at": entry.due,
+        "todo": entry.todo,
+        "priority": entry.priority.name(),
+        "done_at": entry.done,
+    });
+    if let Some(sealed) = &entry.sealed {
+        json["locked"] = json!(true);
+        json["sealed"] = json!(crypto::to_hex(sealed));
+        json["vault"] = json!(entry.vault);
+    }
+    json
+}
+
+fn entry_from_json(value: Value) -> Result<Entry, String> {
+    let text = |key: &str| match &value[key] {
+        Value::Null => Ok(None),
+        Value::String(text) => Ok(Some(text.clone())),
+        other => Err(format!("{} should be text, not {}", key, other)),
+    };
+    let id = match &value["id"] {
+        Value::Null => None,
+        id => Some(
+            id.as_i64()
+                .ok_or_else(|| format!("{} is not a note id", id))?,
+        ),
+    };
+    let tags = match &value["tags"] {
+        Value::Array(tags) => tags
+            .iter()
+            .filter_map(|tag| tag.as_str().map(str::to_string))
+            .collect(),
+        _ => vec![],
+    };
+    let sealed = match (value["locked"].as_bool().unwrap_or(false), text("sealed")?) {
+        (true, Some(sealed)) => {
+            Some(crypto::from_hex(&sealed).ok_or("sealed should be the text in hex")?)
+        }
+        (true, None) => return Err("a locked note needs its sealed text".to_string()),
+        (false, _) => None,
+    };
+    Ok(Entry {
+        id,
+        title: text("title")?,
+        body: match sealed {
+            Some(_) => String::new(),
+            None => text("body")?.ok_or("every note needs a body")?,
+        },
+        created: text("created_at")?,
+        updated: text("updated_at")?,
+        pinned: value["pinned"].as_bool().unwrap_or(false),
+        deleted: text("deleted_at")?,
+        notebook: text("notebook")?,
+        tags,
+        due: text("due_at")?,
+        todo: value["todo"].as_bool().unwrap_or(false),
+        priority: match text("priority")? {
+            Some(priority) => Priority::parse(&priority)
+                .ok_or_else(|| format!("{} is not a priority", priority))?,
+            None => Priority::None,
+        },
+        done: text("done_at")?,
+        vault: text("vault")?,
+        sealed,
+    })
+}
+
+const CSV_HEADER: [&str; 13] = [
+    "id",
+    "title",
+    "body",
+    "created_at",
+    "updated_at",
+    "pinned",
+    "deleted_at",
+    "notebook",
+    "tags",
+    "due_at",
+    "todo",
+    "priority",
+    "done_at",
+];
+
+// Same columns as the JSON, with dates like 2024-01-31 09:00:00 that spreadsheets understand.
+// Locked notes are left out, their text would only be a hex blob in a cell.
+pub fn export_csv(
+    out: impl Write,
+    conn: &Connection,
+) -> Result<Exported, Box<dyn std::error::Error>> {
+    let mut writer = csv::Writer::from_writer(out);
+    writer.write_record(CSV_HEADER)?;
+    let date = |date: &Option<String>| {
+        date.as_deref()
+            .unwrap_or_default()
+            .replace('T', " ")
+            .replace('Z', "")
+    };
+    let mut locked = 0;
+    let count = each_entry(true, conn, |entry| {
+        if entry.sealed.is_some() {
+            locked += 1;
+            return Ok(());
+        }
+        writer.write_record([
+            entry.id.map(|id| id.to_string()).unwrap_or_default(),
+            entry.title.clone().unwrap_or_default(),
+            entry.body.clone(),
+            date(&entry.created),
+            date(&entry.updated),
+            entry.pinned.to_string(),
+            date(&entry.deleted),
+            entry.notebook.clone().unwrap_or_default(),
+            entry.tags.join(" "),
+            date(&entry.due),
+            entry.todo.to_string(),
+            entry.priority.name().to_string(),
+            date(&entry.done),
+        ])?;
+        Ok(())
+    })?;
+    writer.flush()?;
+    Ok(Exported {
+        written: count - locked,
+        locked,
+    })
+}
+
+// Columns are found by their name and may be missing, except for body. Empty cells are
+// missing values, so rows without an id become new notes.
+pub fn import_csv(
+    input: impl std::io::Read,
+    conn: &Connection,
+) -> Result<Imported, Box<dyn std::error::Error>> {
+    let mut reader = csv::Reader::from_reader(input);
+    let header = reader.headers()?.clone();
+    let column = |name: &str| header.iter().position(|column| column == name);
+    let columns: Vec<Option<usize>> = CSV_HEADER.iter().map(|name| column(name)).collect();
+    if columns[2].is_none() {
+        return Err("the CSV has no body column".into());
+    }
+
+    let entries = reader.into_records().enumerate().map(|(number, record)| {
+        let record = record?;
+        let cell = |index: usize| {
+            columns[index]
+                .and_then(|column| record.get(column))
+                .map(str::trim)
+                .filter(|cell| !cell.is_empty())
+                .map(str::to_string)
+        };
+        let id = match cell(0) {
+            Some(id) => Some(
+                id.parse::<i64>()
+                    .map_err(|_| format!("row {}: {} is not a note id", number + 2, id))?,
+            ),
+            None => None,
+        };
+        Ok(Entry {
+            id,
+            title: cell(1),
+            body: columns[2]
+                .and_then(|column| record.get(column))
+                .unwrap_or_default()
+                .to_string(),
+            created: cell(3),
+            updated: cell(4),
+            pinned: yes(cell(5)),
+            deleted: cell(6),
+            notebook: cell(7),
+            tags: cell(8)
+                .map(|tags| tags.split_whitespace().map(str::to_string).collect())
+                .unwrap_or_default(),
+            due: cell(9),
+            todo: yes(cell(10)),
+            priority: match cell(11) {
+                Some(priority) => Priority::parse(&priority)
+                    .ok_or_else(|| format!("row {}: {} is not a priority", number + 2, priority))?,
+                None => Priority::None,
+            },
+            done: cell(12),
+            ..Entry::default()
+        })
+    });
+    import_all(entries, conn)
+}
+
+fn yes(cell: Option<String>) -> bool {
+    matches!(
+        cell.as_deref().map(str::to_lowercase).as_deref(),
+        Some("true" | "1" | "yes")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::migrate;
+
+    fn db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        migrate(&conn).unwrap();
+        conn
+    }
+
+    fn filled_db() -> Connection {
+        let conn = db();
+        conn.execute_batch(
+            "INSERT INTO notes (title, body, created_at, updated_at, pinned, deleted_at)
+             VALUES ('Shopping, \"weekly\"', 'milk,\nbread #home', '2024-01-31 09:00:00',
+                     '2024-02-01 10:30:00', 1, NULL),
+                    (NULL, 'gone', '2024-01-01 00:00:00', '2024-01-02 00:00:00', 0,
+                     '2024-01-03 00:00:00');
+             INSERT INTO notebooks (name) VALUES ('Home');
+             UPDATE notes SET notebook_id = 1, due_at = '2024-02-05 08:00:00', kind = 'todo',
+                 priority = 2, done_at = '2024-02-02 12:00:00' WHERE id = 1;",
+        )
+        .unwrap();
+        sync_tags(1, "milk,\nbread #home", &conn).unwrap();
+        conn
+    }
+
+    fn dump(conn: &Connection) -> String {
+        let mut out = vec![];
+        export_json(&mut out, conn).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn json_restores_everything() {
+        let original = filled_db();
+        let backup = dump(&original);
+        assert_eq!(backup.lines().count(), 2);
+
+        let restored = db();
+        let imported = import_json(backup.as_bytes(), &restored).unwrap();
+        assert_eq!(imported.created, 2);
+        assert_eq!(dump(&restored), backup);
+
+        let again = import_json(backup.as_bytes(), &restored).unwrap();
+        assert_eq!((again.created, again.updated, again.unchanged), (0, 0, 2));
+    }
+
+    #[test]
+    fn csv_restores_everything() {
+        let original = filled_db();
+        let mut csv = vec![];
+        export_csv(&mut csv, &original).unwrap();
+
+        let restored = db();
+        import_csv(csv.as_slice(), &restored).unwrap();
+        assert_eq!(dump(&restored), dump(&original));
+    }
+
+    #[test]
+    fn csv_rows_without_an_id_are_new_notes() {
+        let conn = filled_db();
+        let csv = "title,body\nFrom a sheet,\"two\nlines\"\n";
+        let imported = import_csv(csv.as_bytes(), &conn).unwrap();
+        assert_eq!(imported.created, 1);
+        assert!(dump(&conn).contains(r#""body":"two\nlines","created_at""#));
+        assert!(import_csv("title\nno body".as_bytes(), &conn).is_err());
+    }
+
+    #[test]
+    fn locked_notes_stay_sealed() {
+        let vault = "INSERT INTO vault (id, salt, memory, iterations, lanes, check_value)
+                     VALUES (1, x'c0ffee', 64, 1, 1, x'00')";
+        let original = filled_db();
+        original
+            .execute_batch(&format!(
+                "{}; UPDATE notes SET body = '', sealed = x'5ea1ed' WHERE id = 2",
+                vault
+            ))
+            .unwrap();
+        let backup = dump(&original);
+        assert!(backup.contains(r#""body":"","#));
+        assert!(backup.contains(r#""locked":true,"#));
+        assert!(backup.contains(r#""sealed":"5ea1ed","#) && backup.contains(r#""vault":"c0ffee""#));
+
+        // Only a database with the same passphrase can open it
+        let restored = db();
+        assert!(import_json(backup.as_bytes(), &restored).is_err());
+        restored.execute(vault, []).unwrap();
+        import_json(backup.as_bytes(), &restored).unwrap();
+        assert_eq!(dump(&restored), backup);
+
+        let mut csv = vec![];
+        let exported = export_csv(&mut csv, &original).unwrap();
+        assert_eq!((exported.written, exported.locked), (1, 1));
+        assert!(!String::from_utf8(csv).unwrap().contains("5ea1ed"));
+    }
+
+    #[test]
+    fn broken_lines_import_nothing() {
+        let conn = db();
+        let json = "{\"body\":\"fine\"}\n{\"body\":\"bad date\",\"created_at\":\"yesterday\"}\n";
+        assert!(import_json(json.as_bytes(), &conn).is_err());
+        assert_eq!(dump(&conn), "");
+    }
+}
@@ -0,0 +1,215 @@
+use fallible_iterator::FallibleIterator;
+use rusqlite::Connection;
+
+// [[3]] links to entry 3 and [[Groceries]] to the entry with that title, ignoring case.
+// The target is whatever is between the brackets on one line, without the spaces around it.
+pub fn extract_links(body: &str) -> Vec<String> {
+    let mut links: Vec<String> = find_links(body)
+        .into_iter()
+        .map(|(start, end)| body[start..end].to_string())
+        .collect();
+    links.sort();
+    links.dedup();
+    links
+}
+
+// Where the targets of the links are in the body, in order
+fn find_links(body: &str) -> Vec<(usize, usize)> {
+    let mut found = vec![];
+    let mut offset = 0;
+    while let Some(start) = body[offset..].find("[[") {
+        offset += start + 2;
+        let Some(end) = body[offset..].find("]]") else {
+            break;
+        };
+        let inside = &body[offset..offset + end];
+        // [[a [[b]] is a link to b, the first [[ was just text
+        if inside.contains(['\n', '[']) {
+            continue;
+        }
+        let target = inside.trim();
+        if !target.is_empty() {
+            let start = offset + (inside.len() - inside.trim_start().len());
+            found.push((start, start + target.len()));
+        }
+        offset += end + 2;
+    }
+    found
+}
+
+// The body with the targets of links replaced where the function returns a new one
+pub fn replace_links(
+    body: &str,
+    mut replace: impl FnMut(&str) -> rusqlite::Result<Option<String>>,
+) -> rusqlite::Result<String> {
+    let mut replaced = String::new();
+    let mut last = 0;
+    for (start, end) in find_links(body) {
+        if let Some(target) = replace(&body[start..end])? {
+            replaced.push_str(&body[last..start]);
+            replaced.push_str(&target);
+            last = end;
+        }
+    }
+    replaced.push_str(&body[last..]);
+    Ok(replaced)
+}
+
+// Makes links match the [[links]] in the body
+pub fn sync_links(id: i64, body: &str, conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM links WHERE source_id = ?1", [id])?;
+    for target in extract_links(body) {
+        conn.execute(
+            "INSERT INTO links (source_id, target) VALUES (?1, ?2)",
+            rusqlite::params![id, target],
+        )?;
+    }
+    Ok(())
+}
+
+// Migration for the notes written before links were indexed
+pub fn sync_all_links(conn: &Connection) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare("SELECT id, body FROM notes")?;
+    let notes = stmt
+        .query([])?
+        .map(|row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))
+        .collect::<Vec<(i64, String)>>()?;
+    for (id, body) in notes {
+        sync_links(id, &body, conn)?;
+    }
+    Ok(())
+}
+
+// The title of a note, or its text unless that's locked
+const LABEL: &str = "coalesce(notes.title,
+    CASE WHEN notes.sealed IS NULL THEN notes.body ELSE '(locked)' END)";
+
+// What an entry links to, with the id and title or text of the entry if there is one
+pub struct Link {
+    pub target: String,
+    pub note: Option<(usize, String)>,
+}
+
+// The links in an entry as they were typed, sorted, missing ones included
+pub fn get_links(id: usize, conn: &Connection) -> rusqlite::Result<Vec<Link>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT target, notes.id, {LABEL}
+         FROM resolved_links LEFT JOIN notes ON notes.id = resolved_links.target_id
+         WHERE source_id = ?1 ORDER BY target"
+    ))?;
+    let links = stmt
+        .query([id])?
+        .map(|row| {
+            let note = match row.get::<_, Option<usize>>(1)? {
+                Some(id) => Some((id, row.get(2)?)),
+                None => None,
+            };
+            Ok(Link {
+                target: row.get(0)?,
+                note,
+            })
+        })
+        .collect()?;
+    Ok(links)
+}
+
+// (id, title or body) of the entries linking to this one, deleted ones don't count
+pub fn get_backlinks(id: usize, conn: &Connection) -> rusqlite::Result<Vec<(usize, String)>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT DISTINCT notes.id, {LABEL}
+         FROM resolved_links JOIN notes ON notes.id = resolved_links.source_id
+         WHERE target_id = ?1 AND notes.deleted_at IS NULL ORDER BY notes.id"
+    ))?;
+    let backlinks = stmt
+        .query([id])?
+        .map(|row| Ok((row.get(0)?, row.get(1)?)))
+        .collect()?;
+    Ok(backlinks)
+}
+
+// (id, target) for every link that doesn't lead anywhere, because the entry was deleted,
+// renamed or never existed
+pub fn get_dangling(conn: &Connection) -> rusqlite::Result<Vec<(usize, String)>> {
+    let mut stmt = conn.prepare(
+        "SELECT source_id, target
+         FROM resolved_links JOIN notes ON notes.id = resolved_links.source_id
+         WHERE target_id IS NULL AND notes.deleted_at IS NULL ORDER BY source_id, target",
+    )?;
+    let dangling = stmt
+        .query([])?
+        .map(|row| Ok((row.get(0)?, row.get(1)?)))
+        .collect()?;
+    Ok(dangling)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::NoteStore;
+
+    #[test]
+    fn finds_ids_and_titles_in_brackets() {
+        assert_eq!(
+            extract_links("see [[3]] and [[ Groceries ]], again [[3]]"),
+            vec!["3", "Groceries"]
+        );
+        assert_eq!(extract_links("[[a [[b]] c]]"), vec!["b"]);
+        assert!(extract_links("[[]] [[two\nlines]] [[open").is_empty());
+        assert!(extract_links("[single] and [[unfinished]").is_empty());
+    }
+
+    #[test]
+    fn replaces_only_the_targets() {
+        let replaced = replace_links("[[a [[ 3 ]], [[Groceries]] and [[3]]", |target| {
+            Ok((target == "3").then(|| "three".to_string()))
+        })
+        .unwrap();
+        assert_eq!(replaced, "[[a [[ three ]], [[Groceries]] and [[three]]");
+    }
+
+    #[test]
+    fn links_resolve_when_read_and_dangle_after_deletes() {
+        let store = NoteStore::in_memory();
+        let groceries = store.create(Some("Groceries"), "milk").unwrap();
+        let plumber = store.create(None, "call the plumber").unwrap();
+        let home = store
+            .create(
+                None,
+                "see [[groceries]] and [[2]], later [[Bills]] and [[Groceries]]",
+            )
+            .unwrap();
+        let conn = store.conn();
+
+        let links: Vec<_> = get_links(home, conn)
+            .unwrap()
+            .into_iter()
+            .map(|link| (link.target, link.note.map(|(id, _)| id)))
+            .collect();
+        assert_eq!(
+            links,
+            vec![
+                ("2".to_string(), Some(plumber)),
+                ("Bills".to_string(), None),
+                ("Groceries".to_string(), Some(groceries)),
+                ("groceries".to_string(), Some(groceries)),
+            ]
+        );
+        assert_eq!(
+            get_backlinks(groceries, conn).unwrap(),
+            vec![(
+                home,
+                "see [[groceries]] and [[2]], later [[Bills]] and [[Groceries]]".to_string()
+            )]
+        );
+
+        // Titles are looked up when reading, a new note can fix a dangling link
+        let bills = store.create(Some("bills"), "rent").unwrap();
+        store.delete(plumber).unwrap();
+        assert_eq!(get_dangling(conn).unwrap(), vec![(home, "2".to_string())]);
+        assert_eq!(get_backlinks(bills, conn).unwrap().len(), 1);
+
+        store.update(home, "nothing linked").unwrap();
+        assert!(get_backlinks(groceries, conn).unwrap().is_empty());
+        assert!(get_dangling(conn).unwrap().is_empty());
+    }
+}
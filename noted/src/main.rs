@@ -1,141 +1,841 @@
-use rusqlite::{Connection, Result};
-use std::{io, println};
-use dirs;
-use fallible_iterator::FallibleIterator;
-
-fn id_exists(id: &str, conn: &Connection) -> Result<bool, Box<dyn std::error::Error>> {
-    let mut ids = get_ids(&conn)?;
-    ids.retain(|&x| x == id.parse().unwrap());
-
-    if ids.is_empty() {
-        return Ok(false);
+use command::Command;
+use due::When;
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::{Context, Editor, Helper, Highlighter, Hinter, Validator};
+use similar::{ChangeTag, TextDiff};
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use store::{ListOptions, Note, NoteStore, Priority, Reminder, Revision, Sort};
+
+mod cli;
+mod command;
+mod config;
+mod crypto;
+mod due;
+mod editor;
+mod export;
+mod links;
+mod markdown;
+mod migrations;
+mod notebooks;
+mod session;
+mod store;
+mod sync;
+mod tags;
+mod terminal;
+mod textarea;
+mod tui;
+
+// Matches are bold in a terminal and bracketed everywhere else
+fn highlight() -> (&'static str, &'static str) {
+    if io::stdout().is_terminal() {
+        ("\x1b[1m", "\x1b[0m")
+    } else {
+        ("[", "]")
     }
+}
 
-    Ok(true)
+// One line per note, with the date it's sorted by
+fn print_notes(notes: &[Note], sort: Sort) {
+    for note in notes {
+        let date = match sort {
+            Sort::Created => &note.created_at,
+            Sort::Updated => &note.updated_at,
+        };
+        let due = note
+            .due
+            .as_ref()
+            .map(|due| format!(", due {}", due))
+            .unwrap_or_default();
+        let done = note
+            .done_at
+            .as_ref()
+            .map(|done| format!(", done {}", done))
+            .unwrap_or_default();
+        println!(
+            "{}: {}{}{}{}{}  ({}{}{})",
+            note.id,
+            if note.pinned { "* " } else { "" },
+            checkbox(note),
+            note.notebook
+                .as_ref()
+                .map(|notebook| format!("[{}] ", notebook))
+                .unwrap_or_default(),
+            note.title
+                .as_ref()
+                .map(|title| format!("{} - ", title))
+                .unwrap_or_default(),
+            summary(note),
+            date,
+            due,
+            done
+        );
+    }
 }
 
-fn get_ids(conn: &Connection) -> Result<Vec<usize>, Box<dyn std::error::Error>> {
+// Every revision with what changed since the one before, only the changed lines and the
+// ones around them
+fn print_history(revisions: &[Revision]) {
+    let mut before: Option<&Revision> = None;
+    for revision in revisions {
+        let event = match (before.map(|before| before.deleted), revision.deleted) {
+            (None, _) => "written",
+            (Some(false), true) => "deleted",
+            (Some(true), false) => "undeleted",
+            _ => "changed",
+        };
+        println!("rev {}  {}  {}", revision.rev, revision.saved_at, event);
+
+        let old_title = before.and_then(|before| before.title.as_deref());
+        if old_title != revision.title.as_deref() {
+            println!(
+                "  title: {} -> {}",
+                old_title.unwrap_or("none"),
+                revision.title.as_deref().unwrap_or("none")
+            );
+        }
+        print_diff(before.map(|before| before.body.as_str()), &revision.body);
+        before = Some(revision);
+    }
+}
 
-    let mut stmt = conn.prepare("SELECT id FROM notes")?;
-    let rows = stmt.query(rusqlite::params![])?;
+// The changed lines and the ones around them, everything is new without an old text
+fn print_diff(old: Option<&str>, new: &str) {
+    // Without a newline at the end a last line that got one more after it looks changed
+    let old = old.map(|old| format!("{}\n", old)).unwrap_or_default();
+    let new = format!("{}\n", new);
+    let diff = TextDiff::from_lines(&old, &new);
+    for group in diff.grouped_ops(1) {
+        for op in group {
+            for change in diff.iter_changes(&op) {
+                let sign = match change.tag() {
+                    ChangeTag::Delete => '-',
+                    ChangeTag::Insert => '+',
+                    ChangeTag::Equal => ' ',
+                };
+                println!("  {}{}", sign, change.value().trim_end_matches('\n'));
+            }
+        }
+    }
+}
 
-    let ids = rows.map(|row| row.get(0)).collect::<Vec<usize>>()?;
-    Ok(ids)
+// Each conflict with what taking the other version would change in the entry
+fn print_conflicts(conflicts: &[sync::Conflict]) {
+    for conflict in conflicts {
+        println!(
+            "{}: entry {} was changed on another device too  ({})",
+            conflict.id, conflict.note_id, conflict.found_at
+        );
+        let (current, other) = (&conflict.current, &conflict.other);
+        if current.title != other.title {
+            println!(
+                "  title: {} -> {}",
+                current.title.as_deref().unwrap_or("none"),
+                other.title.as_deref().unwrap_or("none")
+            );
+        }
+        if current.deleted != other.deleted {
+            println!(
+                "  {}",
+                if other.deleted {
+                    "deleted"
+                } else {
+                    "undeleted"
+                }
+            );
+        }
+        match conflict.locked {
+            true => println!("  the entry is locked, /unlock it to compare"),
+            false => print_diff(Some(&current.body), &other.body),
+        }
+    }
 }
 
-fn get_entrys(conn: &Connection) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+// [ ] or [x] for todos with ! to !!! for their priority, nothing for other notes
+fn checkbox(note: &Note) -> String {
+    if !note.todo {
+        return String::new();
+    }
+    let check = if note.done_at.is_some() { "[x]" } else { "[ ]" };
+    let urgency = "!".repeat(note.priority.level() as usize);
+    match urgency.is_empty() {
+        true => format!("{} ", check),
+        false => format!("{} {} ", check, urgency),
+    }
+}
 
-    let mut stmt = conn.prepare("SELECT body FROM notes")?;
-    let rows = stmt.query(rusqlite::params![])?;
+// Overdue notes first, then the upcoming ones
+fn print_reminders(reminders: &[Reminder]) {
+    for overdue in [true, false] {
+        let mut shown = reminders
+            .iter()
+            .filter(|reminder| reminder.overdue == overdue)
+            .peekable();
+        if shown.peek().is_some() {
+            println!("{}", if overdue { "Overdue" } else { "Upcoming" });
+        }
+        for reminder in shown {
+            println!(
+                "  {}: {}{}  (due {})",
+                reminder.id,
+                reminder
+                    .title
+                    .as_ref()
+                    .map(|title| format!("{} - ", title))
+                    .unwrap_or_default(),
+                first_line(&reminder.body),
+                reminder.due
+            );
+        }
+    }
+}
 
-    let entrys = rows.map(|row| row.get(0)).collect::<Vec<String>>()?;
-    Ok(entrys)
+// What lists show of an entry, locked ones only show their title
+fn summary(note: &Note) -> String {
+    match note.locked {
+        true => "(locked)".to_string(),
+        false => first_line(&note.body),
+    }
 }
 
-fn try_to_delete(msg: &str, conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+// Multi-line entries only show their first line in lists
+fn first_line(body: &str) -> String {
+    let mut lines = body.lines();
+    let first = lines.next().unwrap_or_default();
+    match lines.count() {
+        0 => first.to_string(),
+        1 => format!("{} (+1 line)", first),
+        more => format!("{} (+{} lines)", first, more),
+    }
+}
 
-    if !id_exists(msg, &conn)? {
+fn try_to_delete(id: usize, store: &NoteStore) -> Result<(), Box<dyn std::error::Error>> {
+    if !store.exists(id)? {
         println!("Could not delete entry, because it doesn't exist!");
         return Ok(());
     }
 
-    conn.execute("DELETE FROM notes WHERE id = (?1)", [msg])?;
+    // Links to it lead nowhere once it's gone, which is worth knowing right away
+    let backlinks = links::get_backlinks(id, store.conn())?;
+    store.delete(id)?;
+    if !backlinks.is_empty() {
+        let sources: Vec<String> = backlinks
+            .iter()
+            .map(|(source, _)| source.to_string())
+            .collect();
+        println!(
+            "Entry {} is still linked from {}, /links lists the links that lead nowhere",
+            id,
+            sources.join(", ")
+        );
+    }
 
     Ok(())
 }
 
-fn try_to_update(msg: &str, conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+// Links of the entry and the entries linking to it, like a small wiki
+fn print_links(links: &[links::Link], backlinks: &[(usize, String)]) {
+    if !links.is_empty() {
+        println!("Links to");
+    }
+    for link in links {
+        match &link.note {
+            Some((id, label)) => println!("  {}: {}", id, first_line(label)),
+            None => println!("  [[{}]] (missing)", link.target),
+        }
+    }
+    if !backlinks.is_empty() {
+        println!("Linked from");
+    }
+    for (id, label) in backlinks {
+        println!("  {}: {}", id, first_line(label));
+    }
+}
 
-    let msg_split = match msg.split_once(" ") {
-        Some(msg_split) => msg_split,
-        None => {
-            println!("Your entry is not valid!");
-            return Ok(());
+// Asks for the passphrase, unless it's known already. False if it was wrong.
+fn try_to_unlock_session(store: &NoteStore) -> bool {
+    match session::unlock(store) {
+        Ok(()) => true,
+        Err(err) => {
+            println!("{}", err);
+            false
         }
+    }
+}
+
+// Locked entries can only be read or changed with the passphrase
+fn can_read(id: usize, store: &NoteStore) -> Result<bool, Box<dyn std::error::Error>> {
+    Ok(!store.is_locked(id)? || try_to_unlock_session(store))
+}
+
+fn try_to_update(
+    id: usize,
+    body: Option<&str>,
+    store: &NoteStore,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // The editor works on a file, which a locked text must never be written to
+    if body.is_none() && store.is_locked(id)? {
+        println!(
+            "Entry {} is locked, give the new text like /edit {} TEXT",
+            id, id
+        );
+        return Ok(());
+    }
+    if !can_read(id, store)? {
+        return Ok(());
+    }
+    let Some(current) = store.body(id)? else {
+        println!("The given id does not exist!");
+        return Ok(());
     };
 
-    let id = msg_split.0;
-    let body = msg_split.1;
+    if let Some(body) = body {
+        store.update(id, body)?;
+        return Ok(());
+    }
+
+    // Without a new text the entry is opened in the editor
+    match editor::edit(&current) {
+        Ok(Some(body)) if body.is_empty() => println!("Empty entry, nothing saved"),
+        Ok(Some(body)) => store.update(id, &body)?,
+        Ok(None) => println!("Nothing changed"),
+        Err(err) => println!("{}", err),
+    }
+    Ok(())
+}
+
+fn try_to_new(store: &NoteStore) -> Result<(), Box<dyn std::error::Error>> {
+    match editor::edit("") {
+        Ok(Some(body)) if !body.is_empty() => {
+            store.create(None, &body)?;
+        }
+        Ok(_) => println!("Empty entry, nothing saved"),
+        Err(err) => println!("{}", err),
+    }
+    Ok(())
+}
+
+fn try_to_show(id: usize, store: &NoteStore) -> Result<(), Box<dyn std::error::Error>> {
+    if !can_read(id, store)? {
+        return Ok(());
+    }
+    match store.body(id)? {
+        Some(body) => println!("{}", body),
+        None => println!("The given id does not exist!"),
+    }
+    Ok(())
+}
 
-    if !id_exists(id, &conn)? {
+fn try_to_title(
+    id: usize,
+    title: Option<&str>,
+    store: &NoteStore,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !store.exists(id)? {
         println!("The given id does not exist!");
         return Ok(());
     }
 
-    conn.execute("UPDATE notes SET body = (?1) WHERE id = (?2)", [body, id])?;
+    store.set_title(id, title)?;
     Ok(())
 }
 
-fn try_to_list(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+fn try_to_pin(
+    id: usize,
+    pinned: bool,
+    store: &NoteStore,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !store.exists(id)? {
+        println!("The given id does not exist!");
+        return Ok(());
+    }
 
-    let ids = get_ids(&conn)?;
-    let bodys = get_entrys(&conn)?;
+    store.set_pinned(id, pinned)?;
+    Ok(())
+}
 
-    for (id, entry) in ids.iter().zip(bodys) {
-        println!("{}: {}", id, entry);
+fn try_to_notebook(
+    id: usize,
+    notebook: Option<&str>,
+    store: &NoteStore,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !store.exists(id)? {
+        println!("The given id does not exist!");
+        return Ok(());
     }
 
+    notebooks::set_notebook(id, notebook, store.conn())?;
     Ok(())
 }
 
-fn try_to_create(msg: &str, conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
-    conn.execute("INSERT INTO notes (body) values (?1)", [msg])?;
+fn try_to_remind(
+    id: usize,
+    when: Option<&When>,
+    store: &NoteStore,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !store.exists(id)? {
+        println!("The given id does not exist!");
+        return Ok(());
+    }
+
+    match store.set_due(id, when) {
+        Ok(Some(due)) => println!("Due {}", due),
+        Ok(None) => println!("No longer due"),
+        Err(err) => println!("{}", err),
+    }
     Ok(())
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let conn = Connection::open(dirs::home_dir().unwrap().to_str().unwrap().to_owned() + "/notes.db")?;
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS notes (
-            id integer primary key,
-            body text not null
-        )",
-        [],
-    )?;
+fn try_to_list_due(store: &NoteStore) -> Result<(), Box<dyn std::error::Error>> {
+    let reminders = store.reminders()?;
+    if reminders.is_empty() {
+        println!("Nothing is due");
+    }
+    print_reminders(&reminders);
+    Ok(())
+}
 
-    println!("/help for all the commands");
+fn try_to_list_todos(done: bool, store: &NoteStore) -> Result<(), Box<dyn std::error::Error>> {
+    let todos = store.todos(done)?;
+    match (todos.is_empty(), done) {
+        (true, false) => println!("Nothing to do"),
+        (true, true) => println!("Nothing done yet"),
+        _ => print_notes(&todos, Sort::Created),
+    }
+    Ok(())
+}
+
+fn try_to_make_todo(id: usize, store: &NoteStore) -> Result<(), Box<dyn std::error::Error>> {
+    if !store.exists(id)? {
+        println!("The given id does not exist!");
+        return Ok(());
+    }
+
+    store.make_todo(id)?;
+    Ok(())
+}
+
+fn try_to_set_done(
+    id: usize,
+    done: bool,
+    store: &NoteStore,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !store.exists(id)? {
+        println!("The given id does not exist!");
+        return Ok(());
+    }
+
+    store.set_done(id, done)?;
+    Ok(())
+}
+
+fn try_to_set_priority(
+    id: usize,
+    priority: Priority,
+    store: &NoteStore,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !store.exists(id)? {
+        println!("The given id does not exist!");
+        return Ok(());
+    }
+
+    store.set_priority(id, priority)?;
+    Ok(())
+}
+
+fn try_to_show_history(id: usize, store: &NoteStore) -> Result<(), Box<dyn std::error::Error>> {
+    if !can_read(id, store)? {
+        return Ok(());
+    }
+    let revisions = store.revisions(id)?;
+    if revisions.is_empty() {
+        println!("The given id does not exist!");
+    }
+    print_history(&revisions);
+    Ok(())
+}
 
-    let mut running = true;
-    while running == true {
+fn try_to_restore(
+    id: usize,
+    rev: usize,
+    store: &NoteStore,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !store.restore(id, rev)? {
+        println!(
+            "Entry {} has no revision {}, /history {} lists them",
+            id, rev, id
+        );
+    }
+    Ok(())
+}
 
-        let mut buffer = String::new();
-        io::stdin().read_line(&mut buffer)?;
+fn try_to_show_links(
+    id: Option<usize>,
+    store: &NoteStore,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(id) = id else {
+        let dangling = links::get_dangling(store.conn())?;
+        if dangling.is_empty() {
+            println!("Every link leads somewhere");
+        }
+        for (source, target) in dangling {
+            println!("{}: [[{}]]", source, target);
+        }
+        return Ok(());
+    };
+    if !store.exists(id)? {
+        println!("The given id does not exist!");
+        return Ok(());
+    }
 
-        let trimmed_body = buffer.trim();
-        let cmd_split = trimmed_body.split_once(" ");
+    let outgoing = links::get_links(id, store.conn())?;
+    let backlinks = links::get_backlinks(id, store.conn())?;
+    if outgoing.is_empty() && backlinks.is_empty() {
+        println!(
+            "Entry {} has no links, [[id]] or [[title]] in an entry links to another",
+            id
+        );
+    }
+    print_links(&outgoing, &backlinks);
+    Ok(())
+}
 
-        let mut cmd = trimmed_body;
-        let mut msg = " ";
+fn try_to_lock(id: Option<usize>, store: &NoteStore) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(id) = id else {
+        session::forget(store)?;
+        println!("Locked entries need the passphrase again");
+        return Ok(());
+    };
+    if !store.exists(id)? {
+        println!("The given id does not exist!");
+        return Ok(());
+    }
 
-        if cmd_split != None {
+    if try_to_unlock_session(store) {
+        store.lock(id)?;
+    }
+    Ok(())
+}
 
-            cmd = cmd_split.unwrap().0;
-            msg = cmd_split.unwrap().1;
+fn try_to_unlock(id: Option<usize>, store: &NoteStore) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(id) = id else {
+        if !store.has_passphrase()? {
+            println!("Nothing is locked yet, /lock [id] locks an entry");
+        } else if try_to_unlock_session(store) {
+            println!("Unlocked, /lock forgets the passphrase again");
+        }
+        return Ok(());
+    };
+    if !store.exists(id)? {
+        println!("The given id does not exist!");
+        return Ok(());
+    }
+    if !store.is_locked(id)? {
+        println!("Entry {} isn't locked", id);
+        return Ok(());
+    }
 
+    if try_to_unlock_session(store) {
+        store.unlock_note(id)?;
+    }
+    Ok(())
+}
+
+fn try_to_sync(dir: &str, store: &NoteStore) -> Result<(), Box<dyn std::error::Error>> {
+    session::resume(store)?;
+    let synced = match sync::sync(Path::new(dir), store.conn(), store.key().as_deref()) {
+        Ok(synced) => synced,
+        // The drive may just not be there right now
+        Err(err) => {
+            println!("Could not sync: {}", err);
+            return Ok(());
         }
+    };
+    println!(
+        "Sent {} and received {} changes",
+        synced.sent, synced.received
+    );
+    if synced.locked > 0 {
+        println!(
+            "{} locked entries stay here until they're unlocked",
+            synced.locked
+        );
+    }
+    if synced.conflicts > 0 {
+        println!(
+            "{} entries were changed here and elsewhere, /conflicts lists them",
+            synced.conflicts
+        );
+    }
+    Ok(())
+}
+
+fn try_to_list_conflicts(store: &NoteStore) -> Result<(), Box<dyn std::error::Error>> {
+    let conflicts = sync::get_conflicts(store.conn())?;
+    if conflicts.is_empty() {
+        println!("No conflicts");
+    }
+    print_conflicts(&conflicts);
+    Ok(())
+}
+
+fn try_to_resolve(
+    conflict: usize,
+    take_other: bool,
+    store: &NoteStore,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match sync::resolve(conflict, take_other, store.conn()) {
+        Ok(true) => {}
+        Ok(false) => println!("There is no conflict {}, /conflicts lists them", conflict),
+        Err(err) => println!("{}", err),
+    }
+    Ok(())
+}
+
+fn try_to_list_notebooks(store: &NoteStore) -> Result<(), Box<dyn std::error::Error>> {
+    for (notebook, count) in notebooks::get_notebooks(store.conn())? {
+        println!("{} ({})", notebook, count);
+    }
+    Ok(())
+}
 
-        if cmd == "" || cmd == "/exit" || cmd == "/quit" {
-            running = false;
-        } else if cmd == "/del" || cmd == "/d" {
-            try_to_delete(msg, &conn)?;
-        } else if cmd == "/edit" || cmd == "/e" {
-            try_to_update(msg, &conn)?;
-        } else if cmd == "/list" || cmd == "/l" {
-            try_to_list(&conn)?;
-        } else if cmd == "/help" || cmd == "/h" {
-            println!("\t /del [entry] for deleting an entry");
-            println!("\t /edit [id] [entry] for editing an entry");
-            println!("\t /list for listing all entrys");
-            println!("\t Hint: /d, /e and /l also work");
-        } else if cmd.starts_with("/") {
-            println!("Not a valid command. Please try again");
-        } else {
-            try_to_create(trimmed_body, &conn)?;
+fn try_to_list_tags(store: &NoteStore) -> Result<(), Box<dyn std::error::Error>> {
+    for (tag, count) in tags::get_tags(store.conn())? {
+        println!("#{} ({})", tag, count);
+    }
+    Ok(())
+}
+
+fn try_to_rename_tag(
+    old: &str,
+    new: &str,
+    store: &NoteStore,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (Some(old), Some(new)) = (tags::normalise(old), tags::normalise(new)) else {
+        println!("Tags are made of letters, digits, -, _ and /");
+        return Ok(());
+    };
+    if old == new {
+        println!("#{} is already called that, nothing changed", old);
+        return Ok(());
+    }
+    let (changed, merged) = tags::rename_tag(&old, &new, store.conn())?;
+    let verb = if merged { "Merged" } else { "Renamed" };
+    println!("{} #{} into #{} in {} entries", verb, old, new, changed);
+    Ok(())
+}
+
+fn try_to_create(msg: &str, store: &NoteStore) -> Result<(), Box<dyn std::error::Error>> {
+    store.create(None, msg)?;
+    Ok(())
+}
+
+fn try_to_search(query: &str, store: &NoteStore) -> Result<(), Box<dyn std::error::Error>> {
+    let results = match store.search(query, highlight()) {
+        Ok(results) => results,
+        // FTS5 has its own query syntax, a typo in it shouldn't end the session
+        Err(err) => {
+            println!("Not a valid search: {}", err);
+            return Ok(());
         }
+    };
+
+    if results.is_empty() {
+        println!("Nothing found for {}", query);
+    }
+    for (id, snippet) in results {
+        println!("{}: {}", id, snippet);
     }
 
     Ok(())
+}
 
+// One entry from the prompt, a line ending in \ goes on in the next one.
+// None once Ctrl-D ends the input, Ctrl-C starts over.
+fn read_entry(
+    prompt: &mut Editor<Completions, DefaultHistory>,
+) -> Result<Option<String>, ReadlineError> {
+    let interactive = io::stdin().is_terminal();
+    let mut lines: Vec<String> = vec![];
+    loop {
+        let text = match (interactive, lines.is_empty()) {
+            (false, _) => "",
+            (true, true) => "> ",
+            (true, false) => ".. ",
+        };
+        match prompt.readline(text) {
+            Ok(line) => match line.strip_suffix('\\') {
+                Some(line) => lines.push(line.to_string()),
+                None => {
+                    lines.push(line);
+                    return Ok(Some(lines.join("\n")));
+                }
+            },
+            Err(ReadlineError::Interrupted) => lines.clear(),
+            Err(ReadlineError::Eof) if lines.is_empty() => return Ok(None),
+            Err(ReadlineError::Eof) => return Ok(Some(lines.join("\n"))),
+            Err(err) => return Err(err),
+        }
+    }
 }
 
+fn run(command: Command, store: &NoteStore) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        Command::Create(body) => try_to_create(&body, store),
+        Command::Delete(id) => try_to_delete(id, store),
+        Command::Edit(id, body) => try_to_update(id, body.as_deref(), store),
+        Command::New => try_to_new(store),
+        Command::Show(id) => try_to_show(id, store),
+        Command::Title(id, title) => try_to_title(id, title.as_deref(), store),
+        Command::Pin(id, pinned) => try_to_pin(id, pinned, store),
+        Command::Notebook(id, notebook) => try_to_notebook(id, notebook.as_deref(), store),
+        Command::Remind(id, when) => try_to_remind(id, when.as_ref(), store),
+        Command::Due => try_to_list_due(store),
+        Command::NewTodo(body) => {
+            store.create_todo(&body)?;
+            Ok(())
+        }
+        Command::Todo(id) => try_to_make_todo(id, store),
+        Command::Todos(done) => try_to_list_todos(done, store),
+        Command::Done(id, done) => try_to_set_done(id, done, store),
+        Command::Priority(id, priority) => try_to_set_priority(id, priority, store),
+        Command::History(id) => try_to_show_history(id, store),
+        Command::Restore(id, rev) => try_to_restore(id, rev, store),
+        Command::Links(id) => try_to_show_links(id, store),
+        Command::Lock(id) => try_to_lock(id, store),
+        Command::Unlock(id) => try_to_unlock(id, store),
+        Command::Sync(dir) => try_to_sync(&dir, store),
+        Command::Conflicts => try_to_list_conflicts(store),
+        Command::Resolve(conflict, take_other) => try_to_resolve(conflict, take_other, store),
+        Command::Notebooks => try_to_list_notebooks(store),
+        Command::Tags => try_to_list_tags(store),
+        Command::RenameTag(old, new) => try_to_rename_tag(&old, &new, store),
+        Command::List(options) => {
+            print_notes(&store.list(&options)?, options.sort);
+            Ok(())
+        }
+        Command::Search(query) => try_to_search(&query, store),
+        Command::Help => {
+            println!("{}", command::help());
+            Ok(())
+        }
+        Command::Quit => Ok(()),
+    }
+}
 
+// Tab completes commands and the ids of the entries at the prompt
+#[derive(Default, Helper, Hinter, Highlighter, Validator)]
+struct Completions {
+    entries: Vec<(usize, String)>,
+}
+
+impl Completer for Completions {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let (start, candidates) = command::complete(&line[..pos], &self.entries);
+        let candidates = candidates
+            .into_iter()
+            .map(|(display, replacement)| Pair {
+                display,
+                replacement,
+            })
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+// Finds the database from the arguments, environment and config file and brings it up to date
+fn open_database(
+    args: &mut Vec<String>,
+) -> Result<(NoteStore, PathBuf), Box<dyn std::error::Error>> {
+    let (db, profile) = config::take_options(args)?;
+    let path = config::database_path(db.as_deref(), profile.as_deref())?;
+    Ok((NoteStore::open(&path)?, path))
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let (store, path) = match open_database(&mut args) {
+        Ok(opened) => opened,
+        Err(err) => {
+            eprintln!("noted: {}", err);
+            std::process::exit(cli::FAILED);
+        }
+    };
+
+    if !args.is_empty() {
+        let code = match cli::run(&args, &store) {
+            Ok(code) => code,
+            Err(err) => {
+                eprintln!("noted: {}", err);
+                cli::FAILED
+            }
+        };
+        std::process::exit(code);
+    }
+
+    println!("/help for all the commands");
+
+    // Lines starting with a space stay out of the history, like in most shells
+    let config = rustyline::Config::builder()
+        .max_history_size(1000)?
+        .history_ignore_dups(true)?
+        .history_ignore_space(true)
+        .build();
+    let mut prompt: Editor<Completions, DefaultHistory> = Editor::with_config(config)?;
+    prompt.set_helper(Some(Completions::default()));
+    let history = config::history_path(&path);
+    if let Some(history) = &history {
+        // There is none yet the first time
+        let _ = prompt.load_history(history);
+    }
+
+    loop {
+        // Fresh ids for completion, entries may have changed with the last command
+        if let Some(completions) = prompt.helper_mut() {
+            completions.entries = store
+                .list(&ListOptions::parse(&[])?)?
+                .into_iter()
+                .map(|note| {
+                    let label = note.title.clone().unwrap_or_else(|| summary(&note));
+                    (note.id, label)
+                })
+                .collect();
+        }
+
+        let Some(line) = read_entry(&mut prompt)? else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        // The text of a locked entry isn't written down anywhere else either
+        let command = command::parse(&line);
+        let secret = matches!(&command, Ok(Command::Edit(id, Some(_)))
+            if store.is_locked(*id).unwrap_or(true));
+        if !secret {
+            prompt.add_history_entry(line.as_str())?;
+            if let Some(history) = &history {
+                if let Err(err) = prompt.append_history(history) {
+                    println!("Could not save the history: {}", err);
+                }
+            }
+        }
+
+        match command {
+            Ok(Command::Quit) => break,
+            Ok(command) => run(command, &store)?,
+            Err(err) => println!("{}", err),
+        }
+    }
+
+    Ok(())
+}
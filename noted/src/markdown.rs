@@ -0,0 +1,342 @@
+use crate::export::{each_entry, import_entry, Change, Entry, Exported, Imported};
+use crate::store::Priority;
+use crate::tags::extract_tags;
+use rusqlite::Connection;
+use std::fs;
+use std::path::Path;
+
+// One file per note named after its id and title, with the metadata as YAML front matter.
+// Files from an earlier export of the same notes are replaced, even if the title changed,
+// other files in the directory are left alone. Locked notes aren't written.
+pub fn export(dir: &Path, conn: &Connection) -> Result<Exported, Box<dyn std::error::Error>> {
+    fs::create_dir_all(dir)?;
+    let mut written = vec![];
+    let mut locked = 0;
+    each_entry(false, conn, |entry| {
+        if entry.sealed.is_some() {
+            locked += 1;
+            return Ok(());
+        }
+        let name = format!("{}-{}.md", entry.id.unwrap_or_default(), slug(&entry));
+        fs::write(dir.join(&name), to_markdown(&entry))?;
+        written.push((entry.id, name));
+        Ok(())
+    })?;
+
+    for file in fs::read_dir(dir)? {
+        let path = file?.path();
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("");
+        let exported_id = name
+            .strip_suffix(".md")
+            .and_then(|name| name.split('-').next())
+            .and_then(|id| id.parse::<i64>().ok());
+        let stale = written
+            .iter()
+            .any(|(id, written)| *id == exported_id && written != name);
+        if exported_id.is_some() && stale && exported_by_us(&path, exported_id) {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(Exported {
+        written: written.len(),
+        locked,
+    })
+}
+
+// Only files with the id in their front matter came from an export, 1-intro.md of the
+// user's own doesn't
+fn exported_by_us(path: &Path, id: Option<i64>) -> bool {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|text| from_markdown(&text).ok())
+        .is_some_and(|entry| entry.id == id)
+}
+
+// All or nothing. Files without an id get the id of their new note written into them,
+// so importing them again doesn't add them twice.
+pub fn import(dir: &Path, conn: &Connection) -> Result<Imported, Box<dyn std::error::Error>> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|file| file.map(|file| file.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.retain(|path| path.extension().is_some_and(|ext| ext == "md"));
+    paths.sort();
+
+    let tx = conn.unchecked_transaction()?;
+    let mut imported = Imported::default();
+    let mut new_ids = vec![];
+    for path in &paths {
+        let mut entry = from_markdown(&fs::read_to_string(path)?)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        match import_entry(&entry, false, &tx)? {
+            Change::Created(id) => {
+                imported.created += 1;
+                if entry.id.is_none() {
+                    entry.id = Some(id);
+                    entry.tags = extract_tags(&entry.body);
+                    new_ids.push((path, entry));
+                }
+            }
+            Change::Updated => imported.updated += 1,
+            Change::Unchanged => imported.unchanged += 1,
+        }
+    }
+    tx.commit()?;
+
+    for (path, entry) in new_ids {
+        fs::write(path, to_markdown(&entry))?;
+    }
+    Ok(imported)
+}
+
+fn slug(entry: &Entry) -> String {
+    let source = entry
+        .title
+        .as_deref()
+        .unwrap_or_else(|| entry.body.lines().next().unwrap_or_default());
+    let slug = source
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-");
+    match slug.char_indices().nth(40) {
+        Some((end, _)) => slug[..end].trim_end_matches('-').to_string(),
+        None if slug.is_empty() => "note".to_string(),
+        None => slug,
+    }
+}
+
+pub fn to_markdown(entry: &Entry) -> String {
+    let mut text = String::from("---\n");
+    if let Some(id) = entry.id {
+        text.push_str(&format!("id: {}\n", id));
+    }
+    if let Some(title) = &entry.title {
+        text.push_str(&format!("title: {}\n", quote(title)));
+    }
+    if let Some(created) = &entry.created {
+        text.push_str(&format!("created: {}\n", created));
+    }
+    if let Some(updated) = &entry.updated {
+        text.push_str(&format!("updated: {}\n", updated));
+    }
+    if entry.pinned {
+        text.push_str("pinned: true\n");
+    }
+    if let Some(due) = &entry.due {
+        text.push_str(&format!("due: {}\n", due));
+    }
+    if entry.todo {
+        text.push_str("todo: true\n");
+    }
+    if entry.priority != Priority::None {
+        text.push_str(&format!("priority: {}\n", entry.priority.name()));
+    }
+    if let Some(done) = &entry.done {
+        text.push_str(&format!("done: {}\n", done));
+    }
+    if let Some(notebook) = &entry.notebook {
+        text.push_str(&format!("notebook: {}\n", quote(notebook)));
+    }
+    let tags: Vec<String> = entry.tags.iter().map(|tag| quote(tag)).collect();
+    text.push_str(&format!("tags: [{}]\n", tags.join(", ")));
+    text.push_str("---\n");
+    text.push_str(&entry.body);
+    text.push('\n');
+    text
+}
+
+// Reads the front matter written by to_markdown, plain markdown files become new notes
+pub fn from_markdown(text: &str) -> Result<Entry, String> {
+    let Some(rest) = text.strip_prefix("---\n") else {
+        return Ok(Entry {
+            body: text.trim_end().to_string(),
+            ..Entry::default()
+        });
+    };
+    let (front_matter, body) = match rest.split_once("\n---\n") {
+        Some(split) => split,
+        None => (
+            rest.strip_suffix("\n---")
+                .ok_or("front matter never ends")?,
+            "",
+        ),
+    };
+
+    let mut entry = Entry {
+        body: body.trim_end().to_string(),
+        ..Entry::default()
+    };
+    for line in front_matter.lines().filter(|line| !line.trim().is_empty()) {
+        let (key, value) = line
+            .split_once(':')
+            .ok_or_else(|| format!("expected `key: value` instead of {}", line))?;
+        let value = value.trim();
+        match key.trim() {
+            "id" => {
+                entry.id = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("{} is not a note id", value))?,
+                )
+            }
+            "title" => entry.title = Some(unquote(value)?),
+            "created" => entry.created = Some(unquote(value)?),
+            "updated" => entry.updated = Some(unquote(value)?),
+            "pinned" => entry.pinned = value == "true",
+            "due" => entry.due = Some(unquote(value)?),
+            "todo" => entry.todo = value == "true",
+            "priority" => {
+                entry.priority = Priority::parse(&unquote(value)?)
+                    .ok_or_else(|| format!("{} is not a priority", value))?
+            }
+            "done" => entry.done = Some(unquote(value)?),
+            "notebook" => entry.notebook = Some(unquote(value)?),
+            "tags" => {
+                let list = value
+                    .strip_prefix('[')
+                    .and_then(|value| value.strip_suffix(']'))
+                    .ok_or("tags should be a list like [home, work]")?;
+                entry.tags = list
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|tag| !tag.is_empty())
+                    .map(unquote)
+                    .collect::<Result<_, _>>()?;
+            }
+            // Other tools may add their own keys
+            _ => {}
+        }
+    }
+    Ok(entry)
+}
+
+// Double quoted YAML strings escape like JSON, so anything that isn't plain gets quoted that way
+fn quote(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == ' ' || c == '-' || c == '_' || c == '/')
+        && !value.starts_with(' ')
+        && !value.ends_with(' ');
+    match plain {
+        true => value.to_string(),
+        false => serde_json::to_string(value).unwrap_or_default(),
+    }
+}
+
+fn unquote(value: &str) -> Result<String, String> {
+    if value.starts_with('"') {
+        serde_json::from_str(value).map_err(|err| format!("{} in {}", err, value))
+    } else if let Some(value) = value
+        .strip_prefix('\'')
+        .and_then(|value| value.strip_suffix('\''))
+    {
+        Ok(value.replace("''", "'"))
+    } else {
+        Ok(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::each_entry;
+    use crate::migrations::migrate;
+
+    fn db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        migrate(&conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn front_matter_survives_a_round_trip() {
+        let entry = Entry {
+            id: Some(7),
+            title: Some("Plans: \"2024\"".to_string()),
+            body: "---\nnot front matter\n#home".to_string(),
+            created: Some("2024-01-31T09:00:00Z".to_string()),
+            updated: Some("2024-02-01T10:30:00Z".to_string()),
+            pinned: true,
+            notebook: Some("Work Stuff".to_string()),
+            tags: vec!["home".to_string(), "work/x".to_string()],
+            ..Entry::default()
+        };
+        let read = from_markdown(&to_markdown(&entry)).unwrap();
+        assert_eq!(read.id, entry.id);
+        assert_eq!(read.title, entry.title);
+        assert_eq!(read.body, entry.body);
+        assert_eq!(read.created, entry.created);
+        assert_eq!(read.updated, entry.updated);
+        assert!(read.pinned);
+        assert_eq!(read.notebook, entry.notebook);
+        assert_eq!(read.tags, entry.tags);
+    }
+
+    #[test]
+    fn exports_replace_only_their_own_files() {
+        let conn = db();
+        conn.execute("INSERT INTO notes (id, body) VALUES (1, 'intro')", [])
+            .unwrap();
+        let dir = std::env::temp_dir().join(format!("noted-md-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("1-old-title.md"), "---\nid: 1\n---\nintro\n").unwrap();
+        fs::write(dir.join("1-my-own.md"), "# Mine\n").unwrap();
+        fs::write(dir.join("1-other.md"), "---\nid: 2\n---\nsomeone else\n").unwrap();
+
+        assert_eq!(export(&dir, &conn).unwrap().written, 1);
+        let mut names: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|file| file.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["1-intro.md", "1-my-own.md", "1-other.md"]);
+    }
+
+    #[test]
+    fn plain_markdown_is_a_new_note() {
+        let entry = from_markdown("# Heading\n\ntext\n").unwrap();
+        assert_eq!(entry.id, None);
+        assert_eq!(entry.body, "# Heading\n\ntext");
+        assert!(from_markdown("---\nid: 1\n").is_err());
+    }
+
+    #[test]
+    fn import_updates_notes_with_a_known_id() {
+        let conn = db();
+        let mut entry =
+            from_markdown("---\nid: 5\ncreated: 2024-01-31T09:00:00Z\n---\nold #a").unwrap();
+        assert!(matches!(
+            import_entry(&entry, false, &conn).unwrap(),
+            Change::Created(5)
+        ));
+        assert!(matches!(
+            import_entry(&entry, false, &conn).unwrap(),
+            Change::Unchanged
+        ));
+
+        entry.body = "new #b".to_string();
+        entry.notebook = Some("Work".to_string());
+        assert!(matches!(
+            import_entry(&entry, false, &conn).unwrap(),
+            Change::Updated
+        ));
+
+        let mut exported = vec![];
+        each_entry(false, &conn, |entry| {
+            exported.push(entry);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(exported.len(), 1);
+        assert_eq!(exported[0].body, "new #b");
+        assert_eq!(exported[0].created.as_deref(), Some("2024-01-31T09:00:00Z"));
+        assert_eq!(exported[0].notebook.as_deref(), Some("Work"));
+        assert_eq!(exported[0].tags, vec!["b"]);
+    }
+}
@@ -0,0 +1,360 @@
+use crate::{links, tags};
+use rusqlite::Connection;
+
+enum Migration {
+    Sql(&'static str),
+    // For changes SQL alone can't make, like filling new tables from note bodies
+    Rust(&'static str, fn(&Connection) -> rusqlite::Result<()>),
+}
+
+// Every entry upgrades the schema by one version, PRAGMA user_version remembers how many
+// already ran. Databases from before migrations existed are at version 0 but may already
+// have a notes table and an index, so the first one has to cope with both.
+const MIGRATIONS: &[Migration] = &[
+    // 1: notes with a full-text index kept in sync by triggers
+    Migration::Sql(
+        "CREATE TABLE IF NOT EXISTS notes (
+            id integer primary key,
+            body text not null
+        );
+        CREATE VIRTUAL TABLE IF NOT EXISTS notes_fts USING fts5(
+            body, content='notes', content_rowid='id'
+        );
+        CREATE TRIGGER IF NOT EXISTS notes_fts_insert AFTER INSERT ON notes BEGIN
+            INSERT INTO notes_fts (rowid, body) VALUES (new.id, new.body);
+        END;
+        CREATE TRIGGER IF NOT EXISTS notes_fts_delete AFTER DELETE ON notes BEGIN
+            INSERT INTO notes_fts (notes_fts, rowid, body) VALUES ('delete', old.id, old.body);
+        END;
+        CREATE TRIGGER IF NOT EXISTS notes_fts_update AFTER UPDATE ON notes BEGIN
+            INSERT INTO notes_fts (notes_fts, rowid, body) VALUES ('delete', old.id, old.body);
+            INSERT INTO notes_fts (rowid, body) VALUES (new.id, new.body);
+        END;
+        INSERT INTO notes_fts (notes_fts) VALUES ('rebuild');",
+    ),
+    // 2: titles, timestamps, pinning and soft-delete. Existing notes didn't record when they
+    // were written, so they count as written now. Titles are searchable too.
+    Migration::Sql(
+        "ALTER TABLE notes ADD COLUMN title text;
+        ALTER TABLE notes ADD COLUMN created_at text;
+        ALTER TABLE notes ADD COLUMN updated_at text;
+        ALTER TABLE notes ADD COLUMN pinned integer not null default 0;
+        ALTER TABLE notes ADD COLUMN deleted_at text;
+        UPDATE notes SET created_at = datetime('now'), updated_at = datetime('now');
+        CREATE INDEX notes_created_at ON notes (created_at);
+        CREATE INDEX notes_updated_at ON notes (updated_at);
+
+        DROP TRIGGER notes_fts_insert;
+        DROP TRIGGER notes_fts_delete;
+        DROP TRIGGER notes_fts_update;
+        DROP TABLE notes_fts;
+        CREATE VIRTUAL TABLE notes_fts USING fts5(
+            title, body, content='notes', content_rowid='id'
+        );
+        CREATE TRIGGER notes_fts_insert AFTER INSERT ON notes BEGIN
+            INSERT INTO notes_fts (rowid, title, body) VALUES (new.id, new.title, new.body);
+        END;
+        CREATE TRIGGER notes_fts_delete AFTER DELETE ON notes BEGIN
+            INSERT INTO notes_fts (notes_fts, rowid, title, body)
+                VALUES ('delete', old.id, old.title, old.body);
+        END;
+        CREATE TRIGGER notes_fts_update AFTER UPDATE OF title, body ON notes BEGIN
+            INSERT INTO notes_fts (notes_fts, rowid, title, body)
+                VALUES ('delete', old.id, old.title, old.body);
+            INSERT INTO notes_fts (rowid, title, body) VALUES (new.id, new.title, new.body);
+        END;
+        INSERT INTO notes_fts (notes_fts) VALUES ('rebuild');",
+    ),
+    // 3: #tags found in the bodies and notebooks to put notes into
+    Migration::Rust(
+        "CREATE TABLE tags (
+            id integer primary key,
+            name text not null unique
+        );
+        CREATE TABLE note_tags (
+            note_id integer not null references notes (id),
+            tag_id integer not null references tags (id),
+            primary key (note_id, tag_id)
+        );
+        CREATE INDEX note_tags_tag_id ON note_tags (tag_id);
+        CREATE TABLE notebooks (
+            id integer primary key,
+            name text not null unique
+        );
+        ALTER TABLE notes ADD COLUMN notebook_id integer references notebooks (id);",
+        tags::sync_all_tags,
+    ),
+    // 4: due dates, reminded_at is when `noted due --check` last told about the note
+    Migration::Sql(
+        "ALTER TABLE notes ADD COLUMN due_at text;
+        ALTER TABLE notes ADD COLUMN reminded_at text;
+        CREATE INDEX notes_due_at ON notes (due_at);",
+    ),
+    // 5: todos are notes of another kind that can be done and have a priority from 0 to 3
+    Migration::Sql(
+        "ALTER TABLE notes ADD COLUMN kind text not null default 'note'
+            CHECK (kind IN ('note', 'todo'));
+        ALTER TABLE notes ADD COLUMN priority integer not null default 0;
+        ALTER TABLE notes ADD COLUMN done_at text;
+        CREATE INDEX notes_kind ON notes (kind, done_at);",
+    ),
+    // 6: every text a note had, numbered from 1. Triggers record them no matter what changed
+    // the note, existing notes start with what they are now.
+    Migration::Sql(
+        "CREATE TABLE note_revisions (
+            note_id integer not null references notes (id),
+            rev integer not null,
+            title text,
+            body text not null,
+            deleted_at text,
+            saved_at text not null,
+            primary key (note_id, rev)
+        );
+        INSERT INTO note_revisions (note_id, rev, title, body, deleted_at, saved_at)
+            SELECT id, 1, title, body, deleted_at,
+                   coalesce(deleted_at, updated_at, datetime('now'))
+            FROM notes;
+        CREATE TRIGGER note_revisions_insert AFTER INSERT ON notes BEGIN
+            INSERT INTO note_revisions (note_id, rev, title, body, deleted_at, saved_at)
+                SELECT new.id, coalesce(max(rev), 0) + 1, new.title, new.body, new.deleted_at,
+                       datetime('now')
+                FROM note_revisions WHERE note_id = new.id;
+        END;
+        CREATE TRIGGER note_revisions_update AFTER UPDATE OF title, body, deleted_at ON notes
+        WHEN old.title IS NOT new.title OR old.body IS NOT new.body
+            OR (old.deleted_at IS NULL) IS NOT (new.deleted_at IS NULL)
+        BEGIN
+            INSERT INTO note_revisions (note_id, rev, title, body, deleted_at, saved_at)
+                SELECT new.id, coalesce(max(rev), 0) + 1, new.title, new.body, new.deleted_at,
+                       datetime('now')
+                FROM note_revisions WHERE note_id = new.id;
+        END;",
+    ),
+    // 7: locked notes keep their text only sealed with a key derived from a passphrase, their
+    // body and those of their revisions are empty. The vault has what deriving the key needs.
+    Migration::Sql(
+        "CREATE TABLE vault (
+            id integer primary key CHECK (id = 1),
+            salt blob not null,
+            memory integer not null,
+            iterations integer not null,
+            lanes integer not null,
+            check_value blob not null
+        );
+        ALTER TABLE notes ADD COLUMN sealed blob;
+        ALTER TABLE note_revisions ADD COLUMN sealed blob;
+
+        DROP TRIGGER note_revisions_insert;
+        DROP TRIGGER note_revisions_update;
+        CREATE TRIGGER note_revisions_insert AFTER INSERT ON notes BEGIN
+            INSERT INTO note_revisions (note_id, rev, title, body, sealed, deleted_at, saved_at)
+                SELECT new.id, coalesce(max(rev), 0) + 1, new.title, new.body, new.sealed,
+                       new.deleted_at, datetime('now')
+                FROM note_revisions WHERE note_id = new.id;
+        END;
+        CREATE TRIGGER note_revisions_update
+        AFTER UPDATE OF title, body, sealed, deleted_at ON notes
+        WHEN old.title IS NOT new.title OR old.body IS NOT new.body
+            OR old.sealed IS NOT new.sealed
+            OR (old.deleted_at IS NULL) IS NOT (new.deleted_at IS NULL)
+        BEGIN
+            INSERT INTO note_revisions (note_id, rev, title, body, sealed, deleted_at, saved_at)
+                SELECT new.id, coalesce(max(rev), 0) + 1, new.title, new.body, new.sealed,
+                       new.deleted_at, datetime('now')
+                FROM note_revisions WHERE note_id = new.id;
+        END;",
+    ),
+    // 8: [[links]] between notes. Targets are kept as typed and resolved when they're read,
+    // to the note with that id or the oldest one with that title, so a link to a deleted or
+    // renamed note simply stops leading anywhere.
+    Migration::Rust(
+        "CREATE TABLE links (
+            source_id integer not null references notes (id),
+            target text not null,
+            primary key (source_id, target)
+        );
+        CREATE VIEW resolved_links AS
+            SELECT source_id, target,
+                   CASE WHEN target NOT GLOB '*[^0-9]*'
+                       THEN (SELECT id FROM notes
+                             WHERE id = CAST(target AS integer) AND deleted_at IS NULL)
+                       ELSE (SELECT id FROM notes
+                             WHERE title = target COLLATE NOCASE AND deleted_at IS NULL
+                             ORDER BY id LIMIT 1)
+                   END AS target_id
+            FROM links;",
+        links::sync_all_links,
+    ),
+    // 9: syncing through a shared directory. Every database is a device with a Lamport clock,
+    // and every note has a UUID that's the same everywhere and a version vector that counts
+    // the changes each device made to it. dirty notes changed here since the last sync.
+    // Triggers keep it all up to date for local changes, sync sets version itself.
+    Migration::Sql(
+        "CREATE TABLE sync_state (
+            id integer primary key CHECK (id = 1),
+            device text not null,
+            clock integer not null
+        );
+        INSERT INTO sync_state (id, device, clock) VALUES (1, lower(hex(randomblob(8))), 1);
+        CREATE TABLE sync_peers (
+            device text primary key,
+            -- Lines of its change log that were read already
+            seen integer not null
+        );
+        CREATE TABLE conflicts (
+            id integer primary key,
+            note_id integer not null references notes (id),
+            -- The version that lost, from this device or the other one
+            device text not null,
+            lamport integer not null,
+            version text not null,
+            title text,
+            body text not null,
+            deleted_at text,
+            found_at text not null
+        );
+        ALTER TABLE notes ADD COLUMN uuid text;
+        ALTER TABLE notes ADD COLUMN version text not null default '{}';
+        ALTER TABLE notes ADD COLUMN lamport integer not null default 0;
+        ALTER TABLE notes ADD COLUMN changed_by text;
+        ALTER TABLE notes ADD COLUMN dirty integer not null default 0;
+        UPDATE notes SET uuid = lower(hex(randomblob(4))) || '-' || lower(hex(randomblob(2))) || '-4'
+                || substr(lower(hex(randomblob(2))), 2) || '-'
+                || substr('89ab', 1 + abs(random()) % 4, 1)
+                || substr(lower(hex(randomblob(2))), 2) || '-' || lower(hex(randomblob(6))),
+            version = json_object((SELECT device FROM sync_state), 1), lamport = 1,
+            changed_by = (SELECT device FROM sync_state), dirty = 1;
+        CREATE UNIQUE INDEX notes_uuid ON notes (uuid);
+        CREATE INDEX notes_dirty ON notes (dirty);
+        CREATE TRIGGER notes_sync_insert AFTER INSERT ON notes WHEN new.uuid IS NULL
+        BEGIN
+            UPDATE sync_state SET clock = clock + 1;
+            UPDATE notes SET uuid = lower(hex(randomblob(4))) || '-' || lower(hex(randomblob(2))) || '-4'
+                || substr(lower(hex(randomblob(2))), 2) || '-'
+                || substr('89ab', 1 + abs(random()) % 4, 1)
+                || substr(lower(hex(randomblob(2))), 2) || '-' || lower(hex(randomblob(6))),
+                version = json_object((SELECT device FROM sync_state), 1),
+                lamport = (SELECT clock FROM sync_state),
+                changed_by = (SELECT device FROM sync_state), dirty = 1
+            WHERE id = new.id;
+        END;
+        CREATE TRIGGER notes_sync_update
+        AFTER UPDATE OF title, body, sealed, pinned, deleted_at, notebook_id, due_at, kind,
+            priority, done_at ON notes
+        WHEN new.version IS old.version AND (
+            old.title IS NOT new.title OR old.body IS NOT new.body
+            OR old.sealed IS NOT new.sealed OR old.pinned IS NOT new.pinned
+            OR old.deleted_at IS NOT new.deleted_at OR old.notebook_id IS NOT new.notebook_id
+            OR old.due_at IS NOT new.due_at OR old.kind IS NOT new.kind
+            OR old.priority IS NOT new.priority OR old.done_at IS NOT new.done_at)
+        BEGIN
+            UPDATE sync_state SET clock = clock + 1;
+            UPDATE notes SET
+                version = json_set(version, '$.' || (SELECT device FROM sync_state),
+                    coalesce(json_extract(version, '$.' || (SELECT device FROM sync_state)), 0)
+                    + 1),
+                lamport = (SELECT clock FROM sync_state),
+                changed_by = (SELECT device FROM sync_state), dirty = 1
+            WHERE id = new.id;
+        END;",
+    ),
+    // 10: ids differ between devices, so sync sends [[id]] links as [[uuid]] and turns them
+    // back into ids where it can. A link to a note that didn't arrive yet keeps the UUID,
+    // which leads to the note once it's here.
+    Migration::Sql(
+        "DROP VIEW resolved_links;
+        CREATE VIEW resolved_links AS
+            SELECT source_id, target,
+                   CASE WHEN target NOT GLOB '*[^0-9]*'
+                       THEN (SELECT id FROM notes
+                             WHERE id = CAST(target AS integer) AND deleted_at IS NULL)
+                       ELSE coalesce(
+                           (SELECT id FROM notes WHERE uuid = target AND deleted_at IS NULL),
+                           (SELECT id FROM notes
+                            WHERE title = target COLLATE NOCASE AND deleted_at IS NULL
+                            ORDER BY id LIMIT 1))
+                   END AS target_id
+            FROM links;",
+    ),
+    // 11: the other version of a conflict is sealed like the revisions when its note is locked
+    Migration::Sql("ALTER TABLE conflicts ADD COLUMN sealed blob;"),
+];
+
+// Runs the migrations the database hasn't seen yet, each one all or nothing
+pub fn migrate(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version > MIGRATIONS.len() {
+        return Err(format!(
+            "the database is at version {}, this noted only knows up to {}",
+            version,
+            MIGRATIONS.len()
+        )
+        .into());
+    }
+
+    for (done, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.unchecked_transaction()?;
+        match migration {
+            Migration::Sql(sql) => tx.execute_batch(sql)?,
+            Migration::Rust(sql, migrate) => {
+                tx.execute_batch(sql)?;
+                migrate(&tx)?;
+            }
+        }
+        tx.pragma_update(None, "user_version", done + 1)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{ListOptions, NoteStore};
+
+    #[test]
+    fn databases_from_before_migrations_are_brought_up_to_date() {
+        // What the very first noted created, without an index or a version
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE notes (id integer primary key, body text not null);
+            INSERT INTO notes (body) VALUES ('buy milk #home'), ('then call the plumber, see [[1]]');",
+        )
+        .unwrap();
+        let store = NoteStore::new(conn).unwrap();
+        let conn = store.conn();
+
+        let version: usize = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, 11);
+
+        let notes = store.list(&ListOptions::parse(&[]).unwrap()).unwrap();
+        assert_eq!(notes.len(), 2);
+        for note in &notes {
+            assert_eq!(note.title, None);
+            assert!(!note.created_at.is_empty() && !note.updated_at.is_empty());
+        }
+        let uuids: Vec<String> = conn
+            .prepare("SELECT uuid FROM notes")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(uuids.len(), 2);
+        assert!(uuids.iter().all(|uuid| uuid.len() == 36) && uuids[0] != uuids[1]);
+
+        assert_eq!(
+            store.search("plumber", ("", "")).unwrap(),
+            vec![(2, "then call the plumber, see [[1]]".to_string())]
+        );
+        assert_eq!(notes[0].tags, vec!["home"]);
+        assert_eq!(links::get_backlinks(1, conn).unwrap()[0].0, 2);
+
+        // And they keep working for what's written after the upgrade
+        store.update(1, "buy oat milk #home").unwrap();
+        assert_eq!(store.revisions(1).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,40 @@
+use fallible_iterator::FallibleIterator;
+use rusqlite::Connection;
+
+// Moves the note into the notebook, which is created the first time it's used.
+// None takes the note out of its notebook again.
+pub fn set_notebook(
+    id: usize,
+    notebook: Option<&str>,
+    conn: &Connection,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tx = conn.unchecked_transaction()?;
+    if let Some(notebook) = notebook {
+        tx.execute(
+            "INSERT OR IGNORE INTO notebooks (name) VALUES (?1)",
+            [notebook],
+        )?;
+    }
+    tx.execute(
+        "UPDATE notes SET notebook_id = (SELECT id FROM notebooks WHERE name = ?1) WHERE id = ?2",
+        rusqlite::params![notebook, id],
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+// (notebook, number of notes) for all notebooks, empty ones included
+pub fn get_notebooks(
+    conn: &Connection,
+) -> Result<Vec<(String, usize)>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT notebooks.name, count(notes.id) FROM notebooks
+         LEFT JOIN notes ON notes.notebook_id = notebooks.id AND notes.deleted_at IS NULL
+         GROUP BY notebooks.id ORDER BY notebooks.name",
+    )?;
+    let notebooks = stmt
+        .query([])?
+        .map(|row| Ok((row.get(0)?, row.get(1)?)))
+        .collect::<Vec<(String, usize)>>()?;
+    Ok(notebooks)
+}
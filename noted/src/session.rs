@@ -0,0 +1,103 @@
+use crate::crypto::Key;
+use crate::store::NoteStore;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+// How long the key of an unlocked database is kept after the passphrase was typed
+const KEEP: Duration = Duration::from_secs(15 * 60);
+
+// The key is cached in the runtime directory, which only the user can read and which is
+// emptied on logout. Without one (like on macOS) the passphrase is asked for every command.
+fn cache_path(store: &NoteStore) -> Option<PathBuf> {
+    let vault = store.vault_id().ok()??;
+    Some(
+        dirs::runtime_dir()?
+            .join("noted")
+            .join(format!("{}.key", vault)),
+    )
+}
+
+fn load(store: &NoteStore) -> Option<Key> {
+    let path = cache_path(store)?;
+    let age = fs::metadata(&path).ok()?.modified().ok()?.elapsed().ok()?;
+    if age > KEEP {
+        let _ = fs::remove_file(&path);
+        return None;
+    }
+    Key::from_hex(&Zeroizing::new(fs::read_to_string(path).ok()?))
+}
+
+fn save(store: &NoteStore, key: &Key) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(path) = cache_path(store) else {
+        return Ok(());
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    std::io::Write::write_all(&mut options.open(path)?, key.to_hex().as_bytes())?;
+    Ok(())
+}
+
+// $NOTED_PASSPHRASE for scripts, otherwise it's typed without being shown
+fn passphrase(prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+    match env::var("NOTED_PASSPHRASE") {
+        Ok(passphrase) => Ok(passphrase),
+        Err(_) => Ok(rpassword::prompt_password(prompt)?),
+    }
+}
+
+// Makes sure the store has the key, from the cache or by asking for the passphrase.
+// The first time there is no passphrase yet, so it's asked for twice.
+pub fn unlock(store: &NoteStore) -> Result<(), Box<dyn std::error::Error>> {
+    if store.has_key() {
+        return Ok(());
+    }
+    if let Some(key) = load(store) {
+        if store.use_key(key)? {
+            return Ok(());
+        }
+    }
+
+    if store.has_passphrase()? {
+        store.unlock(&passphrase("Passphrase: ")?)?;
+    } else {
+        let first = passphrase("New passphrase: ")?;
+        if first.is_empty() {
+            return Err("the passphrase can't be empty".into());
+        }
+        if env::var_os("NOTED_PASSPHRASE").is_none() && passphrase("Again: ")? != first {
+            return Err("the passphrases don't match".into());
+        }
+        store.unlock(&first)?;
+    }
+    match store.key() {
+        Some(key) => save(store, &key),
+        None => Ok(()),
+    }
+}
+
+// Takes the key from the cache if it's there, without asking for the passphrase
+pub fn resume(store: &NoteStore) -> Result<(), Box<dyn std::error::Error>> {
+    if !store.has_key() {
+        if let Some(key) = load(store) {
+            store.use_key(key)?;
+        }
+    }
+    Ok(())
+}
+
+// Locked notes need the passphrase again, here and in every other session
+pub fn forget(store: &NoteStore) -> Result<(), Box<dyn std::error::Error>> {
+    store.forget_key();
+    if let Some(path) = cache_path(store).filter(|path| path.exists()) {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
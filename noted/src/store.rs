@@ -0,0 +1,1105 @@
+use crate::config;
+use crate::crypto::{self, Cost, Key, WrongKey};
+use crate::due::{self, When};
+use crate::links;
+use crate::migrations::migrate;
+use crate::tags;
+use fallible_iterator::FallibleIterator;
+use rusqlite::{Connection, OptionalExtension};
+use std::cell::{Ref, RefCell};
+use std::path::Path;
+
+pub struct Note {
+    pub id: usize,
+    pub title: Option<String>,
+    pub body: String,
+    // Local time as YYYY-MM-DD HH:MM:SS
+    pub created_at: String,
+    pub updated_at: String,
+    pub pinned: bool,
+    pub notebook: Option<String>,
+    pub tags: Vec<String>,
+    // Local time as YYYY-MM-DD HH:MM
+    pub due: Option<String>,
+    pub todo: bool,
+    pub priority: Priority,
+    // When a todo was done, local time as YYYY-MM-DD HH:MM
+    pub done_at: Option<String>,
+    // Sealed with the passphrase, the body is empty then
+    pub locked: bool,
+}
+
+// One of the texts a note had, for /history
+pub struct Revision {
+    pub rev: usize,
+    pub title: Option<String>,
+    pub body: String,
+    pub deleted: bool,
+    // When the note got this text, local time as YYYY-MM-DD HH:MM:SS
+    pub saved_at: String,
+}
+
+// How urgent a todo is, kept as 0 to 3 so it sorts in SQL
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Priority {
+    #[default]
+    None,
+    Low,
+    Medium,
+    High,
+}
+
+impl Priority {
+    pub fn parse(word: &str) -> Option<Self> {
+        match word.to_lowercase().as_str() {
+            "none" | "0" => Some(Priority::None),
+            "low" | "l" | "1" => Some(Priority::Low),
+            "medium" | "m" | "2" => Some(Priority::Medium),
+            "high" | "h" | "3" => Some(Priority::High),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Priority::None => "none",
+            Priority::Low => "low",
+            Priority::Medium => "medium",
+            Priority::High => "high",
+        }
+    }
+
+    pub fn level(self) -> i64 {
+        self as i64
+    }
+
+    pub fn from_level(level: i64) -> Self {
+        match level {
+            i64::MIN..=0 => Priority::None,
+            1 => Priority::Low,
+            2 => Priority::Medium,
+            _ => Priority::High,
+        }
+    }
+}
+
+// A note that is due, for `noted due`
+pub struct Reminder {
+    pub id: usize,
+    pub title: Option<String>,
+    pub body: String,
+    // Local time as YYYY-MM-DD HH:MM
+    pub due: String,
+    pub overdue: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sort {
+    // Oldest first, the order notes were always listed in
+    Created,
+    // Latest change first
+    Updated,
+}
+
+impl Sort {
+    fn column(self) -> &'static str {
+        match self {
+            Sort::Created => "created_at",
+            Sort::Updated => "updated_at",
+        }
+    }
+}
+
+// What /list and `noted list` show, the dates filter on the column that is sorted by
+#[derive(Debug, PartialEq)]
+pub struct ListOptions {
+    pub sort: Sort,
+    // Inclusive, as YYYY-MM-DD in local time
+    pub since: Option<String>,
+    pub until: Option<String>,
+    // Only the deleted notes instead of the others
+    pub deleted: bool,
+    pub tag: Option<String>,
+    pub notebook: Option<String>,
+}
+
+impl ListOptions {
+    pub fn parse(words: &[&str]) -> Result<Self, String> {
+        let mut options = ListOptions {
+            sort: Sort::Created,
+            since: None,
+            until: None,
+            deleted: false,
+            tag: None,
+            notebook: None,
+        };
+
+        let mut words = words.iter();
+        while let Some(word) = words.next() {
+            match *word {
+                "--sort" => {
+                    options.sort = match words.next() {
+                        Some(&"created") => Sort::Created,
+                        Some(&"updated") => Sort::Updated,
+                        _ => return Err("--sort takes created or updated".to_string()),
+                    }
+                }
+                "--since" => options.since = Some(date(words.next(), "--since")?),
+                "--until" => options.until = Some(date(words.next(), "--until")?),
+                "--deleted" => options.deleted = true,
+                "--tag" => {
+                    options.tag = Some(
+                        words
+                            .next()
+                            .and_then(|tag| tags::normalise(tag))
+                            .ok_or("--tag takes a tag like #work")?,
+                    )
+                }
+                "--notebook" => {
+                    options.notebook =
+                        Some(words.next().ok_or("--notebook takes a name")?.to_string())
+                }
+                other => return Err(format!("{} is not a list option", other)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn date(word: Option<&&str>, option: &str) -> Result<String, String> {
+    let valid = |date: &str| {
+        date.len() == 10
+            && date.char_indices().all(|(i, c)| match i {
+                4 | 7 => c == '-',
+                _ => c.is_ascii_digit(),
+            })
+    };
+    match word {
+        Some(date) if valid(date) => Ok(date.to_string()),
+        _ => Err(format!("{} takes a date like 2024-01-31", option)),
+    }
+}
+
+// What every list of notes reads, in the order note() expects
+const NOTE_COLUMNS: &str = "notes.id, title, body, datetime(created_at, 'localtime'),
+    datetime(updated_at, 'localtime'), pinned, notebooks.name,
+    (SELECT group_concat(tags.name, ' ') FROM note_tags
+     JOIN tags ON tags.id = note_tags.tag_id WHERE note_tags.note_id = notes.id),
+    strftime('%Y-%m-%d %H:%M', due_at, 'localtime'), kind = 'todo', priority,
+    strftime('%Y-%m-%d %H:%M', done_at, 'localtime'), sealed IS NOT NULL";
+
+fn note(row: &rusqlite::Row) -> rusqlite::Result<Note> {
+    Ok(Note {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        body: row.get(2)?,
+        created_at: row.get(3)?,
+        updated_at: row.get(4)?,
+        pinned: row.get(5)?,
+        notebook: row.get(6)?,
+        tags: row
+            .get::<_, Option<String>>(7)?
+            .map(|tags| {
+                let mut tags: Vec<String> = tags.split(' ').map(str::to_string).collect();
+                tags.sort();
+                tags
+            })
+            .unwrap_or_default(),
+        due: row.get(8)?,
+        todo: row.get(9)?,
+        priority: Priority::from_level(row.get(10)?),
+        done_at: row.get(11)?,
+        locked: row.get(12)?,
+    })
+}
+
+// All reads and writes of notes go through here. The statements used on every command are
+// prepared once and cached by the connection.
+pub struct NoteStore {
+    conn: Connection,
+    // Known once the passphrase was given, needed to read and write locked notes
+    key: RefCell<Option<Key>>,
+}
+
+impl NoteStore {
+    // Opens the database, creating its directory if needed, and brings it up to date
+    pub fn open(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::new(config::open(path)?)
+    }
+
+    pub fn new(conn: Connection) -> Result<Self, Box<dyn std::error::Error>> {
+        // Freed pages are overwritten, so the text of a note that got locked doesn't linger
+        conn.pragma_update(None, "secure_delete", true)?;
+        migrate(&conn)?;
+        Ok(Self {
+            conn,
+            key: RefCell::new(None),
+        })
+    }
+
+    #[cfg(test)]
+    pub fn in_memory() -> Self {
+        Self::new(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    // For tags, notebooks and exports, which work on the connection directly
+    pub fn conn(&self) -> &Connection {
+        &self.conn
+    }
+
+    // Deleted notes are kept around but don't count as existing anymore
+    pub fn exists(&self, id: usize) -> rusqlite::Result<bool> {
+        self.conn
+            .prepare_cached(
+                "SELECT EXISTS (SELECT 1 FROM notes WHERE id = ?1 AND deleted_at IS NULL)",
+            )?
+            .query_row([id], |row| row.get(0))
+    }
+
+    // Pinned notes always come first
+    pub fn list(&self, options: &ListOptions) -> rusqlite::Result<Vec<Note>> {
+        let column = options.sort.column();
+        let direction = match options.sort {
+            Sort::Created => "ASC",
+            Sort::Updated => "DESC",
+        };
+        // One statement per sort order, so it can be cached as well
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {NOTE_COLUMNS}
+             FROM notes LEFT JOIN notebooks ON notebooks.id = notes.notebook_id
+             WHERE (deleted_at IS NOT NULL) = ?1
+               AND (?2 IS NULL OR date({column}, 'localtime') >= ?2)
+               AND (?3 IS NULL OR date({column}, 'localtime') <= ?3)
+               AND (?4 IS NULL OR EXISTS (
+                   SELECT 1 FROM note_tags JOIN tags ON tags.id = note_tags.tag_id
+                   WHERE note_tags.note_id = notes.id AND tags.name = ?4))
+               AND (?5 IS NULL OR notebooks.name = ?5)
+             ORDER BY pinned DESC, {column} {direction}, notes.id",
+            column = column,
+            direction = direction
+        ))?;
+        let rows = stmt.query(rusqlite::params![
+            options.deleted,
+            options.since,
+            options.until,
+            options.tag,
+            options.notebook
+        ])?;
+        rows.map(note).collect()
+    }
+
+    // Open todos by priority and due date, or the done ones latest first
+    pub fn todos(&self, done: bool) -> rusqlite::Result<Vec<Note>> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {NOTE_COLUMNS}
+             FROM notes LEFT JOIN notebooks ON notebooks.id = notes.notebook_id
+             WHERE kind = 'todo' AND deleted_at IS NULL AND (done_at IS NOT NULL) = ?1
+             ORDER BY done_at DESC, priority DESC, due_at IS NULL, due_at, created_at, notes.id"
+        ))?;
+        let rows = stmt.query([done])?;
+        rows.map(note).collect()
+    }
+
+    // None for notes that don't exist or are deleted. Locked notes need the key.
+    pub fn body(&self, id: usize) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let note: Option<(String, Option<Vec<u8>>)> = self
+            .conn
+            .prepare_cached("SELECT body, sealed FROM notes WHERE id = ?1 AND deleted_at IS NULL")?
+            .query_row([id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .optional()?;
+        match note {
+            Some((_, Some(sealed))) => Ok(Some(self.key_for(id)?.open_text(&sealed)?)),
+            Some((body, None)) => Ok(Some(body)),
+            None => Ok(None),
+        }
+    }
+
+    // The note and its tags are written together or not at all
+    pub fn create(&self, title: Option<&str>, body: &str) -> rusqlite::Result<usize> {
+        self.insert("note", title, body)
+    }
+
+    pub fn create_todo(&self, body: &str) -> rusqlite::Result<usize> {
+        self.insert("todo", None, body)
+    }
+
+    fn insert(&self, kind: &str, title: Option<&str>, body: &str) -> rusqlite::Result<usize> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.prepare_cached(
+            "INSERT INTO notes (kind, title, body, created_at, updated_at)
+             VALUES (?1, ?2, ?3, datetime('now'), datetime('now'))",
+        )?
+        .execute(rusqlite::params![kind, title, body])?;
+        let id = tx.last_insert_rowid();
+        tags::sync_tags(id, body, &tx)?;
+        links::sync_links(id, body, &tx)?;
+        tx.commit()?;
+        Ok(id as usize)
+    }
+
+    // Locked notes stay locked, their text is sealed again
+    pub fn update(&self, id: usize, body: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let tx = self.conn.unchecked_transaction()?;
+        if self.is_locked(id)? {
+            let sealed = self.key_for(id)?.seal(body.as_bytes());
+            tx.prepare_cached(
+                "UPDATE notes SET sealed = ?1, updated_at = datetime('now') WHERE id = ?2",
+            )?
+            .execute(rusqlite::params![sealed, id])?;
+        } else {
+            tx.prepare_cached(
+                "UPDATE notes SET body = ?1, updated_at = datetime('now') WHERE id = ?2",
+            )?
+            .execute(rusqlite::params![body, id])?;
+            tags::sync_tags(id as i64, body, &tx)?;
+            links::sync_links(id as i64, body, &tx)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    // None removes the title
+    pub fn set_title(&self, id: usize, title: Option<&str>) -> rusqlite::Result<()> {
+        self.conn
+            .prepare_cached(
+                "UPDATE notes SET title = ?1, updated_at = datetime('now') WHERE id = ?2",
+            )?
+            .execute(rusqlite::params![title, id])?;
+        Ok(())
+    }
+
+    pub fn set_pinned(&self, id: usize, pinned: bool) -> rusqlite::Result<()> {
+        self.conn
+            .prepare_cached("UPDATE notes SET pinned = ?1 WHERE id = ?2")?
+            .execute(rusqlite::params![pinned, id])?;
+        Ok(())
+    }
+
+    // Only marks the note as deleted, `list --deleted` still shows it
+    pub fn delete(&self, id: usize) -> rusqlite::Result<()> {
+        self.conn
+            .prepare_cached("UPDATE notes SET deleted_at = datetime('now') WHERE id = ?1")?
+            .execute([id])?;
+        Ok(())
+    }
+
+    // Oldest first, deleted notes included. Empty for notes that never existed.
+    // Locked notes need the key.
+    pub fn revisions(&self, id: usize) -> Result<Vec<Revision>, Box<dyn std::error::Error>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT rev, title, body, sealed, deleted_at IS NOT NULL,
+                    datetime(saved_at, 'localtime')
+             FROM note_revisions WHERE note_id = ?1 ORDER BY rev",
+        )?;
+        let mut rows = stmt.query([id])?;
+        let mut revisions = vec![];
+        while let Some(row) = rows.next()? {
+            let sealed: Option<Vec<u8>> = row.get(3)?;
+            let body = match sealed {
+                Some(sealed) => self.key_for(id)?.open_text(&sealed)?,
+                None => row.get(2)?,
+            };
+            revisions.push(Revision {
+                rev: row.get(0)?,
+                title: row.get(1)?,
+                body,
+                deleted: row.get(4)?,
+                saved_at: row.get(5)?,
+            });
+        }
+        Ok(revisions)
+    }
+
+    // Brings back the title and text of a revision, undeleting the note if needed.
+    // This is a change like any other, so it becomes the newest revision. False if there
+    // is no such revision.
+    pub fn restore(&self, id: usize, rev: usize) -> rusqlite::Result<bool> {
+        let tx = self.conn.unchecked_transaction()?;
+        // Revisions are locked when their note is, so they can be copied as they are
+        let revision: Option<(Option<String>, String, Option<Vec<u8>>)> = tx
+            .prepare_cached(
+                "SELECT title, body, sealed FROM note_revisions WHERE note_id = ?1 AND rev = ?2",
+            )?
+            .query_row([id, rev], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .optional()?;
+        let Some((title, body, sealed)) = revision else {
+            return Ok(false);
+        };
+        tx.prepare_cached(
+            "UPDATE notes SET title = ?1, body = ?2, sealed = ?3, deleted_at = NULL,
+                 updated_at = datetime('now')
+             WHERE id = ?4",
+        )?
+        .execute(rusqlite::params![title, body, sealed, id])?;
+        tags::sync_tags(id as i64, &body, &tx)?;
+        links::sync_links(id as i64, &body, &tx)?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    // Whether the database has a passphrase yet, the first lock sets one
+    pub fn has_passphrase(&self) -> rusqlite::Result<bool> {
+        self.conn
+            .prepare_cached("SELECT EXISTS (SELECT 1 FROM vault)")?
+            .query_row([], |row| row.get(0))
+    }
+
+    // Identifies the passphrase of this database, for caching its key. None without one.
+    pub fn vault_id(&self) -> rusqlite::Result<Option<String>> {
+        self.conn
+            .prepare_cached("SELECT salt FROM vault")?
+            .query_row([], |row| row.get::<_, Vec<u8>>(0))
+            .optional()
+            .map(|salt| salt.map(|salt| crypto::to_hex(&salt)))
+    }
+
+    // Derives the key from the passphrase and keeps it. Sets the passphrase if there is none.
+    pub fn unlock(&self, passphrase: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let vault: Option<(Vec<u8>, Cost, Vec<u8>)> = self
+            .conn
+            .prepare_cached("SELECT salt, memory, iterations, lanes, check_value FROM vault")?
+            .query_row([], |row| {
+                let cost = Cost(row.get(1)?, row.get(2)?, row.get(3)?);
+                Ok((row.get(0)?, cost, row.get(4)?))
+            })
+            .optional()?;
+        let key = match vault {
+            Some((salt, cost, check_value)) => {
+                let key = Key::derive(passphrase, &salt, cost)?;
+                if !key.fits(&check_value) {
+                    return Err(WrongKey.into());
+                }
+                key
+            }
+            None => {
+                let salt = crypto::salt();
+                let key = Key::derive(passphrase, &salt, crypto::COST)?;
+                let Cost(memory, iterations, lanes) = crypto::COST;
+                self.conn.execute(
+                    "INSERT INTO vault (id, salt, memory, iterations, lanes, check_value)
+                     VALUES (1, ?1, ?2, ?3, ?4, ?5)",
+                    rusqlite::params![&salt[..], memory, iterations, lanes, key.check_value()],
+                )?;
+                key
+            }
+        };
+        self.key.replace(Some(key));
+        Ok(())
+    }
+
+    // For a key cached from an earlier unlock, false if it doesn't belong to this database
+    pub fn use_key(&self, key: Key) -> rusqlite::Result<bool> {
+        let check_value: Option<Vec<u8>> = self
+            .conn
+            .prepare_cached("SELECT check_value FROM vault")?
+            .query_row([], |row| row.get(0))
+            .optional()?;
+        let fits = check_value.is_some_and(|check_value| key.fits(&check_value));
+        if fits {
+            self.key.replace(Some(key));
+        }
+        Ok(fits)
+    }
+
+    pub fn has_key(&self) -> bool {
+        self.key.borrow().is_some()
+    }
+
+    pub fn key(&self) -> Option<Ref<'_, Key>> {
+        Ref::filter_map(self.key.borrow(), Option::as_ref).ok()
+    }
+
+    pub fn forget_key(&self) {
+        self.key.replace(None);
+    }
+
+    fn key_for(&self, id: usize) -> Result<Ref<'_, Key>, String> {
+        self.key()
+            .ok_or_else(|| format!("note {} is locked, unlock it with the passphrase", id))
+    }
+
+    // Deleted notes included
+    pub fn is_locked(&self, id: usize) -> rusqlite::Result<bool> {
+        self.conn
+            .prepare_cached(
+                "SELECT EXISTS (SELECT 1 FROM notes WHERE id = ?1 AND sealed IS NOT NULL)",
+            )?
+            .query_row([id], |row| row.get(0))
+    }
+
+    // Seals the text of the note, all of its revisions and the other versions of its conflicts
+    // with the key, leaving the bodies empty. Locked notes can't be searched and have no tags, only the title stays readable.
+    pub fn lock(&self, id: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let key = self.key_for(id)?;
+        if self.is_locked(id)? {
+            return Ok(());
+        }
+        let tx = self.conn.unchecked_transaction()?;
+        let body: String = tx.query_row("SELECT body FROM notes WHERE id = ?1", [id], |row| {
+            row.get(0)
+        })?;
+        tx.execute(
+            "UPDATE notes SET body = '', sealed = ?1 WHERE id = ?2",
+            rusqlite::params![key.seal(body.as_bytes()), id],
+        )?;
+        self.seal_revisions(id, Some(&key), &tx)?;
+        self.seal_conflicts(id, Some(&key), &tx)?;
+        tags::sync_tags(id as i64, "", &tx)?;
+        links::sync_links(id as i64, "", &tx)?;
+        // Merging the full-text index drops what it still knew about the text
+        tx.execute("INSERT INTO notes_fts (notes_fts) VALUES ('optimize')", [])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    // Turns a locked note back into a plain one, revisions and conflicts included
+    pub fn unlock_note(&self, id: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(body) = self.locked_body(id)? else {
+            return Ok(());
+        };
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "UPDATE notes SET body = ?1, sealed = NULL WHERE id = ?2",
+            rusqlite::params![body, id],
+        )?;
+        self.seal_revisions(id, None, &tx)?;
+        self.seal_conflicts(id, None, &tx)?;
+        tags::sync_tags(id as i64, &body, &tx)?;
+        links::sync_links(id as i64, &body, &tx)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    // Deleted notes included, None if the note isn't locked
+    fn locked_body(&self, id: usize) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let sealed: Option<Vec<u8>> = self
+            .conn
+            .query_row("SELECT sealed FROM notes WHERE id = ?1", [id], |row| {
+                row.get(0)
+            })
+            .optional()?
+            .flatten();
+        match sealed {
+            Some(sealed) => Ok(Some(self.key_for(id)?.open_text(&sealed)?)),
+            None => Ok(None),
+        }
+    }
+
+    // Seals every revision with the key, or opens them all without one. Locking or unlocking
+    // doesn't change the text, so the revision the trigger recorded for it goes away.
+    fn seal_revisions(
+        &self,
+        id: usize,
+        key: Option<&Key>,
+        tx: &Connection,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        tx.execute(
+            "DELETE FROM note_revisions WHERE note_id = ?1
+                 AND rev = (SELECT max(rev) FROM note_revisions WHERE note_id = ?1)",
+            [id],
+        )?;
+        let mut stmt =
+            tx.prepare("SELECT rev, body, sealed FROM note_revisions WHERE note_id = ?1")?;
+        let revisions: Vec<(usize, String, Option<Vec<u8>>)> = stmt
+            .query([id])?
+            .map(|row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .collect()?;
+        for (rev, mut body, mut sealed) in revisions {
+            if !self.reseal(id, key, &mut body, &mut sealed)? {
+                continue;
+            }
+            tx.execute(
+                "UPDATE note_revisions SET body = ?1, sealed = ?2 WHERE note_id = ?3 AND rev = ?4",
+                rusqlite::params![body, sealed, id, rev],
+            )?;
+        }
+        Ok(())
+    }
+
+    // Same for the versions of the note that lost a conflict, they'd show the text otherwise
+    fn seal_conflicts(
+        &self,
+        id: usize,
+        key: Option<&Key>,
+        tx: &Connection,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut stmt = tx.prepare("SELECT id, body, sealed FROM conflicts WHERE note_id = ?1")?;
+        let conflicts: Vec<(usize, String, Option<Vec<u8>>)> = stmt
+            .query([id])?
+            .map(|row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .collect()?;
+        for (conflict, mut body, mut sealed) in conflicts {
+            if !self.reseal(id, key, &mut body, &mut sealed)? {
+                continue;
+            }
+            tx.execute(
+                "UPDATE conflicts SET body = ?1, sealed = ?2 WHERE id = ?3",
+                rusqlite::params![body, sealed, conflict],
+            )?;
+        }
+        Ok(())
+    }
+
+    // Seals the body with the key or opens it without one, false if it already was
+    fn reseal(
+        &self,
+        id: usize,
+        key: Option<&Key>,
+        body: &mut String,
+        sealed: &mut Option<Vec<u8>>,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        match (key, &*sealed) {
+            (Some(key), None) => {
+                *sealed = Some(key.seal(body.as_bytes()));
+                body.clear();
+            }
+            (None, Some(text)) => {
+                *body = self.key_for(id)?.open_text(text)?;
+                *sealed = None;
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    // Turns a note into an open todo, todos stay as they are
+    pub fn make_todo(&self, id: usize) -> rusqlite::Result<()> {
+        self.conn
+            .prepare_cached("UPDATE notes SET kind = 'todo' WHERE id = ?1")?
+            .execute([id])?;
+        Ok(())
+    }
+
+    // Notes that weren't todos become done ones. Doing it again keeps the first time.
+    pub fn set_done(&self, id: usize, done: bool) -> rusqlite::Result<()> {
+        self.conn
+            .prepare_cached(
+                "UPDATE notes SET kind = 'todo',
+                     done_at = CASE WHEN ?1 THEN coalesce(done_at, datetime('now')) END
+                 WHERE id = ?2",
+            )?
+            .execute(rusqlite::params![done, id])?;
+        Ok(())
+    }
+
+    pub fn set_priority(&self, id: usize, priority: Priority) -> rusqlite::Result<()> {
+        self.conn
+            .prepare_cached("UPDATE notes SET priority = ?1 WHERE id = ?2")?
+            .execute(rusqlite::params![priority.level(), id])?;
+        Ok(())
+    }
+
+    // None takes the due date away. A new date can remind again, even if the old one did.
+    // Returns when it's due in local time.
+    pub fn set_due(
+        &self,
+        id: usize,
+        when: Option<&When>,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let due = when
+            .map(|when| due::resolve(when, &self.conn))
+            .transpose()?;
+        self.conn
+            .prepare_cached("UPDATE notes SET due_at = ?1, reminded_at = NULL WHERE id = ?2")?
+            .execute(rusqlite::params![due, id])?;
+        Ok(self
+            .conn
+            .prepare_cached("SELECT strftime('%Y-%m-%d %H:%M', ?1, 'localtime')")?
+            .query_row([due], |row| row.get(0))?)
+    }
+
+    // Every note with a due date, soonest first
+    pub fn reminders(&self) -> rusqlite::Result<Vec<Reminder>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT id, title, body, strftime('%Y-%m-%d %H:%M', due_at, 'localtime'),
+                    due_at <= datetime('now')
+             FROM notes WHERE due_at IS NOT NULL AND deleted_at IS NULL
+             ORDER BY due_at, id",
+        )?;
+        let rows = stmt.query([])?;
+        rows.map(|row| {
+            Ok(Reminder {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                body: row.get(2)?,
+                due: row.get(3)?,
+                overdue: row.get(4)?,
+            })
+        })
+        .collect()
+    }
+
+    // The notes that came due since they last reminded, each one only reminds once
+    pub fn fire_reminders(&self) -> rusqlite::Result<Vec<Reminder>> {
+        let tx = self.conn.unchecked_transaction()?;
+        let mut fired = self.reminders()?;
+        fired.retain(|reminder| reminder.overdue);
+        let mut reminded = vec![];
+        for reminder in fired {
+            let changed = tx
+                .prepare_cached(
+                    "UPDATE notes SET reminded_at = datetime('now')
+                     WHERE id = ?1 AND reminded_at IS NULL",
+                )?
+                .execute([reminder.id])?;
+            if changed == 1 {
+                reminded.push(reminder);
+            }
+        }
+        tx.commit()?;
+        Ok(reminded)
+    }
+
+    // (id, snippet) pairs, best match first, with every match wrapped in start and end
+    pub fn search(
+        &self,
+        query: &str,
+        (start, end): (&str, &str),
+    ) -> rusqlite::Result<Vec<(usize, String)>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT notes.id, snippet(notes_fts, -1, ?2, ?3, '...', 12)
+             FROM notes_fts JOIN notes ON notes.id = notes_fts.rowid
+             WHERE notes_fts MATCH ?1 AND notes.deleted_at IS NULL
+             ORDER BY rank",
+        )?;
+        let rows = stmt.query([query, start, end])?;
+        rows.map(|row| Ok((row.get(0)?, row.get(1)?))).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::due::Day;
+
+    fn options(words: &[&str]) -> ListOptions {
+        ListOptions::parse(words).unwrap()
+    }
+
+    fn ids(notes: &[Note]) -> Vec<usize> {
+        notes.iter().map(|note| note.id).collect()
+    }
+
+    #[test]
+    fn ids_and_bodies_stay_together() {
+        let store = NoteStore::in_memory();
+        for body in ["one", "two", "three"] {
+            store.create(None, body).unwrap();
+        }
+        store.delete(2).unwrap();
+        let notes = store.list(&options(&[])).unwrap();
+        let rows: Vec<_> = notes.iter().map(|note| (note.id, &*note.body)).collect();
+        assert_eq!(rows, vec![(1, "one"), (3, "three")]);
+    }
+
+    #[test]
+    fn deleted_and_missing_notes_dont_exist() {
+        let store = NoteStore::in_memory();
+        let id = store.create(None, "milk").unwrap();
+        assert!(store.exists(id).unwrap());
+        assert!(!store.exists(id + 1).unwrap());
+
+        store.delete(id).unwrap();
+        assert!(!store.exists(id).unwrap());
+        assert_eq!(store.body(id).unwrap(), None);
+        assert!(store.list(&options(&[])).unwrap().is_empty());
+        assert_eq!(
+            ids(&store.list(&options(&["--deleted"])).unwrap()),
+            vec![id]
+        );
+        assert!(store.search("milk", ("", "")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn pinned_notes_come_first_in_both_orders() {
+        let store = NoteStore::in_memory();
+        for body in ["a", "b", "c"] {
+            store.create(None, body).unwrap();
+        }
+        store.set_pinned(2, true).unwrap();
+        store
+            .conn()
+            .execute(
+                "UPDATE notes SET updated_at = datetime('now', '+1 day') WHERE id = 1",
+                [],
+            )
+            .unwrap();
+        assert_eq!(ids(&store.list(&options(&[])).unwrap()), vec![2, 1, 3]);
+        assert_eq!(
+            ids(&store.list(&options(&["--sort", "updated"])).unwrap()),
+            vec![2, 1, 3]
+        );
+        store.set_pinned(2, false).unwrap();
+        assert_eq!(ids(&store.list(&options(&[])).unwrap()), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn edits_keep_the_tags_in_sync() {
+        let store = NoteStore::in_memory();
+        let id = store.create(Some("Shopping"), "milk #home").unwrap();
+        store.update(id, "milk #work #Errands").unwrap();
+        store.set_title(id, None).unwrap();
+
+        let notes = store.list(&options(&["--tag", "#work"])).unwrap();
+        assert_eq!(notes[0].tags, vec!["errands", "work"]);
+        assert_eq!(notes[0].title, None);
+        assert!(store.list(&options(&["--tag", "home"])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn filters_by_date_and_notebook() {
+        let store = NoteStore::in_memory();
+        store.create(None, "old").unwrap();
+        store.create(None, "new").unwrap();
+        store
+            .conn()
+            .execute(
+                "UPDATE notes SET created_at = '2020-01-01 12:00:00' WHERE id = 1",
+                [],
+            )
+            .unwrap();
+        crate::notebooks::set_notebook(2, Some("Work"), store.conn()).unwrap();
+
+        let until = options(&["--until", "2020-12-31"]);
+        assert_eq!(ids(&store.list(&until).unwrap()), vec![1]);
+        let since = options(&["--since", "2021-01-01"]);
+        assert_eq!(ids(&store.list(&since).unwrap()), vec![2]);
+        let notes = store.list(&options(&["--notebook", "Work"])).unwrap();
+        assert_eq!(notes[0].notebook.as_deref(), Some("Work"));
+        assert_eq!(ids(&notes), vec![2]);
+    }
+
+    #[test]
+    fn reminders_fire_once_until_the_date_changes() {
+        let store = NoteStore::in_memory();
+        for body in ["past", "future", "never"] {
+            store.create(None, body).unwrap();
+        }
+        let due = store
+            .set_due(
+                1,
+                Some(&When::At(Some(Day::Date("2020-01-01".to_string())), (9, 0))),
+            )
+            .unwrap();
+        assert_eq!(due.as_deref(), Some("2020-01-01 09:00"));
+        store.set_due(2, Some(&When::In(60))).unwrap();
+
+        let reminders = store.reminders().unwrap();
+        let due: Vec<_> = reminders.iter().map(|r| (r.id, r.overdue)).collect();
+        assert_eq!(due, vec![(1, true), (2, false)]);
+        let notes = store.list(&options(&[])).unwrap();
+        assert_eq!(notes[0].due.as_deref(), Some("2020-01-01 09:00"));
+        assert_eq!(notes[2].due, None);
+
+        let fired = store.fire_reminders().unwrap();
+        assert_eq!(fired.iter().map(|r| r.id).collect::<Vec<_>>(), vec![1]);
+        assert!(store.fire_reminders().unwrap().is_empty());
+
+        store
+            .set_due(
+                1,
+                Some(&When::At(Some(Day::Date("2021-01-01".to_string())), (9, 0))),
+            )
+            .unwrap();
+        assert_eq!(store.fire_reminders().unwrap().len(), 1);
+        assert_eq!(store.set_due(1, None).unwrap(), None);
+        assert_eq!(store.reminders().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn open_todos_come_by_priority_and_done_ones_keep_when() {
+        let store = NoteStore::in_memory();
+        store.create(None, "just a note").unwrap();
+        for body in ["later", "urgent", "soon"] {
+            store.create_todo(body).unwrap();
+        }
+        store.set_priority(3, Priority::High).unwrap();
+        store.set_priority(4, Priority::Low).unwrap();
+        assert_eq!(ids(&store.todos(false).unwrap()), vec![3, 4, 2]);
+
+        store.set_done(3, true).unwrap();
+        store
+            .conn()
+            .execute(
+                "UPDATE notes SET done_at = datetime('now', '-1 hour') WHERE id = 3",
+                [],
+            )
+            .unwrap();
+        store.set_done(1, true).unwrap();
+        assert_eq!(ids(&store.todos(false).unwrap()), vec![4, 2]);
+        let done = store.todos(true).unwrap();
+        assert_eq!(ids(&done), vec![1, 3]);
+        assert!(done.iter().all(|note| note.todo && note.done_at.is_some()));
+
+        store.set_done(3, false).unwrap();
+        store.make_todo(1).unwrap();
+        assert_eq!(ids(&store.todos(true).unwrap()), vec![1]);
+        let notes = store.list(&options(&[])).unwrap();
+        assert_eq!(notes[2].priority, Priority::High);
+        assert_eq!(notes[2].done_at, None);
+    }
+
+    #[test]
+    fn every_change_is_a_revision() {
+        let store = NoteStore::in_memory();
+        let id = store.create(None, "milk #home").unwrap();
+        store.update(id, "milk #home").unwrap();
+        store.set_pinned(id, true).unwrap();
+        store.update(id, "oat milk #shop").unwrap();
+        store.set_title(id, Some("Shopping")).unwrap();
+        store.delete(id).unwrap();
+
+        let revisions = store.revisions(id).unwrap();
+        let seen: Vec<_> = revisions
+            .iter()
+            .map(|r| (r.rev, r.title.as_deref(), &*r.body, r.deleted))
+            .collect();
+        assert_eq!(
+            seen,
+            vec![
+                (1, None, "milk #home", false),
+                (2, None, "oat milk #shop", false),
+                (3, Some("Shopping"), "oat milk #shop", false),
+                (4, Some("Shopping"), "oat milk #shop", true),
+            ]
+        );
+        assert!(store.revisions(id + 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn restoring_rolls_back_and_undeletes() {
+        let store = NoteStore::in_memory();
+        let id = store.create(Some("Shopping"), "milk #home").unwrap();
+        store.update(id, "oat milk #shop").unwrap();
+        store.delete(id).unwrap();
+
+        assert!(!store.restore(id, 9).unwrap());
+        assert!(!store.exists(id).unwrap());
+        assert!(store.restore(id, 1).unwrap());
+        assert_eq!(store.body(id).unwrap().as_deref(), Some("milk #home"));
+        let notes = store.list(&options(&["--tag", "home"])).unwrap();
+        assert_eq!(ids(&notes), vec![id]);
+        assert_eq!(notes[0].title.as_deref(), Some("Shopping"));
+        assert_eq!(store.revisions(id).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn locked_notes_need_the_key() {
+        let store = NoteStore::in_memory();
+        let id = store.create(Some("Bank"), "pin 1234 #secret").unwrap();
+        store.update(id, "pin 4321 #secret").unwrap();
+
+        assert!(store.lock(id).is_err());
+        store.unlock("correct horse").unwrap();
+        store.lock(id).unwrap();
+        let stored: (String, String) = store
+            .conn
+            .query_row(
+                "SELECT notes.body, group_concat(note_revisions.body, '')
+                 FROM notes JOIN note_revisions ON note_revisions.note_id = notes.id",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(stored, (String::new(), String::new()));
+        assert!(store.search("4321", ("", "")).unwrap().is_empty());
+        assert!(store
+            .list(&options(&["--tag", "secret"]))
+            .unwrap()
+            .is_empty());
+
+        store.update(id, "pin 9999 #secret").unwrap();
+        store.forget_key();
+        assert!(store.body(id).is_err());
+        assert!(store.revisions(id).is_err());
+        assert!(store.list(&options(&[])).unwrap()[0].locked);
+        let wrong = store.unlock("battery staple").unwrap_err();
+        assert_eq!(wrong.to_string(), "wrong passphrase");
+
+        store.unlock("correct horse").unwrap();
+        assert_eq!(store.body(id).unwrap().as_deref(), Some("pin 9999 #secret"));
+        store.unlock_note(id).unwrap();
+        store.forget_key();
+        assert_eq!(store.body(id).unwrap().as_deref(), Some("pin 9999 #secret"));
+        let bodies: Vec<String> = store
+            .revisions(id)
+            .unwrap()
+            .into_iter()
+            .map(|revision| revision.body)
+            .collect();
+        assert_eq!(
+            bodies,
+            vec!["pin 1234 #secret", "pin 4321 #secret", "pin 9999 #secret"]
+        );
+        assert_eq!(store.list(&options(&["--tag", "secret"])).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn search_highlights_matches() {
+        let store = NoteStore::in_memory();
+        store.create(None, "call the plumber").unwrap();
+        store.create(None, "buy milk").unwrap();
+        assert_eq!(
+            store.search("plumber", ("[", "]")).unwrap(),
+            vec![(1, "call the [plumber]".to_string())]
+        );
+        assert!(store.search("\"unclosed", ("", "")).is_err());
+    }
+
+    #[test]
+    fn triggers_keep_the_index_in_sync() {
+        let store = NoteStore::in_memory();
+        let found = |query: &str| -> Vec<usize> {
+            let found = store.search(query, ("", "")).unwrap();
+            found.into_iter().map(|(id, _)| id).collect()
+        };
+        // Compares the index with the notes table and fails on any difference
+        let check = || {
+            store
+                .conn()
+                .execute(
+                    "INSERT INTO notes_fts (notes_fts) VALUES ('integrity-check')",
+                    [],
+                )
+                .unwrap()
+        };
+
+        let id = store.create(None, "call the plumber").unwrap();
+        check();
+        assert_eq!(found("plumber"), vec![id]);
+
+        store.update(id, "call the electrician").unwrap();
+        store.set_title(id, Some("Repairs")).unwrap();
+        check();
+        assert!(found("plumber").is_empty());
+        assert_eq!(found("electrician"), vec![id]);
+        assert_eq!(found("repairs"), vec![id]);
+
+        // Nothing in noted deletes for real, so the revisions have to go first
+        for sql in [
+            "DELETE FROM note_revisions WHERE note_id = ?1",
+            "DELETE FROM notes WHERE id = ?1",
+        ] {
+            store.conn().execute(sql, [id]).unwrap();
+        }
+        check();
+        assert!(found("electrician").is_empty());
+    }
+
+    #[test]
+    fn better_matches_come_first() {
+        let store = NoteStore::in_memory();
+        store
+            .create(None, "bread, eggs, butter, cheese and some milk")
+            .unwrap();
+        store.create(None, "milk, milk and more milk").unwrap();
+        store.create(None, "nothing to see here").unwrap();
+        let ids: Vec<usize> = store
+            .search("milk", ("", ""))
+            .unwrap()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(ids, vec![2, 1]);
+    }
+}
@@ -0,0 +1,749 @@
+use crate::crypto::Key;
+use crate::links::{replace_links, sync_links};
+use crate::tags::sync_tags;
+use fallible_iterator::FallibleIterator;
+use rusqlite::{Connection, OptionalExtension};
+use serde_json::{json, Value};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+// Databases sync through a shared directory, like a mounted drive. Every database appends
+// the notes it changed to its own DEVICE.jsonl there and reads what the others appended
+// since it last looked, so no file is ever written by two machines.
+
+// How many changes each device made to a note, as JSON in notes.version
+type Version = BTreeMap<String, u64>;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Synced {
+    pub sent: usize,
+    pub received: usize,
+    pub conflicts: usize,
+    // Locked notes stay where they are, their key only works in their own database
+    pub locked: usize,
+}
+
+// One line of a change log, the whole note as it was after the change. Dates are UTC as
+// stored in the database.
+struct Change {
+    uuid: String,
+    device: String,
+    lamport: i64,
+    version: Version,
+    title: Option<String>,
+    body: String,
+    pinned: bool,
+    created_at: String,
+    updated_at: String,
+    deleted_at: Option<String>,
+    notebook: Option<String>,
+    due_at: Option<String>,
+    kind: String,
+    priority: i64,
+    done_at: Option<String>,
+}
+
+// A note that was changed on two devices without one knowing about the other. The note has
+// the newer version, the other one is kept until it's resolved.
+pub struct Conflict {
+    pub id: usize,
+    pub note_id: usize,
+    pub current: Side,
+    pub other: Side,
+    // The text of the note itself can't be compared then
+    pub locked: bool,
+    // Local time as YYYY-MM-DD HH:MM:SS
+    pub found_at: String,
+}
+
+pub struct Side {
+    pub title: Option<String>,
+    pub body: String,
+    pub deleted: bool,
+}
+
+enum Applied {
+    Received,
+    Unchanged,
+    Conflict,
+}
+
+// Sends what changed here first, so both sides of a conflict see it, then applies what the
+// other devices changed. The directory has to exist, it's most likely a drive that's not
+// mounted otherwise. The key is only needed when a locked note was changed elsewhere too.
+pub fn sync(
+    dir: &Path,
+    conn: &Connection,
+    key: Option<&Key>,
+) -> Result<Synced, Box<dyn std::error::Error>> {
+    if !dir.is_dir() {
+        return Err(format!("{} is not a directory, is it mounted?", dir.display()).into());
+    }
+    let device: String = conn.query_row("SELECT device FROM sync_state", [], |row| row.get(0))?;
+    let mut synced = Synced::default();
+    send(dir, &device, conn, &mut synced)?;
+
+    let mut logs: Vec<(String, std::path::PathBuf)> = fs::read_dir(dir)?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let peer = path
+                .file_name()?
+                .to_str()?
+                .strip_suffix(".jsonl")?
+                .to_string();
+            (peer != device).then_some((peer, path))
+        })
+        .collect();
+    logs.sort();
+    for (peer, path) in logs {
+        receive(&peer, &path, conn, key, &mut synced)?;
+    }
+    Ok(synced)
+}
+
+fn send(
+    dir: &Path,
+    device: &str,
+    conn: &Connection,
+    synced: &mut Synced,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tx = conn.unchecked_transaction()?;
+    let mut stmt = tx.prepare(
+        "SELECT uuid, changed_by, lamport, version, title, body, pinned, created_at,
+                updated_at, deleted_at, notebooks.name, due_at, kind, priority, done_at,
+                sealed IS NOT NULL
+         FROM notes LEFT JOIN notebooks ON notebooks.id = notes.notebook_id
+         WHERE dirty ORDER BY lamport",
+    )?;
+    let mut rows = stmt.query([])?;
+    let mut lines = String::new();
+    while let Some(row) = rows.next()? {
+        if row.get(15)? {
+            synced.locked += 1;
+            continue;
+        }
+        let change = Change {
+            uuid: row.get(0)?,
+            device: row.get(1)?,
+            lamport: row.get(2)?,
+            version: parse_version(&row.get::<_, String>(3)?)?,
+            title: row.get(4)?,
+            body: links_to_uuids(&row.get::<_, String>(5)?, &tx)?,
+            pinned: row.get(6)?,
+            created_at: row.get(7)?,
+            updated_at: row.get(8)?,
+            deleted_at: row.get(9)?,
+            notebook: row.get(10)?,
+            due_at: row.get(11)?,
+            kind: row.get(12)?,
+            priority: row.get(13)?,
+            done_at: row.get(14)?,
+        };
+        lines.push_str(&change_json(&change).to_string());
+        lines.push('\n');
+        synced.sent += 1;
+    }
+    drop(rows);
+    drop(stmt);
+
+    if !lines.is_empty() {
+        let mut log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(format!("{}.jsonl", device)))?;
+        // One write, so the others never see half of it unless the drive fills up
+        log.write_all(lines.as_bytes())?;
+        log.sync_all()?;
+    }
+    tx.execute(
+        "UPDATE notes SET dirty = 0 WHERE dirty AND sealed IS NULL",
+        [],
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+// Reads the lines of the log that weren't seen yet, all or nothing. A last line without a
+// newline is still being written and waits for the next sync.
+fn receive(
+    peer: &str,
+    path: &Path,
+    conn: &Connection,
+    key: Option<&Key>,
+    synced: &mut Synced,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tx = conn.unchecked_transaction()?;
+    let seen: usize = tx
+        .query_row(
+            "SELECT seen FROM sync_peers WHERE device = ?1",
+            [peer],
+            |row| row.get(0),
+        )
+        .optional()?
+        .unwrap_or(0);
+    let text = fs::read_to_string(path)?;
+    let lines: Vec<&str> = text
+        .split_inclusive('\n')
+        .filter(|line| line.ends_with('\n'))
+        .collect();
+
+    for (number, line) in lines.iter().enumerate().skip(seen) {
+        let mut change = serde_json::from_str(line)
+            .map_err(|err| err.to_string())
+            .and_then(change_from_json)
+            .map_err(|err| format!("{} line {}: {}", path.display(), number + 1, err))?;
+        change.body = links_to_ids(&change.body, &tx)?;
+        match apply(&change, &tx, key)? {
+            Applied::Received => synced.received += 1,
+            Applied::Conflict => synced.conflicts += 1,
+            Applied::Unchanged => {}
+        }
+    }
+    tx.execute(
+        "INSERT INTO sync_peers (device, seen) VALUES (?1, ?2)
+         ON CONFLICT (device) DO UPDATE SET seen = excluded.seen",
+        rusqlite::params![peer, lines.len()],
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn apply(
+    change: &Change,
+    conn: &Connection,
+    key: Option<&Key>,
+) -> Result<Applied, Box<dyn std::error::Error>> {
+    // Lamport: the clock here is always ahead of everything it has seen
+    conn.execute(
+        "UPDATE sync_state SET clock = max(clock, ?1)",
+        [change.lamport],
+    )?;
+    let local: Option<(usize, String, i64, Option<String>, bool)> = conn
+        .query_row(
+            "SELECT id, version, lamport, changed_by, sealed IS NOT NULL
+             FROM notes WHERE uuid = ?1",
+            [&change.uuid],
+            |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            },
+        )
+        .optional()?;
+    let Some((id, version, lamport, changed_by, locked)) = local else {
+        insert(change, conn)?;
+        return Ok(Applied::Received);
+    };
+    let version = parse_version(&version)?;
+
+    match compare(&version, &change.version) {
+        // Seen already, or it's older than what's here
+        Some(Ordering::Equal | Ordering::Greater) => Ok(Applied::Unchanged),
+        Some(Ordering::Less) if !locked => {
+            write(id, change, &change.version, conn)?;
+            // A resolution from the other side settles the conflicts it knew about
+            let conflicts: Vec<(usize, String)> = conn
+                .prepare("SELECT id, version FROM conflicts WHERE note_id = ?1")?
+                .query([id])?
+                .map(|row| Ok((row.get(0)?, row.get(1)?)))
+                .collect()?;
+            for (conflict, version) in conflicts {
+                if compare(&parse_version(&version)?, &change.version).is_some() {
+                    conn.execute("DELETE FROM conflicts WHERE id = ?1", [conflict])?;
+                }
+            }
+            Ok(Applied::Received)
+        }
+        // Changed on both sides. Both pick the same winner, the change with the later clock,
+        // and keep the other one as a conflict. Locked notes always win until they're unlocked.
+        _ => {
+            let merged = merge(&version, &change.version);
+            let theirs = (change.lamport, Some(change.device.as_str()));
+            if !locked && theirs > (lamport, changed_by.as_deref()) {
+                conn.execute(
+                    "INSERT INTO conflicts (note_id, device, lamport, version, title, body,
+                                            deleted_at, found_at)
+                     SELECT id, coalesce(changed_by, ''), lamport, version, title, body,
+                            deleted_at, datetime('now')
+                     FROM notes WHERE id = ?1",
+                    [id],
+                )?;
+                write(id, change, &merged, conn)?;
+            } else {
+                // The other version of a locked note is sealed like its revisions
+                let (body, sealed) = match (locked, key) {
+                    (false, _) => (change.body.as_str(), None),
+                    (true, Some(key)) => ("", Some(key.seal(change.body.as_bytes()))),
+                    (true, None) => {
+                        return Err(format!(
+                            "note {} is locked and was changed on another device too, \
+                             give the passphrase with unlock and sync again",
+                            id
+                        )
+                        .into())
+                    }
+                };
+                conn.execute(
+                    "INSERT INTO conflicts (note_id, device, lamport, version, title, body,
+                                            sealed, deleted_at, found_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, datetime('now'))",
+                    rusqlite::params![
+                        id,
+                        change.device,
+                        change.lamport,
+                        version_json(&change.version),
+                        change.title,
+                        body,
+                        sealed,
+                        change.deleted_at
+                    ],
+                )?;
+                conn.execute(
+                    "UPDATE notes SET version = ?1 WHERE id = ?2",
+                    rusqlite::params![version_json(&merged), id],
+                )?;
+            }
+            Ok(Applied::Conflict)
+        }
+    }
+}
+
+// Setting version keeps the triggers from counting this as a change made here
+fn write(id: usize, change: &Change, version: &Version, conn: &Connection) -> rusqlite::Result<()> {
+    add_notebook(change, conn)?;
+    conn.execute(
+        "UPDATE notes SET title = ?1, body = ?2, pinned = ?3, created_at = ?4, updated_at = ?5,
+             deleted_at = ?6, notebook_id = (SELECT id FROM notebooks WHERE name = ?7),
+             reminded_at = CASE WHEN due_at IS ?8 THEN reminded_at END, due_at = ?8,
+             kind = ?9, priority = ?10, done_at = ?11, version = ?12, lamport = ?13,
+             changed_by = ?14
+         WHERE id = ?15",
+        rusqlite::params![
+            change.title,
+            change.body,
+            change.pinned,
+            change.created_at,
+            change.updated_at,
+            change.deleted_at,
+            change.notebook,
+            change.due_at,
+            change.kind,
+            change.priority,
+            change.done_at,
+            version_json(version),
+            change.lamport,
+            change.device,
+            id
+        ],
+    )?;
+    sync_tags(id as i64, &change.body, conn)?;
+    sync_links(id as i64, &change.body, conn)
+}
+
+// Notes from another device get the next id here, ids aren't the same everywhere. That's why
+// [[id]] links travel as [[uuid]].
+fn insert(change: &Change, conn: &Connection) -> rusqlite::Result<()> {
+    add_notebook(change, conn)?;
+    conn.execute(
+        "INSERT INTO notes (uuid, title, body, pinned, created_at, updated_at, deleted_at,
+                            notebook_id, due_at, kind, priority, done_at, version, lamport,
+                            changed_by)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, (SELECT id FROM notebooks WHERE name = ?8), ?9,
+                 ?10, ?11, ?12, ?13, ?14, ?15)",
+        rusqlite::params![
+            change.uuid,
+            change.title,
+            change.body,
+            change.pinned,
+            change.created_at,
+            change.updated_at,
+            change.deleted_at,
+            change.notebook,
+            change.due_at,
+            change.kind,
+            change.priority,
+            change.done_at,
+            version_json(&change.version),
+            change.lamport,
+            change.device
+        ],
+    )?;
+    let id = conn.last_insert_rowid();
+    sync_tags(id, &change.body, conn)?;
+    sync_links(id, &change.body, conn)
+}
+
+// [[3]] is another note on every device, [[its uuid]] is the same everywhere
+fn links_to_uuids(body: &str, conn: &Connection) -> rusqlite::Result<String> {
+    replace_links(body, |target| {
+        if target.is_empty() || !target.chars().all(|c| c.is_ascii_digit()) {
+            return Ok(None);
+        }
+        conn.query_row(
+            "SELECT uuid FROM notes WHERE id = CAST(?1 AS integer)",
+            [target],
+            |row| row.get(0),
+        )
+        .optional()
+    })
+}
+
+// Back to the ids here for the notes that are here, the others keep their UUID
+fn links_to_ids(body: &str, conn: &Connection) -> rusqlite::Result<String> {
+    replace_links(body, |target| {
+        conn.query_row("SELECT id FROM notes WHERE uuid = ?1", [target], |row| {
+            row.get::<_, i64>(0).map(|id| id.to_string())
+        })
+        .optional()
+    })
+}
+
+fn add_notebook(change: &Change, conn: &Connection) -> rusqlite::Result<()> {
+    if let Some(notebook) = &change.notebook {
+        conn.execute(
+            "INSERT OR IGNORE INTO notebooks (name) VALUES (?1)",
+            [notebook],
+        )?;
+    }
+    Ok(())
+}
+
+// Oldest first
+pub fn get_conflicts(conn: &Connection) -> rusqlite::Result<Vec<Conflict>> {
+    let mut stmt = conn.prepare(
+        "SELECT conflicts.id, note_id, notes.title, notes.body, notes.deleted_at IS NOT NULL,
+                conflicts.title, conflicts.body, conflicts.deleted_at IS NOT NULL,
+                notes.sealed IS NOT NULL, datetime(found_at, 'localtime')
+         FROM conflicts JOIN notes ON notes.id = conflicts.note_id ORDER BY conflicts.id",
+    )?;
+    let conflicts = stmt
+        .query([])?
+        .map(|row| {
+            Ok(Conflict {
+                id: row.get(0)?,
+                note_id: row.get(1)?,
+                current: Side {
+                    title: row.get(2)?,
+                    body: row.get(3)?,
+                    deleted: row.get(4)?,
+                },
+                other: Side {
+                    title: row.get(5)?,
+                    body: row.get(6)?,
+                    deleted: row.get(7)?,
+                },
+                locked: row.get(8)?,
+                found_at: row.get(9)?,
+            })
+        })
+        .collect()?;
+    Ok(conflicts)
+}
+
+// Keeps the note as it is or takes the other version. Either way it counts as a change made
+// here, so the next sync settles the conflict on the other devices as well. False if there
+// is no such conflict.
+pub fn resolve(
+    conflict: usize,
+    take_other: bool,
+    conn: &Connection,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let tx = conn.unchecked_transaction()?;
+    let found: Option<(usize, bool)> = tx
+        .query_row(
+            "SELECT note_id, notes.sealed IS NOT NULL
+             FROM conflicts JOIN notes ON notes.id = conflicts.note_id
+             WHERE conflicts.id = ?1",
+            [conflict],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    let Some((id, locked)) = found else {
+        return Ok(false);
+    };
+    if locked {
+        return Err(format!("note {} is locked, unlock it to resolve the conflict", id).into());
+    }
+
+    if take_other {
+        let body: String = tx.query_row(
+            "UPDATE notes SET (title, body, deleted_at) =
+                 (SELECT title, body, deleted_at FROM conflicts WHERE id = ?1),
+                 updated_at = datetime('now')
+             WHERE id = ?2
+             RETURNING body",
+            [conflict, id],
+            |row| row.get(0),
+        )?;
+        sync_tags(id as i64, &body, &tx)?;
+        sync_links(id as i64, &body, &tx)?;
+    }
+    // Like the trigger does for every other change, which it didn't if nothing changed
+    tx.execute("UPDATE sync_state SET clock = clock + 1", [])?;
+    tx.execute(
+        "UPDATE notes SET
+             version = json_set(version, '$.' || (SELECT device FROM sync_state),
+                 coalesce(json_extract(version, '$.' || (SELECT device FROM sync_state)), 0) + 1),
+             lamport = (SELECT clock FROM sync_state),
+             changed_by = (SELECT device FROM sync_state), dirty = 1
+         WHERE id = ?1",
+        [id],
+    )?;
+    tx.execute("DELETE FROM conflicts WHERE id = ?1", [conflict])?;
+    tx.commit()?;
+    Ok(true)
+}
+
+fn parse_version(text: &str) -> Result<Version, String> {
+    serde_json::from_str(text).map_err(|err| format!("{} is not a version: {}", text, err))
+}
+
+fn version_json(version: &Version) -> String {
+    json!(version).to_string()
+}
+
+// None if each one has changes the other doesn't know about
+fn compare(a: &Version, b: &Version) -> Option<Ordering> {
+    let mut ordering = Ordering::Equal;
+    for device in a.keys().chain(b.keys()) {
+        let (x, y) = (a.get(device).unwrap_or(&0), b.get(device).unwrap_or(&0));
+        match (ordering, x.cmp(y)) {
+            (_, Ordering::Equal) => {}
+            (Ordering::Equal, other) => ordering = other,
+            (current, other) if current != other => return None,
+            _ => {}
+        }
+    }
+    Some(ordering)
+}
+
+fn merge(a: &Version, b: &Version) -> Version {
+    let mut merged = a.clone();
+    for (device, count) in b {
+        let entry = merged.entry(device.clone()).or_insert(0);
+        *entry = (*entry).max(*count);
+    }
+    merged
+}
+
+fn change_json(change: &Change) -> Value {
+    json!({
+        "uuid": change.uuid,
+        "device": change.device,
+        "lamport": change.lamport,
+        "version": change.version,
+        "title": change.title,
+        "body": change.body,
+        "pinned": change.pinned,
+        "created_at": change.created_at,
+        "updated_at": change.updated_at,
+        "deleted_at": change.deleted_at,
+        "notebook": change.notebook,
+        "due_at": change.due_at,
+        "kind": change.kind,
+        "priority": change.priority,
+        "done_at": change.done_at,
+    })
+}
+
+fn change_from_json(value: Value) -> Result<Change, String> {
+    let text = |key: &str| -> Result<Option<String>, String> {
+        match &value[key] {
+            Value::Null => Ok(None),
+            Value::String(text) => Ok(Some(text.clone())),
+            _ => Err(format!("{} should be text", key)),
+        }
+    };
+    let required = |key: &str| -> Result<String, String> {
+        text(key)?.ok_or_else(|| format!("a change needs {}", key))
+    };
+    let number = |key: &str| -> Result<i64, String> {
+        value[key]
+            .as_i64()
+            .ok_or_else(|| format!("{} should be a number", key))
+    };
+    let version = serde_json::from_value(value["version"].clone())
+        .map_err(|err| format!("version: {}", err))?;
+    let kind = required("kind")?;
+    if kind != "note" && kind != "todo" {
+        return Err(format!("{} is not a kind of note", kind));
+    }
+    Ok(Change {
+        uuid: required("uuid")?,
+        device: required("device")?,
+        lamport: number("lamport")?,
+        version,
+        title: text("title")?,
+        body: required("body")?,
+        pinned: value["pinned"].as_bool().unwrap_or(false),
+        created_at: required("created_at")?,
+        updated_at: required("updated_at")?,
+        deleted_at: text("deleted_at")?,
+        notebook: text("notebook")?,
+        due_at: text("due_at")?,
+        kind,
+        priority: number("priority")?,
+        done_at: text("done_at")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{ListOptions, NoteStore};
+    use std::path::PathBuf;
+
+    fn shared(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("noted-sync-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn bodies(store: &NoteStore) -> Vec<String> {
+        let mut stmt = store
+            .conn()
+            .prepare("SELECT body FROM notes WHERE deleted_at IS NULL ORDER BY uuid")
+            .unwrap();
+        stmt.query([])
+            .unwrap()
+            .map(|row| row.get(0))
+            .collect()
+            .unwrap()
+    }
+
+    fn version(pairs: &[(&str, u64)]) -> Version {
+        pairs
+            .iter()
+            .map(|(device, count)| (device.to_string(), *count))
+            .collect()
+    }
+
+    #[test]
+    fn versions_know_what_came_first() {
+        let a1 = version(&[("a", 1)]);
+        let a2b1 = version(&[("a", 2), ("b", 1)]);
+        let b1 = version(&[("b", 1)]);
+        assert_eq!(compare(&a1, &a1), Some(Ordering::Equal));
+        assert_eq!(compare(&a1, &a2b1), Some(Ordering::Less));
+        assert_eq!(compare(&a2b1, &b1), Some(Ordering::Greater));
+        assert_eq!(compare(&a1, &b1), None);
+        assert_eq!(merge(&a1, &b1), version(&[("a", 1), ("b", 1)]));
+    }
+
+    #[test]
+    fn changes_go_both_ways() {
+        let dir = shared("both-ways");
+        let (laptop, desktop) = (NoteStore::in_memory(), NoteStore::in_memory());
+        let milk = laptop.create(Some("Shopping"), "milk #home").unwrap();
+        laptop.create(None, "call the plumber").unwrap();
+
+        let synced = sync(&dir, laptop.conn(), None).unwrap();
+        assert_eq!(synced.sent, 2);
+        let synced = sync(&dir, desktop.conn(), None).unwrap();
+        assert_eq!((synced.sent, synced.received), (0, 2));
+        assert_eq!(bodies(&desktop), bodies(&laptop));
+
+        let on_desktop = desktop.list(&ListOptions::parse(&[]).unwrap()).unwrap()[0].id;
+        desktop.update(on_desktop, "oat milk #home").unwrap();
+        desktop.delete(on_desktop + 1).unwrap();
+        sync(&dir, desktop.conn(), None).unwrap();
+        let synced = sync(&dir, laptop.conn(), None).unwrap();
+        assert_eq!((synced.received, synced.conflicts), (2, 0));
+        assert_eq!(
+            laptop.body(milk).unwrap().as_deref(),
+            Some("oat milk #home")
+        );
+        assert!(!laptop.exists(milk + 1).unwrap());
+
+        // Nothing new, nothing sent, and reading a log twice changes nothing
+        assert_eq!(sync(&dir, laptop.conn(), None).unwrap(), Synced::default());
+        desktop
+            .conn()
+            .execute("DELETE FROM sync_peers", [])
+            .unwrap();
+        assert_eq!(sync(&dir, desktop.conn(), None).unwrap().received, 0);
+    }
+
+    #[test]
+    fn edits_on_both_sides_are_conflicts_until_resolved() {
+        let dir = shared("conflict");
+        let (laptop, desktop) = (NoteStore::in_memory(), NoteStore::in_memory());
+        let id = laptop.create(None, "milk").unwrap();
+        sync(&dir, laptop.conn(), None).unwrap();
+        sync(&dir, desktop.conn(), None).unwrap();
+
+        laptop.update(id, "oat milk").unwrap();
+        desktop.update(id, "milk\nbread").unwrap();
+        sync(&dir, laptop.conn(), None).unwrap();
+        assert_eq!(sync(&dir, desktop.conn(), None).unwrap().conflicts, 1);
+        assert_eq!(sync(&dir, laptop.conn(), None).unwrap().conflicts, 1);
+
+        // Both sides keep the same version and the other one as the conflict
+        assert_eq!(bodies(&laptop), bodies(&desktop));
+        let conflicts = get_conflicts(laptop.conn()).unwrap();
+        assert_eq!(conflicts.len(), 1);
+        let other = conflicts[0].other.body.clone();
+        assert_ne!(conflicts[0].current.body, other);
+        assert_eq!(get_conflicts(desktop.conn()).unwrap()[0].other.body, other);
+
+        assert!(resolve(conflicts[0].id, true, laptop.conn()).unwrap());
+        assert!(!resolve(conflicts[0].id, true, laptop.conn()).unwrap());
+        assert_eq!(laptop.body(id).unwrap(), Some(other.clone()));
+        sync(&dir, laptop.conn(), None).unwrap();
+        let synced = sync(&dir, desktop.conn(), None).unwrap();
+        assert_eq!((synced.received, synced.conflicts), (1, 0));
+        assert_eq!(bodies(&desktop), vec![other]);
+        assert!(get_conflicts(desktop.conn()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn conflicts_of_locked_notes_are_sealed_too() {
+        let dir = shared("locked");
+        let (laptop, desktop) = (NoteStore::in_memory(), NoteStore::in_memory());
+        let id = laptop.create(None, "pin 1234").unwrap();
+        sync(&dir, laptop.conn(), None).unwrap();
+        sync(&dir, desktop.conn(), None).unwrap();
+        laptop.update(id, "pin 5678").unwrap();
+        desktop.update(id, "pin 9012").unwrap();
+        sync(&dir, laptop.conn(), None).unwrap();
+        sync(&dir, desktop.conn(), None).unwrap();
+        sync(&dir, laptop.conn(), None).unwrap();
+
+        let plain = |store: &NoteStore| -> Vec<String> {
+            let mut stmt = store
+                .conn()
+                .prepare("SELECT body FROM conflicts WHERE sealed IS NULL ORDER BY id")
+                .unwrap();
+            stmt.query([])
+                .unwrap()
+                .map(|row| row.get(0))
+                .collect()
+                .unwrap()
+        };
+        assert_eq!(plain(&laptop).len(), 1);
+        laptop.unlock("correct horse").unwrap();
+        laptop.lock(id).unwrap();
+        assert!(plain(&laptop).is_empty());
+        laptop.unlock_note(id).unwrap();
+        assert_eq!(plain(&laptop).len(), 1);
+
+        // Another change from elsewhere is kept sealed, which needs the key
+        laptop.lock(id).unwrap();
+        desktop.update(id, "pin 3456").unwrap();
+        sync(&dir, desktop.conn(), None).unwrap();
+        assert!(sync(&dir, laptop.conn(), None).is_err());
+        let synced = sync(&dir, laptop.conn(), laptop.key().as_deref()).unwrap();
+        assert_eq!(synced.conflicts, 1);
+        assert!(plain(&laptop).is_empty());
+        let bodies = get_conflicts(laptop.conn()).unwrap();
+        assert!(bodies.iter().all(|conflict| conflict.other.body.is_empty()));
+    }
+}
@@ -0,0 +1,206 @@
+use fallible_iterator::FallibleIterator;
+use rusqlite::Connection;
+
+// A #tag starts a word and runs until anything but letters, digits, -, _ or /,
+// so C# or page#anchor aren't tags. Tags are case insensitive and stored lowercase.
+fn find_tags(body: &str) -> Vec<(usize, usize)> {
+    let mut found = vec![];
+    let mut previous = None;
+    for (start, c) in body.char_indices() {
+        if c == '#' && previous.is_none_or(char::is_whitespace) {
+            let name = &body[start + 1..];
+            let len = name.find(|c: char| !is_tag_char(c)).unwrap_or(name.len());
+            if len > 0 {
+                found.push((start, start + 1 + len));
+            }
+        }
+        previous = Some(c);
+    }
+    found
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '_' || c == '/'
+}
+
+// Names of all tags in the body, sorted and without duplicates
+pub fn extract_tags(body: &str) -> Vec<String> {
+    let mut tags: Vec<String> = find_tags(body)
+        .into_iter()
+        .map(|(start, end)| body[start + 1..end].to_lowercase())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+// A tag as typed by the user, with or without the #
+pub fn normalise(tag: &str) -> Option<String> {
+    let tag = tag.strip_prefix('#').unwrap_or(tag);
+    (!tag.is_empty() && tag.chars().all(is_tag_char)).then(|| tag.to_lowercase())
+}
+
+fn replace_tag(body: &str, old: &str, new: &str) -> String {
+    let mut replaced = String::new();
+    let mut last = 0;
+    for (start, end) in find_tags(body) {
+        if body[start + 1..end].to_lowercase() == old {
+            replaced.push_str(&body[last..start]);
+            replaced.push('#');
+            replaced.push_str(new);
+            last = end;
+        }
+    }
+    replaced.push_str(&body[last..]);
+    replaced
+}
+
+// Makes note_tags match the tags in the body, tags no note uses anymore are dropped
+pub fn sync_tags(id: i64, body: &str, conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM note_tags WHERE note_id = ?1", [id])?;
+    for tag in extract_tags(body) {
+        conn.execute("INSERT OR IGNORE INTO tags (name) VALUES (?1)", [&tag])?;
+        conn.execute(
+            "INSERT INTO note_tags (note_id, tag_id) SELECT ?1, id FROM tags WHERE name = ?2",
+            rusqlite::params![id, tag],
+        )?;
+    }
+    conn.execute(
+        "DELETE FROM tags WHERE id NOT IN (SELECT tag_id FROM note_tags)",
+        [],
+    )?;
+    Ok(())
+}
+
+// Migration for the notes written before tags were extracted
+pub fn sync_all_tags(conn: &Connection) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare("SELECT id, body FROM notes")?;
+    let notes = stmt
+        .query([])?
+        .map(|row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))
+        .collect::<Vec<(i64, String)>>()?;
+    for (id, body) in notes {
+        sync_tags(id, &body, conn)?;
+    }
+    Ok(())
+}
+
+// (tag, number of notes) for every tag on a note that isn't deleted
+pub fn get_tags(conn: &Connection) -> Result<Vec<(String, usize)>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT tags.name, count(*) FROM tags
+         JOIN note_tags ON note_tags.tag_id = tags.id
+         JOIN notes ON notes.id = note_tags.note_id
+         WHERE notes.deleted_at IS NULL
+         GROUP BY tags.name ORDER BY tags.name",
+    )?;
+    let tags = stmt
+        .query([])?
+        .map(|row| Ok((row.get(0)?, row.get(1)?)))
+        .collect::<Vec<(String, usize)>>()?;
+    Ok(tags)
+}
+
+// Rewrites #old to #new in every note, which merges them if #new is already used.
+// Returns the number of notes that changed and whether it was a merge.
+pub fn rename_tag(
+    old: &str,
+    new: &str,
+    conn: &Connection,
+) -> Result<(usize, bool), Box<dyn std::error::Error>> {
+    if old == new {
+        return Ok((0, false));
+    }
+    let tx = conn.unchecked_transaction()?;
+    let merged: bool = tx.query_row(
+        "SELECT EXISTS (SELECT 1 FROM tags WHERE name = ?1)",
+        [new],
+        |row| row.get(0),
+    )?;
+
+    let notes = {
+        let mut stmt = tx.prepare(
+            "SELECT notes.id, notes.body FROM notes
+             JOIN note_tags ON note_tags.note_id = notes.id
+             JOIN tags ON tags.id = note_tags.tag_id
+             WHERE tags.name = ?1",
+        )?;
+        let rows = stmt.query([old])?;
+        rows.map(|row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))
+            .collect::<Vec<(i64, String)>>()?
+    };
+
+    for (id, body) in &notes {
+        let body = replace_tag(body, old, new);
+        tx.execute(
+            "UPDATE notes SET body = ?1, updated_at = datetime('now') WHERE id = ?2",
+            rusqlite::params![body, id],
+        )?;
+        sync_tags(*id, &body, &tx)?;
+    }
+    tx.commit()?;
+
+    Ok((notes.len(), merged))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::NoteStore;
+
+    fn tags(conn: &Connection) -> Vec<(String, usize)> {
+        get_tags(conn).unwrap()
+    }
+
+    #[test]
+    fn tags_start_words_and_ignore_case() {
+        assert_eq!(
+            extract_tags("#Home call #urgent, C# page#anchor #home #a/b-c_d #"),
+            vec!["a/b-c_d", "home", "urgent"]
+        );
+        assert_eq!(normalise("#Home"), Some("home".to_string()));
+        assert_eq!(normalise("work"), Some("work".to_string()));
+        assert_eq!(normalise("#"), None);
+        assert_eq!(normalise("two words"), None);
+    }
+
+    #[test]
+    fn unused_tags_go_away() {
+        let store = NoteStore::in_memory();
+        let id = store.create(None, "milk #home #shopping").unwrap();
+        store.create(None, "plumber #home").unwrap();
+        let conn = store.conn();
+        assert_eq!(
+            tags(conn),
+            vec![("home".to_string(), 2), ("shopping".to_string(), 1)]
+        );
+
+        sync_tags(id as i64, "milk #Home", conn).unwrap();
+        assert_eq!(tags(conn), vec![("home".to_string(), 2)]);
+        let stored: usize = conn
+            .query_row("SELECT count(*) FROM tags", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(stored, 1);
+    }
+
+    #[test]
+    fn renaming_rewrites_the_text_and_merges() {
+        let store = NoteStore::in_memory();
+        let milk = store.create(None, "milk #Home, #homework").unwrap();
+        let plumber = store.create(None, "plumber #house").unwrap();
+        let conn = store.conn();
+
+        assert_eq!(rename_tag("home", "flat", conn).unwrap(), (1, false));
+        assert_eq!(store.body(milk).unwrap().unwrap(), "milk #flat, #homework");
+
+        assert_eq!(rename_tag("house", "flat", conn).unwrap(), (1, true));
+        assert_eq!(store.body(plumber).unwrap().unwrap(), "plumber #flat");
+        assert_eq!(
+            tags(conn),
+            vec![("flat".to_string(), 2), ("homework".to_string(), 1)]
+        );
+
+        assert_eq!(rename_tag("flat", "flat", conn).unwrap(), (0, false));
+        assert_eq!(rename_tag("missing", "other", conn).unwrap(), (0, false));
+    }
+}
@@ -0,0 +1,169 @@
+// Multi-line text being edited in the TUI. The cursor counts chars, not bytes.
+pub struct TextArea {
+    lines: Vec<String>,
+    row: usize,
+    col: usize,
+}
+
+impl TextArea {
+    // The cursor starts at the end, where notes usually get added to
+    pub fn new(text: &str) -> Self {
+        let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+        if lines.is_empty() {
+            lines.push(String::new());
+        }
+        let row = lines.len() - 1;
+        let col = lines[row].chars().count();
+        Self { lines, row, col }
+    }
+
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    // (row, column) of the cursor
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.row, self.col)
+    }
+
+    fn byte(&self, col: usize) -> usize {
+        let line = &self.lines[self.row];
+        line.char_indices()
+            .nth(col)
+            .map_or(line.len(), |(byte, _)| byte)
+    }
+
+    fn len(&self) -> usize {
+        self.lines[self.row].chars().count()
+    }
+
+    pub fn insert(&mut self, c: char) {
+        let byte = self.byte(self.col);
+        self.lines[self.row].insert(byte, c);
+        self.col += 1;
+    }
+
+    pub fn newline(&mut self) {
+        let byte = self.byte(self.col);
+        let rest = self.lines[self.row].split_off(byte);
+        self.row += 1;
+        self.col = 0;
+        self.lines.insert(self.row, rest);
+    }
+
+    // Joins the line with the one above at its start
+    pub fn backspace(&mut self) {
+        if self.col > 0 {
+            self.col -= 1;
+            let byte = self.byte(self.col);
+            self.lines[self.row].remove(byte);
+        } else if self.row > 0 {
+            let line = self.lines.remove(self.row);
+            self.row -= 1;
+            self.col = self.len();
+            self.lines[self.row].push_str(&line);
+        }
+    }
+
+    // Joins the line below at the end of the line
+    pub fn delete(&mut self) {
+        if self.col < self.len() {
+            let byte = self.byte(self.col);
+            self.lines[self.row].remove(byte);
+        } else if self.row + 1 < self.lines.len() {
+            let line = self.lines.remove(self.row + 1);
+            self.lines[self.row].push_str(&line);
+        }
+    }
+
+    pub fn left(&mut self) {
+        if self.col > 0 {
+            self.col -= 1;
+        } else if self.row > 0 {
+            self.row -= 1;
+            self.col = self.len();
+        }
+    }
+
+    pub fn right(&mut self) {
+        if self.col < self.len() {
+            self.col += 1;
+        } else if self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = 0;
+        }
+    }
+
+    pub fn up(&mut self) {
+        if self.row > 0 {
+            self.row -= 1;
+            self.col = self.col.min(self.len());
+        }
+    }
+
+    pub fn down(&mut self) {
+        if self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = self.col.min(self.len());
+        }
+    }
+
+    pub fn home(&mut self) {
+        self.col = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.col = self.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typing_goes_where_the_cursor_is() {
+        let mut text = TextArea::new("milk\nbread");
+        assert_eq!(text.cursor(), (1, 5));
+        text.up();
+        assert_eq!(text.cursor(), (0, 4));
+        text.home();
+        for c in "oat ".chars() {
+            text.insert(c);
+        }
+        text.end();
+        text.newline();
+        text.insert('ü');
+        assert_eq!(text.text(), "oat milk\nü\nbread");
+    }
+
+    #[test]
+    fn deleting_across_lines_joins_them() {
+        let mut text = TextArea::new("ab\ncd");
+        text.up();
+        text.end();
+        text.delete();
+        assert_eq!(text.text(), "abcd");
+
+        let mut text = TextArea::new("ab\ncd");
+        text.home();
+        text.backspace();
+        assert_eq!(text.text(), "abcd");
+        assert_eq!(text.cursor(), (0, 2));
+    }
+
+    #[test]
+    fn multibyte_chars_are_one_step() {
+        let mut text = TextArea::new("äöü");
+        text.left();
+        text.backspace();
+        assert_eq!(text.text(), "äü");
+        text.right();
+        text.right();
+        assert_eq!(text.cursor(), (0, 2));
+    }
+}
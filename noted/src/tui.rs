@@ -0,0 +1,578 @@
+use crate::checkbox;
+use crate::store::{ListOptions, Note, NoteStore};
+use crate::terminal::TerminalGuard;
+use crate::textarea::TextArea;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use std::io::{self, IsTerminal};
+use tui::{
+    backend::{Backend, CrosstermBackend},
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
+    Frame, Terminal,
+};
+use tui_input::backend::crossterm::EventHandler;
+use tui_input::Input;
+
+// A note open in the editor, None for a new one
+struct Editing {
+    id: Option<usize>,
+    original: String,
+    text: TextArea,
+}
+
+// Keeps track of what the keys do right now
+enum Mode {
+    Browse,
+    Search,
+    Edit(Editing),
+    ConfirmDelete(usize),
+    // Leaving the editor with unsaved changes, no goes back to editing
+    ConfirmDiscard(Editing),
+}
+
+pub struct App {
+    notes: Vec<Note>,
+    // Indices into notes in the order they're listed, only the matches while searching
+    shown: Vec<usize>,
+    selected: ListState,
+    search: Input,
+    mode: Mode,
+    // Lines the preview is scrolled down by
+    scroll: u16,
+    // Shown instead of the key hints until the next key
+    status: Option<String>,
+    quit: bool,
+}
+
+impl App {
+    pub fn new(store: &NoteStore) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut app = Self {
+            notes: vec![],
+            shown: vec![],
+            selected: ListState::default(),
+            search: Input::default(),
+            mode: Mode::Browse,
+            scroll: 0,
+            status: None,
+            quit: false,
+        };
+        app.reload(store)?;
+        Ok(app)
+    }
+
+    fn selected_note(&self) -> Option<&Note> {
+        let index = self.shown.get(self.selected.selected()?)?;
+        self.notes.get(*index)
+    }
+
+    fn select(&mut self, index: usize) {
+        let index = index.min(self.shown.len().saturating_sub(1));
+        if self.selected.selected() != Some(index) {
+            self.scroll = 0;
+        }
+        self.selected
+            .select((!self.shown.is_empty()).then_some(index));
+    }
+
+    fn select_id(&mut self, id: usize) {
+        let index = self
+            .shown
+            .iter()
+            .position(|index| self.notes[*index].id == id);
+        if let Some(index) = index {
+            self.select(index);
+        }
+    }
+
+    // Reads the notes again after a change and keeps the same one selected if it's still there
+    fn reload(&mut self, store: &NoteStore) -> Result<(), Box<dyn std::error::Error>> {
+        let selected = self.selected_note().map(|note| note.id);
+        self.notes = store.list(&ListOptions::parse(&[])?)?;
+        self.filter(store);
+        match selected {
+            Some(id) => self.select_id(id),
+            None => self.select(0),
+        }
+        Ok(())
+    }
+
+    // The last word is searched as a prefix, so results show up while typing it
+    fn filter(&mut self, store: &NoteStore) {
+        let query = self.search.value().trim();
+        if query.is_empty() {
+            self.shown = (0..self.notes.len()).collect();
+        } else {
+            let prefix = query
+                .ends_with(char::is_alphanumeric)
+                .then(|| format!("{}*", query));
+            let results = prefix
+                .and_then(|prefix| store.search(&prefix, ("", "")).ok())
+                .map(Ok)
+                .unwrap_or_else(|| store.search(query, ("", "")));
+            match results {
+                Ok(results) => {
+                    self.shown = results
+                        .iter()
+                        .filter_map(|(id, _)| self.notes.iter().position(|note| note.id == *id))
+                        .collect();
+                }
+                // Most likely only half typed, the last results stay until it's valid again
+                Err(_) => self.status = Some("not a valid search yet".to_string()),
+            }
+        }
+        let selected = self.selected.selected().unwrap_or(0);
+        self.select(selected);
+    }
+
+    fn edit(&mut self) {
+        if let Some(note) = self.selected_note() {
+            // Typing the passphrase doesn't fit in here, the prompt or `noted edit` can do that
+            if note.locked {
+                self.status = Some(format!(
+                    "note {} is locked, `noted edit {} TEXT` asks for the passphrase",
+                    note.id, note.id
+                ));
+                return;
+            }
+            self.mode = Mode::Edit(Editing {
+                id: Some(note.id),
+                original: note.body.clone(),
+                text: TextArea::new(&note.body),
+            });
+        }
+    }
+
+    pub fn handle(
+        &mut self,
+        key: KeyEvent,
+        store: &NoteStore,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.status = None;
+        match std::mem::replace(&mut self.mode, Mode::Browse) {
+            Mode::Browse => self.browse(key, store)?,
+            Mode::Search => match key.code {
+                KeyCode::Esc => {
+                    self.search.reset();
+                    self.filter(store);
+                }
+                KeyCode::Enter | KeyCode::Down | KeyCode::Up => {}
+                _ => {
+                    self.search.handle_event(&Event::Key(key));
+                    self.filter(store);
+                    self.mode = Mode::Search;
+                }
+            },
+            Mode::Edit(editing) => self.type_into(editing, key, store)?,
+            Mode::ConfirmDelete(id) => {
+                if key.code == KeyCode::Char('y') {
+                    store.delete(id)?;
+                    self.reload(store)?;
+                    self.status = Some(format!(
+                        "deleted note {}, `noted list --deleted` still has it",
+                        id
+                    ));
+                }
+            }
+            Mode::ConfirmDiscard(editing) => {
+                if key.code != KeyCode::Char('y') {
+                    self.mode = Mode::Edit(editing);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn browse(
+        &mut self,
+        key: KeyEvent,
+        store: &NoteStore,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let selected = self.selected.selected().unwrap_or(0);
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+            KeyCode::Down | KeyCode::Char('j') => self.select(selected + 1),
+            KeyCode::Up | KeyCode::Char('k') => self.select(selected.saturating_sub(1)),
+            KeyCode::Home | KeyCode::Char('g') => self.select(0),
+            KeyCode::End | KeyCode::Char('G') => self.select(self.shown.len()),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_add(5),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(5),
+            KeyCode::Char('/') => self.mode = Mode::Search,
+            KeyCode::Enter | KeyCode::Char('e') => self.edit(),
+            KeyCode::Char('n') => {
+                self.mode = Mode::Edit(Editing {
+                    id: None,
+                    original: String::new(),
+                    text: TextArea::new(""),
+                })
+            }
+            KeyCode::Char('d') | KeyCode::Delete => {
+                if let Some(note) = self.selected_note() {
+                    self.mode = Mode::ConfirmDelete(note.id);
+                }
+            }
+            KeyCode::Char('p') => {
+                if let Some(note) = self.selected_note() {
+                    store.set_pinned(note.id, !note.pinned)?;
+                    self.reload(store)?;
+                }
+            }
+            // Checking a note off makes it a todo, like /done
+            KeyCode::Char('x') => {
+                if let Some(note) = self.selected_note() {
+                    store.set_done(note.id, note.done_at.is_none())?;
+                    self.reload(store)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn type_into(
+        &mut self,
+        mut editing: Editing,
+        key: KeyEvent,
+        store: &NoteStore,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let text = &mut editing.text;
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('s') if control => return self.save(editing, store),
+            KeyCode::Esc if text.text().trim_end() != editing.original.trim_end() => {
+                self.mode = Mode::ConfirmDiscard(editing);
+                return Ok(());
+            }
+            KeyCode::Esc => return Ok(()),
+            KeyCode::Char(c) if !control => text.insert(c),
+            KeyCode::Tab => text.insert('\t'),
+            KeyCode::Enter => text.newline(),
+            KeyCode::Backspace => text.backspace(),
+            KeyCode::Delete => text.delete(),
+            KeyCode::Left => text.left(),
+            KeyCode::Right => text.right(),
+            KeyCode::Up => text.up(),
+            KeyCode::Down => text.down(),
+            KeyCode::Home => text.home(),
+            KeyCode::End => text.end(),
+            _ => {}
+        }
+        self.mode = Mode::Edit(editing);
+        Ok(())
+    }
+
+    fn save(
+        &mut self,
+        editing: Editing,
+        store: &NoteStore,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let body = editing.text.text().trim_end().to_string();
+        if body.trim().is_empty() {
+            self.status = Some("empty notes aren't saved".to_string());
+            self.mode = Mode::Edit(editing);
+            return Ok(());
+        }
+
+        let id = match editing.id {
+            Some(id) if body == editing.original.trim_end() => {
+                self.status = Some(format!("nothing changed in note {}", id));
+                return Ok(());
+            }
+            Some(id) => {
+                store.update(id, &body)?;
+                id
+            }
+            None => store.create(None, &body)?,
+        };
+        self.status = Some(format!("saved note {}", id));
+        self.reload(store)?;
+        self.select_id(id);
+        Ok(())
+    }
+}
+
+// Opens the full-screen interface, `q` leaves it again
+pub fn run(store: &NoteStore) -> Result<(), Box<dyn std::error::Error>> {
+    if !io::stdout().is_terminal() {
+        return Err("noted tui needs a terminal".into());
+    }
+    let mut app = App::new(store)?;
+
+    let _guard = TerminalGuard::enter()?;
+    let backend = CrosstermBackend::new(io::stdout());
+    let mut terminal = Terminal::new(backend)?;
+    terminal.clear()?;
+
+    while !app.quit {
+        terminal.draw(|f| draw(f, &mut app))?;
+        // Windows also reports releasing a key
+        if let Event::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Release {
+                app.handle(key, store)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ])
+        .split(f.size());
+    let panes = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(35), Constraint::Percentage(65)])
+        .split(rows[1]);
+
+    let searching = matches!(app.mode, Mode::Search);
+    let search = Paragraph::new(app.search.value()).block(
+        Block::default()
+            .title("Search")
+            .borders(Borders::ALL)
+            .border_style(highlighted(searching)),
+    );
+    f.render_widget(search, rows[0]);
+    if searching {
+        f.set_cursor(
+            rows[0].x + 1 + app.search.visual_cursor() as u16,
+            rows[0].y + 1,
+        );
+    }
+
+    let items: Vec<ListItem> = app
+        .shown
+        .iter()
+        .map(|index| {
+            let note = &app.notes[*index];
+            let label = note.title.as_deref().unwrap_or_else(|| match note.locked {
+                true => "(locked)",
+                false => note.body.lines().next().unwrap_or_default(),
+            });
+            let pin = if note.pinned { "* " } else { "" };
+            ListItem::new(format!("{}{}{}", pin, checkbox(note), label))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .title(format!("Notes ({})", app.shown.len()))
+                .borders(Borders::ALL),
+        )
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    f.render_stateful_widget(list, panes[0], &mut app.selected);
+
+    match &app.mode {
+        Mode::Edit(editing) | Mode::ConfirmDiscard(editing) => draw_editor(f, editing, panes[1]),
+        _ => draw_preview(f, app, panes[1]),
+    }
+
+    let hints = match &app.mode {
+        Mode::Browse => {
+            "↑↓ move  / search  enter edit  n new  d delete  p pin  x done  q quit".to_string()
+        }
+        Mode::Search => "type to search  enter done  esc clear".to_string(),
+        Mode::Edit(_) => "ctrl-s save  esc cancel".to_string(),
+        Mode::ConfirmDelete(id) => format!("delete note {}? y/n", id),
+        Mode::ConfirmDiscard(_) => "throw away the changes? y/n".to_string(),
+    };
+    let status = match (&app.status, &app.mode) {
+        (_, Mode::ConfirmDelete(_) | Mode::ConfirmDiscard(_)) => {
+            Span::styled(hints, Style::default().add_modifier(Modifier::BOLD))
+        }
+        (Some(status), _) => Span::raw(status.clone()),
+        (None, _) => Span::styled(hints, Style::default().add_modifier(Modifier::DIM)),
+    };
+    f.render_widget(Paragraph::new(Spans::from(status)), rows[2]);
+}
+
+fn draw_preview<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let Some(note) = app.selected_note() else {
+        let empty = match app.search.value().is_empty() {
+            true => "no notes yet, n writes one",
+            false => "nothing found",
+        };
+        let preview = Paragraph::new(empty).block(Block::default().borders(Borders::ALL));
+        f.render_widget(preview, area);
+        return;
+    };
+
+    let mut lines = vec![];
+    if let Some(title) = &note.title {
+        lines.push(Spans::from(Span::styled(
+            title.clone(),
+            Style::default().add_modifier(Modifier::BOLD),
+        )));
+    }
+    let mut details = vec![format!("written {}", note.created_at)];
+    if note.updated_at != note.created_at {
+        details.push(format!("changed {}", note.updated_at));
+    }
+    if let Some(notebook) = &note.notebook {
+        details.push(format!("in {}", notebook));
+    }
+    if note.pinned {
+        details.push("pinned".to_string());
+    }
+    if let Some(due) = &note.due {
+        details.push(format!("due {}", due));
+    }
+    if let Some(done) = &note.done_at {
+        details.push(format!("done {}", done));
+    }
+    if note.locked {
+        details.push("locked".to_string());
+    }
+    lines.push(Spans::from(Span::styled(
+        details.join(" · "),
+        Style::default().add_modifier(Modifier::DIM),
+    )));
+    lines.push(Spans::default());
+    lines.extend(note.body.lines().map(|line| Spans::from(line.to_string())));
+
+    let preview = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .title(format!("Note {}", note.id))
+                .borders(Borders::ALL),
+        )
+        .wrap(Wrap { trim: false })
+        .scroll((app.scroll, 0));
+    f.render_widget(preview, area);
+}
+
+// Not wrapped, so the cursor stays where the text is
+fn draw_editor<B: Backend>(f: &mut Frame<B>, editing: &Editing, area: Rect) {
+    let (row, col) = editing.text.cursor();
+    let height = area.height.saturating_sub(2) as usize;
+    let width = area.width.saturating_sub(2) as usize;
+    let top = (row + 1).saturating_sub(height);
+    let left = (col + 1).saturating_sub(width);
+
+    let title = match editing.id {
+        Some(id) => format!("Editing note {}", id),
+        None => "New note".to_string(),
+    };
+    let lines: Vec<Spans> = editing
+        .text
+        .lines()
+        .iter()
+        .map(|line| Spans::from(line.clone()))
+        .collect();
+    let editor = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .title(title)
+                .borders(Borders::ALL)
+                .border_style(highlighted(true)),
+        )
+        .scroll((top as u16, left as u16));
+    f.render_widget(editor, area);
+    f.set_cursor(
+        area.x + 1 + (col - left) as u16,
+        area.y + 1 + (row - top) as u16,
+    );
+}
+
+fn highlighted(active: bool) -> Style {
+    match active {
+        true => Style::default().add_modifier(Modifier::BOLD),
+        false => Style::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db() -> NoteStore {
+        let store = NoteStore::in_memory();
+        store.create(None, "milk and bread").unwrap();
+        store
+            .create(Some("Plumber"), "call about the sink")
+            .unwrap();
+        store
+    }
+
+    fn press(app: &mut App, keys: &str, store: &NoteStore) {
+        for c in keys.chars() {
+            let key = match c {
+                '\n' => KeyCode::Enter,
+                '\x1b' => KeyCode::Esc,
+                c => KeyCode::Char(c),
+            };
+            app.handle(KeyEvent::new(key, KeyModifiers::NONE), store)
+                .unwrap();
+        }
+    }
+
+    fn save(app: &mut App, store: &NoteStore) {
+        let key = KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL);
+        app.handle(key, store).unwrap();
+    }
+
+    fn shown(app: &App) -> Vec<usize> {
+        app.shown.iter().map(|index| app.notes[*index].id).collect()
+    }
+
+    #[test]
+    fn searching_narrows_the_list_while_typing() {
+        let store = db();
+        let mut app = App::new(&store).unwrap();
+        press(&mut app, "/sin", &store);
+        assert_eq!(shown(&app), vec![2]);
+        assert_eq!(app.selected_note().map(|note| note.id), Some(2));
+
+        press(&mut app, "\x1b", &store);
+        assert_eq!(shown(&app), vec![1, 2]);
+    }
+
+    #[test]
+    fn deleting_asks_first() {
+        let store = db();
+        let mut app = App::new(&store).unwrap();
+        press(&mut app, "dn", &store);
+        assert_eq!(shown(&app), vec![1, 2]);
+        press(&mut app, "dy", &store);
+        assert_eq!(shown(&app), vec![2]);
+        assert!(app.status.as_deref().unwrap().contains("deleted note 1"));
+    }
+
+    #[test]
+    fn edits_are_saved_or_thrown_away() {
+        let store = db();
+        let mut app = App::new(&store).unwrap();
+        press(&mut app, "j\n!", &store);
+        save(&mut app, &store);
+        assert_eq!(app.selected_note().unwrap().body, "call about the sink!");
+        assert!(matches!(app.mode, Mode::Browse));
+
+        // Esc with changes asks, no keeps editing
+        press(&mut app, "\n?\x1bn", &store);
+        assert!(matches!(app.mode, Mode::Edit(_)));
+        press(&mut app, "\x1by", &store);
+        assert!(matches!(app.mode, Mode::Browse));
+        assert_eq!(app.selected_note().unwrap().body, "call about the sink!");
+    }
+
+    #[test]
+    fn new_notes_are_written_in_place() {
+        let store = db();
+        let mut app = App::new(&store).unwrap();
+        press(&mut app, "n", &store);
+        save(&mut app, &store);
+        assert_eq!(app.status.as_deref(), Some("empty notes aren't saved"));
+
+        press(&mut app, "hi\n#there", &store);
+        save(&mut app, &store);
+        assert_eq!(shown(&app), vec![1, 2, 3]);
+        assert_eq!(app.selected_note().unwrap().body, "hi\n#there");
+        assert_eq!(app.selected_note().unwrap().tags, vec!["there"]);
+    }
+}
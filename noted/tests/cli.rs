@@ -0,0 +1,106 @@
+mod common;
+
+use common::{home, noted, stderr, stdout};
+use serde_json::{json, Value};
+use std::fs;
+use std::process::Output;
+
+fn parse(output: &Output) -> Value {
+    assert!(output.status.success(), "{:?}", output);
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn exit_codes_tell_what_went_wrong() {
+    let home = home("exit-codes");
+    noted(&home, &[], &["add", "milk"]);
+    assert_eq!(noted(&home, &[], &["show", "1"]).status.code(), Some(0));
+
+    // 1: there is no such note or nothing was found
+    for args in [&["show", "9"][..], &["rm", "9"], &["search", "bread"]] {
+        assert_eq!(noted(&home, &[], args).status.code(), Some(1), "{:?}", args);
+    }
+
+    // 2: wrong usage, with the usage after the message
+    for args in [
+        &["bogus"][..],
+        &["edit"],
+        &["rm", "one"],
+        &["search", "\"milk"],
+    ] {
+        let output = noted(&home, &[], args);
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        assert!(stderr(&output).contains("usage: noted"), "{:?}", args);
+    }
+
+    // 3: anything else, here a file that isn't a database
+    let broken = home.join("broken.db");
+    fs::write(&broken, "not a database, really not a database").unwrap();
+    let output = noted(&home, &[], &["--db", broken.to_str().unwrap(), "list"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(!stderr(&output).contains("usage: noted"));
+}
+
+#[test]
+fn json_output_has_a_stable_shape() {
+    let home = home("json-shape");
+    assert_eq!(
+        parse(&noted(
+            &home,
+            &[],
+            &["--json", "add", "--title", "Shop", "buy milk #home"]
+        )),
+        json!({ "id": 1 })
+    );
+    noted(&home, &[], &["add", "call the plumber"]);
+
+    let list = parse(&noted(&home, &[], &["--json", "list"]));
+    let notes = list.as_array().unwrap();
+    assert_eq!(notes.len(), 2);
+    let keys: Vec<&str> = notes[0]
+        .as_object()
+        .unwrap()
+        .keys()
+        .map(String::as_str)
+        .collect();
+    assert_eq!(
+        keys,
+        [
+            "body",
+            "created_at",
+            "done_at",
+            "due",
+            "id",
+            "locked",
+            "notebook",
+            "pinned",
+            "priority",
+            "tags",
+            "title",
+            "todo",
+            "updated_at"
+        ]
+    );
+    assert_eq!(notes[0]["id"], 1);
+    assert_eq!(notes[0]["title"], "Shop");
+    assert_eq!(notes[0]["tags"], json!(["home"]));
+    assert_eq!(notes[1]["title"], Value::Null);
+
+    assert_eq!(
+        parse(&noted(&home, &[], &["--json", "edit", "1", "buy bread"])),
+        json!({ "id": 1, "body": "buy bread" })
+    );
+    assert_eq!(
+        parse(&noted(&home, &[], &["--json", "search", "bread"])),
+        json!([{ "id": 1, "snippet": "buy [bread]" }])
+    );
+    assert_eq!(
+        parse(&noted(&home, &[], &["--json", "rm", "2"])),
+        json!({ "id": 2 })
+    );
+
+    // Nothing found is still JSON, only the exit code says so
+    let none = noted(&home, &[], &["--json", "search", "plumber"]);
+    assert_eq!(none.status.code(), Some(1));
+    assert_eq!(stdout(&none), "[]\n");
+}
@@ -0,0 +1,54 @@
+// Helpers shared by the integration tests, not every test file uses all of them
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+// A home of its own with a fresh database for every test
+pub fn home(name: &str) -> PathBuf {
+    let home = std::env::temp_dir().join(format!("noted-test-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&home);
+    fs::create_dir_all(home.join("run")).unwrap();
+    home
+}
+
+// Nothing from the environment of the test run leaks in, keys are cached in the runtime
+// directory inside the home and temporary files are written there too. `env` comes on top.
+pub fn noted(home: &Path, env: &[(&str, &str)], args: &[&str]) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_noted"));
+    command
+        .args(args)
+        .env("HOME", home)
+        .env("XDG_RUNTIME_DIR", home.join("run"))
+        .env("TMPDIR", home)
+        .env_remove("NOTED_DB")
+        .env_remove("XDG_DATA_HOME")
+        .env_remove("XDG_CONFIG_HOME")
+        .env_remove("NOTED_PASSPHRASE")
+        .env_remove("VISUAL")
+        .env_remove("EDITOR");
+    for (key, value) in env {
+        command.env(key, value);
+    }
+    command.output().unwrap()
+}
+
+// The stub editor runs as `sh script file`
+pub fn with_editor(home: &Path, script: &str, args: &[&str]) -> Output {
+    let editor = home.join("editor.sh");
+    fs::write(&editor, script).unwrap();
+    noted(
+        home,
+        &[("EDITOR", &format!("sh {}", editor.display()))],
+        args,
+    )
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use common::{home, noted, stderr, stdout};
+use std::fs;
+
+#[test]
+fn notes_go_to_the_data_directory_by_default() {
+    let home = home("default");
+    noted(&home, &[], &["add", "hello"]);
+    assert!(home.join(".local/share/noted/notes.db").is_file());
+    assert!(!home.join("notes.db").exists());
+}
+
+#[test]
+fn db_flag_beats_noted_db_beats_the_default() {
+    let home = home("flag");
+    let from_env = home.join("env.db");
+    let from_flag = home.join("flag").join("flag.db");
+    let env = [("NOTED_DB", from_env.to_str().unwrap())];
+
+    noted(&home, &env, &["add", "in env"]);
+    noted(
+        &home,
+        &env,
+        &["--db", from_flag.to_str().unwrap(), "add", "in flag"],
+    );
+
+    assert!(stdout(&noted(&home, &env, &["list"])).contains("in env"));
+    let flag = stdout(&noted(
+        &home,
+        &env,
+        &["--db", from_flag.to_str().unwrap(), "list"],
+    ));
+    assert!(flag.contains("in flag") && !flag.contains("in env"));
+    assert_eq!(stdout(&noted(&home, &[], &["list"])), "");
+}
+
+#[test]
+fn profiles_come_from_the_config_file() {
+    let home = home("profiles");
+    fs::create_dir_all(home.join(".config/noted")).unwrap();
+    fs::write(
+        home.join(".config/noted/config.toml"),
+        "default = \"personal\"\n\n[profiles]\nwork = \"~/work.db\"\n",
+    )
+    .unwrap();
+
+    noted(&home, &[], &["--profile", "work", "add", "standup"]);
+    noted(&home, &[], &["add", "groceries"]);
+    assert!(home.join("work.db").is_file());
+    assert!(home.join(".local/share/noted/personal.db").is_file());
+
+    let work = stdout(&noted(&home, &[], &["-p", "work", "list"]));
+    assert!(work.contains("standup") && !work.contains("groceries"));
+    assert!(stdout(&noted(&home, &[], &["list"])).contains("groceries"));
+}
+
+#[test]
+fn legacy_database_moves_once() {
+    let home = home("legacy");
+    noted(
+        &home,
+        &[],
+        &[
+            "--db",
+            home.join("notes.db").to_str().unwrap(),
+            "add",
+            "old",
+        ],
+    );
+
+    let moved = noted(&home, &[], &["list"]);
+    assert!(stdout(&moved).contains("old"));
+    assert!(stderr(&moved).contains("moved"));
+    assert!(!home.join("notes.db").exists());
+
+    let again = noted(&home, &[], &["list"]);
+    assert!(stdout(&again).contains("old"));
+    assert!(again.stderr.is_empty());
+}
+
+#[test]
+fn json_is_only_a_flag_before_the_command() {
+    let home = home("json");
+    let added = noted(&home, &[], &["-j", "add", "fix the -j flag", "--json"]);
+    assert_eq!(stdout(&added), "{\"id\":1}\n");
+    assert_eq!(
+        stdout(&noted(&home, &[], &["show", "1"])),
+        "fix the -j flag --json\n"
+    );
+}
+
+#[test]
+fn options_after_the_command_are_text() {
+    let home = home("text");
+    noted(&home, &[], &["add", "buy", "-p", "5", "apples"]);
+    assert_eq!(
+        stdout(&noted(&home, &[], &["show", "1"])),
+        "buy -p 5 apples\n"
+    );
+    assert!(!home.join(".local/share/noted/5.db").exists());
+}
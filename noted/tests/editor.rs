@@ -0,0 +1,65 @@
+mod common;
+
+use common::{home, stderr, stdout, with_editor};
+
+#[test]
+fn new_saves_what_was_written_in_the_editor() {
+    let home = home("new");
+    let written = with_editor(
+        &home,
+        "printf 'shopping\\nmilk\\nbread\\n' > \"$1\"",
+        &["new"],
+    );
+    assert!(written.status.success());
+    assert_eq!(stdout(&written), "1\n");
+
+    let shown = with_editor(&home, "exit 1", &["show", "1"]);
+    assert_eq!(stdout(&shown), "shopping\nmilk\nbread\n");
+
+    let listed = with_editor(&home, "exit 1", &["list"]);
+    assert!(stdout(&listed).starts_with("1: shopping (+2 lines)"));
+}
+
+#[test]
+fn edit_opens_the_note_and_detects_no_op_edits() {
+    let home = home("edit");
+    with_editor(&home, "", &["add", "first", "draft"]);
+
+    let unchanged = with_editor(&home, "true", &["edit", "1"]);
+    assert!(unchanged.status.success());
+    assert!(stderr(&unchanged).contains("nothing changed"));
+
+    // Only changes the note if the editor saw the old text
+    let script = "grep -q '^first draft$' \"$1\" && printf 'second\\ndraft\\n' > \"$1\"";
+    assert!(with_editor(&home, script, &["edit", "1"]).status.success());
+    assert_eq!(
+        stdout(&with_editor(&home, "", &["show", "1"])),
+        "second\ndraft\n"
+    );
+}
+
+#[test]
+fn empty_or_failed_editing_saves_nothing() {
+    let home = home("empty");
+    assert_eq!(with_editor(&home, "true", &["new"]).status.code(), Some(1));
+    assert_ne!(
+        with_editor(&home, "echo text > \"$1\"; exit 3", &["new"])
+            .status
+            .code(),
+        Some(0)
+    );
+    assert_eq!(stdout(&with_editor(&home, "", &["list"])), "");
+}
+
+#[test]
+fn emptying_a_note_in_the_editor_keeps_it() {
+    let home = home("emptied");
+    with_editor(&home, "", &["add", "keep", "me"]);
+    assert_eq!(
+        with_editor(&home, ": > \"$1\"", &["edit", "1"])
+            .status
+            .code(),
+        Some(1)
+    );
+    assert_eq!(stdout(&with_editor(&home, "", &["show", "1"])), "keep me\n");
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use common::{home, noted, stderr, stdout, with_editor};
+use std::fs;
+use std::path::Path;
+
+const PASSPHRASE: &[(&str, &str)] = &[("NOTED_PASSPHRASE", "correct horse")];
+
+fn database(home: &Path) -> Vec<u8> {
+    fs::read(home.join(".local/share/noted/notes.db")).unwrap()
+}
+
+fn contains(haystack: &[u8], needle: &str) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle.as_bytes())
+}
+
+#[test]
+fn locked_notes_are_unreadable_without_the_passphrase() {
+    let home = home("unreadable");
+    noted(
+        &home,
+        &[],
+        &["add", "--title", "Bank", "pin is 7391 #money"],
+    );
+    noted(&home, &[], &["edit", "1", "pin is 7391 or 8402 #money"]);
+    assert!(contains(&database(&home), "7391"));
+
+    assert!(noted(&home, PASSPHRASE, &["lock", "1"]).status.success());
+    let db = database(&home);
+    assert!(!contains(&db, "7391") && !contains(&db, "8402"));
+    assert!(!contains(&db, "correct horse"));
+
+    noted(&home, &[], &["lock"]);
+    let list = stdout(&noted(&home, &[], &["list"]));
+    assert!(list.contains("Bank - (locked)"));
+    assert_eq!(
+        noted(&home, &[], &["search", "7391"]).status.code(),
+        Some(1)
+    );
+
+    let wrong = noted(
+        &home,
+        &[("NOTED_PASSPHRASE", "battery staple")],
+        &["show", "1"],
+    );
+    assert_eq!(wrong.status.code(), Some(3));
+    assert!(stderr(&wrong).contains("wrong passphrase"));
+    assert!(!stdout(&wrong).contains("7391"));
+    // Backups keep it sealed, the other formats leave it out
+    let backup = noted(&home, &[], &["export", "--format", "json", "-"]);
+    assert!(backup.status.success());
+    assert!(stdout(&backup).contains("\"locked\":true") && !stdout(&backup).contains("7391"));
+    let csv = noted(&home, &[], &["export", "--format", "csv", "-"]);
+    assert!(csv.status.success() && !stdout(&csv).contains("Bank"));
+    assert!(stderr(&csv).contains("left out 1 locked notes"));
+
+    let shown = noted(&home, PASSPHRASE, &["show", "1"]);
+    assert_eq!(stdout(&shown), "pin is 7391 or 8402 #money\n");
+}
+
+#[test]
+fn the_key_is_kept_for_the_session_until_lock() {
+    let home = home("session");
+    noted(&home, &[], &["add", "door code 2468"]);
+    noted(&home, PASSPHRASE, &["lock", "1"]);
+
+    // Cached from the lock, no passphrase needed
+    assert_eq!(
+        stdout(&noted(&home, &[], &["show", "1"])),
+        "door code 2468\n"
+    );
+    noted(&home, &[], &["edit", "1", "door code 1357"]);
+    assert!(!contains(&database(&home), "1357"));
+
+    // Without the cached key it's asked for again, a wrong one doesn't do
+    noted(&home, &[], &["lock"]);
+    assert!(
+        !noted(&home, &[("NOTED_PASSPHRASE", "wrong")], &["show", "1"])
+            .status
+            .success()
+    );
+
+    noted(&home, PASSPHRASE, &["unlock", "1"]);
+    noted(&home, &[], &["lock"]);
+    assert_eq!(
+        stdout(&noted(&home, &[], &["show", "1"])),
+        "door code 1357\n"
+    );
+    assert!(contains(&database(&home), "1357"));
+    let history = stdout(&noted(&home, &[], &["history", "1"]));
+    assert!(history.contains("-door code 2468") && history.contains("+door code 1357"));
+}
+
+#[test]
+fn locked_text_never_goes_to_the_editor() {
+    let home = home("editor");
+    noted(&home, &[], &["add", "safe combination 9753"]);
+    noted(&home, PASSPHRASE, &["lock", "1"]);
+
+    // The stand-in editor would leave a copy of whatever it was given
+    let output = with_editor(
+        &home,
+        "cp \"$1\" \"$HOME/copy\"; echo changed > \"$1\"",
+        &["edit", "1"],
+    );
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr(&output).contains("is locked"));
+    assert!(!home.join("copy").exists());
+
+    // Nothing with the text in it is left around for others to read, temp files included
+    let mut dirs = vec![home.clone()];
+    while let Some(dir) = dirs.pop() {
+        for file in fs::read_dir(dir).unwrap() {
+            let path = file.unwrap().path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else {
+                assert!(!contains(&fs::read(&path).unwrap(), "9753"), "{:?}", path);
+            }
+        }
+    }
+    assert_eq!(
+        stdout(&noted(&home, &[], &["show", "1"])),
+        "safe combination 9753\n"
+    );
+}
@@ -0,0 +1,132 @@
+mod common;
+
+use common::{home, stderr, stdout};
+use std::fs;
+use std::path::Path;
+use std::process::Output;
+
+// Two machines are two databases next to the shared directory
+fn noted(home: &Path, machine: &str, args: &[&str]) -> Output {
+    let db = home.join(format!("{}.db", machine));
+    common::noted(home, &[("NOTED_DB", db.to_str().unwrap())], args)
+}
+
+fn sync(home: &Path, machine: &str) -> serde_json::Value {
+    let shared = home.join("shared");
+    fs::create_dir_all(&shared).unwrap();
+    let output = noted(home, machine, &["--json", "sync", shared.to_str().unwrap()]);
+    assert!(output.status.success(), "{:?}", output);
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn laptop_and_desktop_end_up_with_the_same_notes() {
+    let home = home("same");
+    noted(
+        &home,
+        "laptop",
+        &["add", "--title", "Shopping", "milk #home"],
+    );
+    noted(&home, "desktop", &["add", "call the plumber"]);
+
+    assert_eq!(sync(&home, "laptop")["sent"], 1);
+    assert_eq!(sync(&home, "desktop")["received"], 1);
+    assert_eq!(sync(&home, "laptop")["received"], 1);
+
+    // Ids are only the same by chance, titles and texts are what counts
+    let laptop = stdout(&noted(&home, "laptop", &["search", "plumber"]));
+    assert!(laptop.contains("plumber"));
+    let desktop = stdout(&noted(&home, "desktop", &["list", "--tag", "home"]));
+    assert!(desktop.contains("Shopping - milk #home"));
+}
+
+#[test]
+fn conflicts_wait_for_a_decision() {
+    let home = home("conflict");
+    noted(&home, "laptop", &["add", "milk"]);
+    sync(&home, "laptop");
+    sync(&home, "desktop");
+
+    noted(&home, "laptop", &["edit", "1", "oat milk"]);
+    noted(&home, "desktop", &["edit", "1", "milk and bread"]);
+    sync(&home, "laptop");
+    assert_eq!(sync(&home, "desktop")["conflicts"], 1);
+    assert_eq!(sync(&home, "laptop")["conflicts"], 1);
+
+    let conflicts = stdout(&noted(&home, "desktop", &["conflicts"]));
+    assert!(conflicts.starts_with("1: entry 1 was changed on another device too"));
+    assert!(conflicts.contains("-") && conflicts.contains("+"));
+    let kept = stdout(&noted(&home, "desktop", &["show", "1"]));
+    assert_eq!(kept, stdout(&noted(&home, "laptop", &["show", "1"])));
+
+    assert!(noted(&home, "desktop", &["resolve", "1", "keep"])
+        .status
+        .success());
+    assert_eq!(
+        noted(&home, "desktop", &["resolve", "1", "keep"])
+            .status
+            .code(),
+        Some(1)
+    );
+    sync(&home, "desktop");
+    sync(&home, "laptop");
+    assert_eq!(stdout(&noted(&home, "laptop", &["conflicts"])), "");
+    assert_eq!(stdout(&noted(&home, "laptop", &["show", "1"])), kept);
+}
+
+#[test]
+fn a_missing_directory_is_an_error() {
+    let home = home("missing");
+    let output = noted(&home, "laptop", &["sync", "/nonexistent/noted"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr(&output).contains("is it mounted?"));
+}
+
+#[test]
+fn id_links_point_at_the_same_note_everywhere() {
+    let home = home("links");
+    noted(&home, "laptop", &["add", "milk"]);
+    noted(&home, "laptop", &["add", "see [[1]]"]);
+    noted(&home, "desktop", &["add", "call the plumber"]);
+    sync(&home, "laptop");
+    sync(&home, "desktop");
+
+    // Here the plumber is 1, so the milk came in as 2
+    assert_eq!(
+        stdout(&noted(&home, "desktop", &["show", "3"])),
+        "see [[2]]\n"
+    );
+    let links = stdout(&noted(&home, "desktop", &["links", "3"]));
+    assert!(
+        links.contains("milk") && !links.contains("plumber"),
+        "{}",
+        links
+    );
+
+    noted(&home, "desktop", &["edit", "3", "see [[2]] and [[1]]"]);
+    sync(&home, "desktop");
+    sync(&home, "laptop");
+    assert_eq!(
+        stdout(&noted(&home, "laptop", &["show", "2"])),
+        "see [[1]] and [[3]]\n"
+    );
+    assert!(stdout(&noted(&home, "laptop", &["show", "3"])).contains("plumber"));
+}
+
+#[test]
+fn links_to_notes_that_arrive_later_still_lead_there() {
+    let home = home("later");
+    noted(&home, "laptop", &["add", "first, see [[2]]"]);
+    noted(&home, "laptop", &["add", "second, see [[1]]"]);
+    noted(&home, "desktop", &["add", "something else"]);
+    sync(&home, "laptop");
+    sync(&home, "desktop");
+
+    // One of the two came first and still has the UUID of the other in it
+    for (id, other) in [("2", 3), ("3", 2)] {
+        let links = stdout(&noted(&home, "desktop", &["--json", "links", id]));
+        let links: serde_json::Value = serde_json::from_str(&links).unwrap();
+        assert_eq!(links["links"][0]["id"], other);
+        assert_eq!(links["backlinks"][0], other);
+    }
+}